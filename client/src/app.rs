@@ -3,7 +3,7 @@ use std::{collections::HashMap, f32::consts::PI, ffi::CString, rc::Rc, time::Ins
 use glam::Vec4Swizzles;
 
 use crate::{
-    egui::{ui, EGui},
+    egui::{ui, EGui, Tool},
     game_object::{GameObject, TransformComponent},
     graphics::{
         systems::{PointLightSystem, SimpleRenderSystem},
@@ -18,6 +18,19 @@ use crate::{
     Input, KeyboardMovementController, ModelAsset, TextureAsset,
 };
 
+/// How many chunks around the camera are requested in each direction.
+const VIEW_DISTANCE: i32 = 4;
+/// Chunks further than this from the camera are forgotten.
+const UNLOAD_DISTANCE: i32 = VIEW_DISTANCE + 2;
+/// Seconds before a chunk that never arrived is requested again.
+const CHUNK_REQUEST_TIMEOUT: f32 = 2.0;
+/// How far apart heights are drawn.
+const HEIGHT_STEP: f32 = 0.25;
+/// How far the mouse ray moves between checks for a tile under it.
+const PICK_STEP: f32 = 0.05;
+/// How far away tiles can be clicked.
+const PICK_DISTANCE: f32 = 200.0;
+
 pub struct App {
     window: Window,
     device: Rc<Device>,
@@ -46,17 +59,21 @@ pub struct App {
     camera_controller: KeyboardMovementController,
     viewer_object: GameObject,
 
-    game_objects: HashMap<u8, GameObject>,
+    game_objects: HashMap<u32, GameObject>,
 
-    select_id: u8,
+    select_id: u32,
 
     network: Network,
 
     players: Vec<Option<common::Player>>,
 
     world: Option<common::world::World>,
+    /// What the numeric tile ids of the server mean, sent when joining.
+    tiles: common::world::TileRegistry,
 
-    chunk_position_to_ids: HashMap<common::Position, u8>,
+    chunk_position_to_ids: HashMap<common::world::ChunkPos, u32>,
+    /// Chunks asked for but not received yet, with when they were asked for.
+    requested_chunks: HashMap<common::world::ChunkPos, Instant>,
 }
 
 impl App {
@@ -150,7 +167,9 @@ impl App {
         for path in TextureAsset::iter() {
             let asset = TextureAsset::get(&path).unwrap();
 
-            tile_atlas.add_texture(asset)?;
+            let name = path.trim_end_matches(".png");
+
+            tile_atlas.add_texture(name, asset)?;
         }
 
         let tile_atlas_image = tile_atlas.build(device.clone())?;
@@ -218,8 +237,10 @@ impl App {
             players: Vec::new(),
 
             world: None,
+            tiles: common::world::TileRegistry::default(),
 
             chunk_position_to_ids: HashMap::new(),
+            requested_chunks: HashMap::new(),
         })
     }
 
@@ -319,31 +340,60 @@ impl App {
                             .unwrap()
                             .0;
                 }
-                common::ServerPacket::Chat => {
+                common::ServerPacket::Chat | common::ServerPacket::SystemMessage => {
                     let message = std::str::from_utf8(&payload)
                         .unwrap()
                         .trim_matches(char::from(0))
                         .to_string();
 
+                    let message = match server_message {
+                        common::ServerPacket::SystemMessage => ui::ChatMessage::System(message),
+                        _ => ui::ChatMessage::Player(message),
+                    };
+
                     if let Some(chat) = self.egui.get_mut::<ui::Chat>("Chat") {
                         chat.messages.push(message);
                     }
                 }
-                common::ServerPacket::ChunkModified => {
-                    let chunk: common::world::Chunk =
+                common::ServerPacket::Disconnect => {
+                    let reason = String::from_utf8_lossy(&payload)
+                        .trim_matches(char::from(0))
+                        .to_string();
+
+                    log::info!("disconnected by the server: {}", reason);
+
+                    self.network.reset();
+                    self.egui.disconnected(reason);
+                }
+                common::ServerPacket::ClaimsModified => {
+                    let claims: Vec<common::world::Claim> =
                         bincode::serde::decode_from_slice(&payload, bincode::config::standard())
                             .unwrap()
                             .0;
 
-                    // should always be some
                     if let Some(world) = &mut self.world {
-                        world
-                            .chunks
-                            .get_mut((chunk.position.x, chunk.position.y))
+                        world.claims = claims;
+                    }
+                }
+                common::ServerPacket::WorldChanged => {
+                    let world: common::world::World =
+                        bincode::decode_from_slice(&payload, bincode::config::standard())
                             .unwrap()
-                            .tiles = chunk.tiles.clone(); // hmmmm
+                            .0;
+
+                    log::info!("moved to another world");
 
-                        log::info!("recreating chunk game object");
+                    self.set_world(world)?;
+                }
+                common::ServerPacket::ChunkModified => {
+                    let chunk: common::world::Chunk =
+                        bincode::serde::decode_from_slice(&payload, bincode::config::standard())
+                            .unwrap()
+                            .0;
+
+                    // should always be some
+                    if self.world.is_some() {
+                        self.requested_chunks.remove(&chunk.position);
 
                         if let Some(id) = self.chunk_position_to_ids.remove(&chunk.position) {
                             self.game_objects.remove(&id);
@@ -352,12 +402,76 @@ impl App {
                         let id = self.create_chunk_game_object(&chunk)?;
 
                         self.chunk_position_to_ids.insert(chunk.position, id);
+
+                        if let Some(world) = &mut self.world {
+                            world.chunks.insert(chunk.position, chunk);
+                        }
                     }
                 }
                 _ => {}
             }
         }
 
+        self.stream_chunks()?;
+
+        Ok(())
+    }
+
+    /// Asks for the missing chunks around the camera and forgets the ones far behind it.
+    fn stream_chunks(&mut self) -> anyhow::Result<(), AppError> {
+        let world = match &mut self.world {
+            Some(world) => world,
+            None => return Ok(()),
+        };
+
+        let translation = self.viewer_object.transform.translation;
+
+        let center = match common::world::TilePos::from_f32(translation.x, translation.z) {
+            Some(tile) => tile.chunk(),
+            None => return Ok(()),
+        };
+
+        let mut missing = Vec::new();
+
+        for y in center.y - VIEW_DISTANCE..=center.y + VIEW_DISTANCE {
+            for x in center.x - VIEW_DISTANCE..=center.x + VIEW_DISTANCE {
+                let position = common::world::ChunkPos::new(x, y);
+
+                let pending = self
+                    .requested_chunks
+                    .get(&position)
+                    .is_some_and(|time| time.elapsed().as_secs_f32() < CHUNK_REQUEST_TIMEOUT);
+
+                if !pending && !world.chunks.contains_key(&position) {
+                    self.requested_chunks.insert(position, Instant::now());
+
+                    missing.push(position);
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            self.network.request_chunks(&missing)?;
+        }
+
+        let far = world
+            .chunks
+            .keys()
+            .filter(|position| {
+                (position.x - center.x).abs() > UNLOAD_DISTANCE
+                    || (position.y - center.y).abs() > UNLOAD_DISTANCE
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        for position in far {
+            world.chunks.remove(&position);
+
+            if let Some(id) = self.chunk_position_to_ids.remove(&position) {
+                self.game_objects.remove(&id);
+            }
+        }
+
         Ok(())
     }
 
@@ -369,7 +483,10 @@ impl App {
         if let Some(world) = &self.world {
             if self.egui_hovered
                 || state != winit::event::ElementState::Pressed
-                || button != winit::event::MouseButton::Left
+                || !matches!(
+                    button,
+                    winit::event::MouseButton::Left | winit::event::MouseButton::Right
+                )
             {
                 return Ok(());
             }
@@ -383,33 +500,56 @@ impl App {
                     ),
                     self.camera.as_ref().unwrap(),
                 ) {
-                    let plane = crate::graphics::Plane {
-                        center: glam::Vec3::ZERO,
-                        normal: glam::Vec3::Y,
-                    };
-
-                    if let Some(distance) = plane.intersect(&ray) {
-                        let point = ray.origin + ray.dir * distance;
+                    // the top of a tile of a chunk the server already sent, other tiles
+                    // can't be edited
+                    let surface = |point: glam::Vec3| {
+                        let tile = common::world::TilePos::from_f32(point.x, point.z)?;
+                        let height = world.height(tile)?;
 
-                        let position = (glam::vec2((point.x - 0.5) / 10.0, (point.z + 0.5) / 10.0)
-                            * 10.0)
-                            .round();
+                        let layer = world
+                            .top(tile)
+                            .map_or(common::world::Layer::Ground, |(layer, _)| layer);
 
-                        let p = position - glam::Vec2::Y;
+                        Some((tile, layer, surface_height(height, layer)))
+                    };
 
-                        if p.x >= 0.0
-                            && p.y >= 0.0
-                            && p.x < (world.width * common::world::CHUNK_SIZE) as f32
-                            && p.y < (world.height * common::world::CHUNK_SIZE) as f32
-                        {
-                            self.network.send_client_world_click(p.abs())?;
+                    // the first tile whose top the ray goes below, up is negative y
+                    let clicked = ray
+                        .march(PICK_STEP, PICK_DISTANCE, |point| {
+                            surface(point).is_some_and(|(_, _, y)| point.y >= y)
+                        })
+                        .and_then(surface);
+
+                    // right clicking opens the entity of a tile, if it has one
+                    if button == winit::event::MouseButton::Right {
+                        let entity =
+                            clicked.and_then(|(tile, _, _)| Some((tile, world.entity(tile)?)));
+
+                        if let Some((tile, entity)) = entity {
+                            if let Some(editor) =
+                                self.egui.get_mut::<ui::TileEntityEditor>("Tile entity")
+                            {
+                                editor.edit(tile, entity.clone());
+                            }
 
-                            self.game_objects
-                                .get_mut(&self.select_id)
-                                .unwrap()
-                                .transform
-                                .translation = glam::vec3(position.x + 0.5, 0.0, position.y - 0.5);
+                            self.egui.set_open("Tile entity", true);
+                        }
+                    } else if let Some((tile, layer, y)) = clicked {
+                        match self.egui.tool {
+                            Tool::Place => self.network.send_client_world_click(tile, layer)?,
+                            Tool::Raise => self
+                                .network
+                                .send_height_change(tile, common::world::HeightChange::Raise)?,
+                            Tool::Lower => self
+                                .network
+                                .send_height_change(tile, common::world::HeightChange::Lower)?,
                         }
+
+                        self.game_objects
+                            .get_mut(&self.select_id)
+                            .unwrap()
+                            .transform
+                            .translation = glam::vec3(tile.x as f32 + 0.5, y, tile.y as f32 + 0.5);
                     }
                 }
             }
@@ -484,14 +624,14 @@ impl App {
                         &mut self.network,
                         &self.players,
                         &self.world,
+                        self.camera.as_ref(),
                     )?;
 
                     self.egui_hovered = r.0;
 
-                    if let Some(world) = r.1 {
-                        self.create_chunk_game_objects(&world)?;
-
-                        self.world = Some(world);
+                    if let Some(join_info) = r.1 {
+                        self.tiles = join_info.tiles;
+                        self.set_world(join_info.world)?;
                     }
 
                     self.renderer.end_frame(&self.window)?;
@@ -513,87 +653,116 @@ impl App {
         Ok(())
     }
 
-    fn create_chunk_game_objects(
-        &mut self,
-        world: &common::world::World,
-    ) -> anyhow::Result<(), AppError> {
-        for x in 0..world.width {
-            for y in 0..world.height {
-                let chunk = world.chunks.get((x, y)).unwrap();
+    /// Replaces the world, its chunks are requested again as the camera needs them.
+    fn set_world(&mut self, world: common::world::World) -> anyhow::Result<(), AppError> {
+        for (_, id) in self.chunk_position_to_ids.drain() {
+            self.game_objects.remove(&id);
+        }
 
-                let id = self.create_chunk_game_object(chunk)?;
+        self.requested_chunks.clear();
 
-                self.chunk_position_to_ids.insert(chunk.position, id);
-            }
+        for chunk in world.chunks.values() {
+            let id = self.create_chunk_game_object(chunk)?;
+
+            self.chunk_position_to_ids.insert(chunk.position, id);
         }
 
+        self.world = Some(world);
+
         Ok(())
     }
 
     fn create_chunk_game_object(
         &mut self,
         chunk: &common::world::Chunk,
-    ) -> anyhow::Result<u8, AppError> {
+    ) -> anyhow::Result<u32, AppError> {
         let mut vertices: Vec<Vertex> = Vec::new();
 
-        for chunk_x in 0..common::world::CHUNK_SIZE {
-            for chunk_y in 0..common::world::CHUNK_SIZE {
-                let tile = chunk.tiles.get((chunk_x, chunk_y)).unwrap();
-
-                let size = (1.0 / 16.0)
-                    - (1.0 / (self.tile_atlas.size * self.tile_atlas.tile_size) as f32) * 2.0;
-
-                let offset_x = match tile.ty {
-                    common::world::TileType::Grass => 0.0,
-                    common::world::TileType::Sand => {
-                        32.0 * ((1.0 / 16.0)
-                            + (1.0 / (self.tile_atlas.size * self.tile_atlas.tile_size) as f32))
-                    }
-                };
-
-                let offset_y = 0.0;
-
-                vertices.push(Vertex {
-                    position: glam::vec3(chunk_x as f32, 0.0, chunk_y as f32),
-                    color: glam::vec3(1.0, 1.0, 1.0),
-                    normal: glam::vec3(0.0, 0.0, 0.0),
-                    uv: glam::vec2(offset_x, offset_y),
-                });
+        // two triangles between four corners, going around the quad
+        let mut quad = |corners: [glam::Vec3; 4], (offset, size): (glam::Vec2, f32), shade: f32| {
+            let uvs = [
+                offset,
+                offset + glam::vec2(size, 0.0),
+                offset + glam::vec2(size, size),
+                offset + glam::vec2(0.0, size),
+            ];
 
+            for i in [0, 1, 3, 1, 3, 2] {
                 vertices.push(Vertex {
-                    position: glam::vec3(chunk_x as f32 + 1.0, 0.0, chunk_y as f32),
-                    color: glam::vec3(1.0, 1.0, 1.0),
+                    position: corners[i],
+                    color: glam::Vec3::splat(shade),
                     normal: glam::vec3(0.0, 0.0, 0.0),
-                    uv: glam::vec2(offset_x + size, offset_y),
+                    uv: uvs[i],
                 });
+            }
+        };
 
-                vertices.push(Vertex {
-                    position: glam::vec3(chunk_x as f32, 0.0, chunk_y as f32 + 1.0),
-                    color: glam::vec3(1.0, 1.0, 1.0),
-                    normal: glam::vec3(0.0, 0.0, 0.0),
-                    uv: glam::vec2(offset_x, offset_y + size),
-                });
+        for (layer, local, tile) in common::world::Layer::ALL.into_iter().flat_map(|layer| {
+            common::world::LocalPos::all()
+                .filter_map(move |local| Some((layer, local, chunk.tile(local, layer)?)))
+        }) {
+            let height = chunk.height(local);
+            let y = surface_height(height, layer);
+
+            // textures are named after the tiles they are used for
+            let texture = self
+                .tiles
+                .get(tile.ty)
+                .map_or("", |tile| tile.texture.as_str());
+
+            let uv = self.tile_atlas.uv(texture);
+
+            let (x0, z0) = (local.x() as f32, local.y() as f32);
+            let (x1, z1) = (x0 + 1.0, z0 + 1.0);
+
+            quad(
+                [
+                    glam::vec3(x0, y, z0),
+                    glam::vec3(x1, y, z0),
+                    glam::vec3(x1, y, z1),
+                    glam::vec3(x0, y, z1),
+                ],
+                uv,
+                1.0,
+            );
 
-                vertices.push(Vertex {
-                    position: glam::vec3(chunk_x as f32 + 1.0, 0.0, chunk_y as f32),
-                    color: glam::vec3(1.0, 1.0, 1.0),
-                    normal: glam::vec3(0.0, 0.0, 0.0),
-                    uv: glam::vec2(offset_x + size, offset_y),
-                });
+            if layer != common::world::Layer::Ground {
+                continue;
+            }
 
-                vertices.push(Vertex {
-                    position: glam::vec3(chunk_x as f32, 0.0, chunk_y as f32 + 1.0),
-                    color: glam::vec3(1.0, 1.0, 1.0),
-                    normal: glam::vec3(0.0, 0.0, 0.0),
-                    uv: glam::vec2(offset_x, offset_y + size),
-                });
+            // the ground drops down to lower neighbours, tiles on the edge of the chunk
+            // drop all the way so there are no gaps whatever the next chunk looks like
+            let edges = [
+                (0, -1, [(x0, z0), (x1, z0)]),
+                (1, 0, [(x1, z0), (x1, z1)]),
+                (0, 1, [(x1, z1), (x0, z1)]),
+                (-1, 0, [(x0, z1), (x0, z0)]),
+            ];
+
+            for (dx, dy, [(ax, az), (bx, bz)]) in edges {
+                let neighbour = local
+                    .x()
+                    .checked_add_signed(dx)
+                    .zip(local.y().checked_add_signed(dy))
+                    .and_then(|(x, y)| common::world::LocalPos::new(x, y))
+                    .map_or(0, |neighbour| chunk.height(neighbour));
+
+                if neighbour >= height {
+                    continue;
+                }
 
-                vertices.push(Vertex {
-                    position: glam::vec3(chunk_x as f32 + 1.0, 0.0, chunk_y as f32 + 1.0),
-                    color: glam::vec3(1.0, 1.0, 1.0),
-                    normal: glam::vec3(0.0, 0.0, 0.0),
-                    uv: glam::vec2(offset_x + size, offset_y + size),
-                });
+                let bottom = surface_height(neighbour, layer);
+
+                quad(
+                    [
+                        glam::vec3(ax, y, az),
+                        glam::vec3(bx, y, bz),
+                        glam::vec3(bx, bottom, bz),
+                        glam::vec3(ax, bottom, az),
+                    ],
+                    uv,
+                    0.7,
+                );
             }
         }
 
@@ -604,9 +773,9 @@ impl App {
             None,
             Some(TransformComponent {
                 translation: glam::vec3(
-                    (chunk.position.x * common::world::CHUNK_SIZE) as f32,
+                    chunk.position.x as f32 * common::world::CHUNK_SIZE as f32,
                     0.0,
-                    (chunk.position.y * common::world::CHUNK_SIZE) as f32,
+                    chunk.position.y as f32 * common::world::CHUNK_SIZE as f32,
                 ),
                 scale: glam::Vec3::ONE,
                 rotation: glam::Vec3::ZERO,
@@ -622,7 +791,7 @@ impl App {
 
     fn load_game_objects(
         device: Rc<Device>,
-    ) -> anyhow::Result<(HashMap<u8, GameObject>, u8), AppError> {
+    ) -> anyhow::Result<(HashMap<u32, GameObject>, u32), AppError> {
         let mut game_objects = HashMap::new();
 
         let floor_model = Model::from_file(device.clone(), ModelAsset::get("quad.obj").unwrap())?;
@@ -712,6 +881,17 @@ impl App {
     }
 }
 
+/// Where the tiles of a layer at a height are drawn, up is negative y.
+fn surface_height(height: u8, layer: common::world::Layer) -> f32 {
+    let offset = match layer {
+        common::world::Layer::Ground => 0.0,
+        common::world::Layer::Object => -0.4,
+        common::world::Layer::Decoration => -0.8,
+    };
+
+    offset - height as f32 * HEIGHT_STEP
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
     #[error("")]
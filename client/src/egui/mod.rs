@@ -6,7 +6,7 @@ use crate::{
     app::AppError,
     graphics::{
         vulkan::{EGuiIntegration, Renderer},
-        RenderError, Window,
+        Camera, RenderError, Window,
     },
     network::{Network, NetworkError},
 };
@@ -28,6 +28,14 @@ pub trait Ui {
     fn as_any(&mut self) -> &mut dyn std::any::Any;
 }
 
+/// What left clicking the world does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Place,
+    Raise,
+    Lower,
+}
+
 pub trait View {
     fn ui(&mut self, ui: &mut egui::Ui, props: &Props) -> anyhow::Result<(), AppError>;
 }
@@ -39,6 +47,10 @@ pub struct EGui {
 
     open: BTreeSet<String>,
 
+    show_claims: bool,
+
+    pub tool: Tool,
+
     timer: u16,
     err: Option<NetworkError>,
 }
@@ -52,7 +64,10 @@ impl EGui {
             renderer.swapchain.swapchain_image_format,
         )?;
 
-        let uis_vec: Vec<Box<dyn Ui>> = vec![Box::new(ui::Chat::default())];
+        let uis_vec: Vec<Box<dyn Ui>> = vec![
+            Box::new(ui::Chat::default()),
+            Box::new(ui::TileEntityEditor::default()),
+        ];
 
         let mut uis = HashMap::new();
 
@@ -67,6 +82,10 @@ impl EGui {
 
             open: BTreeSet::new(),
 
+            show_claims: false,
+
+            tool: Tool::Place,
+
             timer: 0,
             err: None,
         })
@@ -84,12 +103,17 @@ impl EGui {
         network: &mut Network,
         players: &Vec<Option<common::Player>>,
         world: &Option<common::world::World>,
-    ) -> anyhow::Result<(bool, Option<common::world::World>), AppError> {
+        camera: Option<&Camera>,
+    ) -> anyhow::Result<(bool, Option<common::JoinInfo>), AppError> {
         self.egui_integration.begin_frame(window);
 
+        if let (true, Some(world), Some(camera)) = (self.show_claims, world, camera) {
+            self.draw_claims(window, world, camera);
+        }
+
         let mut hovered = false;
 
-        let mut join_world = None;
+        let mut join_info = None;
 
         let r = egui::TopBottomPanel::top("top_panel").show(
             &self.egui_integration.egui_ctx.clone(),
@@ -123,8 +147,8 @@ impl EGui {
 
                     if ui.button("Connect").clicked() {
                         match network.connect() {
-                            Ok(world) => {
-                                join_world = world;
+                            Ok(info) => {
+                                join_info = info;
                             }
                             Err(err) => {
                                 self.timer = 500;
@@ -165,15 +189,28 @@ impl EGui {
                     if ui.button("Chat").clicked() {
                         self.toggle_open("Chat");
                     };
+
+                    ui.checkbox(&mut self.show_claims, "Show claims");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Tool: ");
+                        ui.radio_value(&mut self.tool, Tool::Place, "Place");
+                        ui.radio_value(&mut self.tool, Tool::Raise, "Raise");
+                        ui.radio_value(&mut self.tool, Tool::Lower, "Lower");
+                    });
                 }
 
                 if self.timer > 0 {
                     ui.separator();
 
-                    ui.colored_label(
-                        egui::Color32::RED,
-                        format!("Failed to join server: {}", self.err.as_ref().unwrap()),
-                    );
+                    let text = match self.err.as_ref().unwrap() {
+                        NetworkError::Disconnected(reason) => {
+                            format!("Disconnected from server: {}", reason)
+                        }
+                        err => format!("Failed to join server: {}", err),
+                    };
+
+                    ui.colored_label(egui::Color32::RED, text);
                 }
 
                 ui.separator();
@@ -222,7 +259,7 @@ impl EGui {
             }
         }
 
-        Ok((hovered, join_world))
+        Ok((hovered, join_info))
     }
 
     pub unsafe fn resize(
@@ -237,8 +274,71 @@ impl EGui {
         )
     }
 
-    fn set_open(&mut self, key: &'static str, is_open: bool) {
-        if is_open && !self.open.contains(key) {
+    /// Outlines every claim on the ground and labels it with its name.
+    fn draw_claims(&self, window: &Window, world: &common::world::World, camera: &Camera) {
+        let ctx = &self.egui_integration.egui_ctx;
+
+        let painter = ctx.layer_painter(egui::LayerId::background());
+
+        let size = window.inner().inner_size();
+        let size = glam::vec2(size.width as f32, size.height as f32) / ctx.pixels_per_point();
+
+        let view_projection = camera.projection_matrix * camera.view_matrix;
+
+        // projects a point on the ground to screen space, if it is in front of the camera
+        let project = |x: i32, y: i32| {
+            let clip = view_projection * glam::vec4(x as f32, 0.0, y as f32, 1.0);
+
+            if clip.w <= 0.0 {
+                return None;
+            }
+
+            let ndc = glam::vec2(clip.x, clip.y) / clip.w;
+            let screen = (ndc + glam::Vec2::ONE) / 2.0 * size;
+
+            Some(egui::pos2(screen.x, screen.y))
+        };
+
+        for claim in &world.claims {
+            let color = match claim.owner {
+                common::world::ClaimOwner::Player(_) => egui::Color32::LIGHT_BLUE,
+                common::world::ClaimOwner::Group(_) => egui::Color32::LIGHT_RED,
+            };
+
+            let corners = [
+                project(claim.min.x, claim.min.y),
+                project(claim.max.x + 1, claim.min.y),
+                project(claim.max.x + 1, claim.max.y + 1),
+                project(claim.min.x, claim.max.y + 1),
+            ];
+
+            if let [Some(a), Some(b), Some(c), Some(d)] = corners {
+                painter.add(egui::Shape::closed_line(
+                    vec![a, b, c, d],
+                    egui::Stroke::new(2.0, color),
+                ));
+
+                painter.text(
+                    a,
+                    egui::Align2::LEFT_BOTTOM,
+                    &claim.name,
+                    egui::FontId::default(),
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Shows why the server removed us.
+    pub fn disconnected(&mut self, reason: String) {
+        self.set_open("Chat", false);
+
+        self.timer = 500;
+        self.err = Some(NetworkError::Disconnected(reason));
+    }
+
+    pub fn set_open(&mut self, key: &'static str, is_open: bool) {
+        if is_open {
             self.open.insert(key.to_owned());
         } else {
             self.open.remove(key);
@@ -5,9 +5,14 @@ use crate::{
     egui::{Props, Ui, View},
 };
 
+pub enum ChatMessage {
+    Player(String),
+    System(String), // sent by the server only to us, e.g. command output
+}
+
 pub struct Chat {
     pub text: String,
-    pub messages: Vec<String>,
+    pub messages: Vec<ChatMessage>,
 }
 
 impl Default for Chat {
@@ -51,7 +56,14 @@ impl View for Chat {
             .stick_to_bottom()
             .show(ui, |ui| {
                 for message in &self.messages {
-                    ui.label(message);
+                    match message {
+                        ChatMessage::Player(message) => {
+                            ui.label(message);
+                        }
+                        ChatMessage::System(message) => {
+                            ui.colored_label(egui::Color32::YELLOW, message);
+                        }
+                    }
                 }
             });
 
@@ -1,3 +1,5 @@
 mod chat;
+mod tile_entity;
 
 pub use chat::*;
+pub use tile_entity::*;
@@ -0,0 +1,108 @@
+use common::world::{TileEntity, TilePos};
+
+use crate::{
+    app::AppError,
+    egui::{Props, Ui, View},
+};
+
+/// Edits a copy of the entity of a tile, the server sends the chunk back once it
+/// accepted the edit.
+#[derive(Default)]
+pub struct TileEntityEditor {
+    editing: Option<(TilePos, TileEntity)>,
+}
+
+impl TileEntityEditor {
+    pub fn edit(&mut self, position: TilePos, entity: TileEntity) {
+        self.editing = Some((position, entity));
+    }
+}
+
+impl Ui for TileEntityEditor {
+    fn name(&self) -> &'static str {
+        "Tile entity"
+    }
+
+    fn show(
+        &mut self,
+        ctx: &egui::Context,
+        open: &mut bool,
+        props: &Props,
+    ) -> Option<egui::InnerResponse<Option<anyhow::Result<(), AppError>>>> {
+        let title = match &self.editing {
+            Some((position, entity)) => format!("{} at {}", entity.kind(), position),
+            None => "Nothing selected".to_string(),
+        };
+
+        egui::Window::new(title)
+            .id(egui::Id::new(self.name()))
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| -> anyhow::Result<(), AppError> {
+                self.ui(ui, props)
+            })
+    }
+
+    fn as_any(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl View for TileEntityEditor {
+    fn ui(&mut self, ui: &mut egui::Ui, props: &Props) -> anyhow::Result<(), AppError> {
+        let (position, entity) = match &mut self.editing {
+            Some(editing) => editing,
+            None => {
+                ui.label("Right click a sign, chest or door to edit it");
+                return Ok(());
+            }
+        };
+
+        match entity {
+            TileEntity::Sign { text } => {
+                ui.text_edit_multiline(text);
+            }
+            TileEntity::Container { items } => {
+                let mut removed = None;
+
+                for (i, item) in items.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(item);
+
+                        if ui.button("Remove").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+
+                if let Some(i) = removed {
+                    items.remove(i);
+                }
+
+                if items.len() < TileEntity::MAX_ITEMS && ui.button("Add").clicked() {
+                    items.push(String::new());
+                }
+            }
+            TileEntity::Door { open } => {
+                // doors don't need saving, they just open
+                let label = if *open { "Close" } else { "Open" };
+
+                if ui.button(label).clicked() {
+                    *open = !*open;
+
+                    props.network.send_tile_entity_edit(*position, entity)?;
+                }
+
+                return Ok(());
+            }
+        }
+
+        ui.separator();
+
+        if ui.button("Save").clicked() {
+            props.network.send_tile_entity_edit(*position, entity)?;
+        }
+
+        Ok(())
+    }
+}
@@ -29,10 +29,10 @@ pub struct PointLightComponent {
     pub light_intensity: f32,
 }
 
-static mut CURRENT_ID: u8 = 0;
+static mut CURRENT_ID: u32 = 0;
 
 pub struct GameObject {
-    pub id: u8,
+    pub id: u32,
     pub model: Option<Rc<Model>>,
     pub color: glam::Vec3,
     pub transform: TransformComponent,
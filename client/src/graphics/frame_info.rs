@@ -31,5 +31,5 @@ pub struct FrameInfo<'a> {
     pub camera: &'a Camera,
     pub global_descriptor_set: ash::vk::DescriptorSet,
     pub image_descriptor_set: ash::vk::DescriptorSet,
-    pub game_objects: &'a mut HashMap<u8, GameObject>,
+    pub game_objects: &'a mut HashMap<u32, GameObject>,
 }
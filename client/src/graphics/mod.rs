@@ -1,6 +1,5 @@
 mod camera;
 mod frame_info;
-mod plane;
 mod ray;
 pub mod systems;
 mod tile_atlas;
@@ -9,7 +8,6 @@ mod window;
 
 pub use camera::*;
 pub use frame_info::*;
-pub use plane::*;
 pub use ray::*;
 pub use tile_atlas::*;
 pub use window::*;
@@ -33,4 +33,18 @@ impl Ray {
             dir: ray_world.normalize(),
         })
     }
+
+    /// Steps along the ray until `hit` is true for a point, up to `max_distance` away.
+    pub fn march(
+        &self,
+        step: f32,
+        max_distance: f32,
+        hit: impl Fn(glam::Vec3) -> bool,
+    ) -> Option<glam::Vec3> {
+        let steps = (max_distance / step) as u32;
+
+        (0..=steps)
+            .map(|i| self.origin + self.dir * (i as f32 * step))
+            .find(|point| hit(*point))
+    }
 }
@@ -10,6 +10,8 @@ use super::{
 pub struct TileAtlas {
     image_buffer: image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
     textures: Vec<image::DynamicImage>,
+    /// The name of the texture in each slot, row by row.
+    names: Vec<String>,
 
     pub size: u32,
     pub tile_size: u32,
@@ -22,23 +24,48 @@ impl TileAtlas {
         Ok(Self {
             image_buffer,
             textures: Vec::new(),
+            names: Vec::new(),
 
             size,
             tile_size,
         })
     }
 
+    /// Adds a texture that is looked up by `name` in `uv`.
     pub fn add_texture(
         &mut self,
+        name: &str,
         asset: rust_embed::EmbeddedFile,
     ) -> anyhow::Result<(), RenderError> {
         let image = image::load_from_memory(asset.data.as_ref())?;
 
         self.textures.push(image);
+        self.names.push(name.to_string());
 
         Ok(())
     }
 
+    /// The corner and size of the texture named `name` in texture coordinates, inset by
+    /// half a pixel so neighbouring textures do not bleed in. Unknown names get the first
+    /// texture.
+    pub fn uv(&self, name: &str) -> (glam::Vec2, f32) {
+        let slot = self
+            .names
+            .iter()
+            .position(|other| other == name)
+            .unwrap_or(0) as u32;
+
+        let slot_size = 1.0 / self.size as f32;
+        let pixel = 1.0 / (self.size * self.tile_size) as f32;
+
+        let corner = glam::vec2(
+            (slot % self.size) as f32 * slot_size,
+            (slot / self.size) as f32 * slot_size,
+        );
+
+        (corner + glam::Vec2::splat(pixel / 2.0), slot_size - pixel)
+    }
+
     pub fn build(&mut self, device: Rc<Device>) -> anyhow::Result<Rc<Image>, RenderError> {
         let mut x = 0;
         let mut y = 0;
@@ -3,6 +3,9 @@ use std::{
     net::{SocketAddr, UdpSocket},
 };
 
+/// The most chunks the server sends for a single request.
+const MAX_CHUNK_REQUEST: usize = 16;
+
 pub struct Network {
     pub ip: String,
     socket: Option<UdpSocket>,
@@ -28,68 +31,78 @@ impl Network {
         }
     }
 
-    pub fn connect(&mut self) -> anyhow::Result<Option<common::world::World>, NetworkError> {
+    pub fn connect(&mut self) -> anyhow::Result<Option<common::JoinInfo>, NetworkError> {
         if !self.connected {
-            if self.username != "".to_string() {
-                match self.ip.parse::<SocketAddr>() {
-                    Ok(remote_addr) => {
-                        let local_addr: SocketAddr = if remote_addr.is_ipv4() {
-                            "0.0.0.0:0"
-                        } else {
-                            "[::]:0"
-                        }
-                        .parse()
-                        .unwrap();
+            common::validate_username(&self.username)?;
+
+            match self.ip.parse::<SocketAddr>() {
+                Ok(remote_addr) => {
+                    let local_addr: SocketAddr = if remote_addr.is_ipv4() {
+                        "0.0.0.0:0"
+                    } else {
+                        "[::]:0"
+                    }
+                    .parse()
+                    .unwrap();
 
-                        self.socket = UdpSocket::bind(local_addr).ok();
+                    self.socket = UdpSocket::bind(local_addr).ok();
 
-                        if let Some(socket) = &self.socket {
-                            socket.connect(remote_addr)?;
+                    if let Some(socket) = &self.socket {
+                        socket.connect(remote_addr)?;
 
-                            // register as client in server
+                        // register as client in server
 
-                            let mut send = vec![common::ClientPacket::Join as u8];
-                            send.extend(&mut self.username.as_bytes().iter().copied());
+                        let mut send = vec![common::ClientPacket::Join as u8];
+                        send.extend(&mut self.username.as_bytes().iter().copied());
 
-                            socket.send(&send)?;
+                        socket.send(&send)?;
 
-                            let mut response = vec![0u8; 1_024];
-                            let len = socket.recv(&mut response)?;
+                        let mut response = vec![0u8; common::MAX_DATAGRAM_SIZE];
+                        let len = socket.recv(&mut response)?;
 
-                            let join_result = common::ServerPacket::try_from(response[0]).unwrap();
+                        let join_result = common::ServerPacket::try_from(response[0]).unwrap();
 
-                            match join_result {
-                                common::ServerPacket::JoinResult => {
-                                    if len > 1 as usize {
-                                        let split = response.split_at(2);
+                        match join_result {
+                            common::ServerPacket::JoinResult => {
+                                if len > 1 as usize {
+                                    let split = response.split_at(2);
 
-                                        let user_id = split.0[1];
+                                    let user_id = split.0[1];
 
-                                        println!("user id: {}", user_id);
+                                    println!("user id: {}", user_id);
 
-                                        let world = bincode::decode_from_slice(
-                                            split.1,
-                                            bincode::config::standard(),
-                                        )
-                                        .unwrap()
-                                        .0;
+                                    let join_info = bincode::decode_from_slice(
+                                        split.1,
+                                        bincode::config::standard(),
+                                    )
+                                    .unwrap()
+                                    .0;
 
-                                        self.connected = true;
-                                        self.client_id = Some(user_id);
+                                    self.connected = true;
+                                    self.client_id = Some(user_id);
 
-                                        return Ok(Some(world));
-                                    } else {
-                                        log::info!("Server did not let us in");
-                                    }
+                                    return Ok(Some(join_info));
+                                } else {
+                                    log::info!("Server did not let us in");
                                 }
-                                _ => {}
                             }
+                            common::ServerPacket::JoinRejected => {
+                                let rejection = bincode::decode_from_slice(
+                                    &response[1..len],
+                                    bincode::config::standard(),
+                                )
+                                .unwrap()
+                                .0;
+
+                                self.socket = None;
+
+                                return Err(NetworkError::Rejected(rejection));
+                            }
+                            _ => {}
                         }
                     }
-                    Err(_) => return Err(NetworkError::InvalidIP),
                 }
-            } else {
-                return Err(NetworkError::EmptyUsername);
+                Err(_) => return Err(NetworkError::InvalidIP),
             }
         }
 
@@ -111,6 +124,14 @@ impl Network {
         Ok(())
     }
 
+    /// Forgets the connection without telling the server, used when the server removed us.
+    pub fn reset(&mut self) {
+        self.socket = None;
+
+        self.connected = false;
+        self.client_id = None;
+    }
+
     pub fn update(
         &mut self,
     ) -> anyhow::Result<(Option<common::ServerPacket>, Vec<u8>), NetworkError> {
@@ -130,7 +151,7 @@ impl Network {
 
                 self.keep_alive_timer += 1;
 
-                let mut data = vec![0u8; 1_024];
+                let mut data = vec![0u8; common::MAX_DATAGRAM_SIZE];
 
                 match socket.recv(&mut data) {
                     Ok(len) => {
@@ -162,9 +183,59 @@ impl Network {
         Ok(())
     }
 
+    pub fn send_height_change(
+        &self,
+        position: common::world::TilePos,
+        change: common::world::HeightChange,
+    ) -> anyhow::Result<(), NetworkError> {
+        if self.connected {
+            if let Some(socket) = &self.socket {
+                let mut send = vec![
+                    common::ClientPacket::ChangeHeight as u8,
+                    self.client_id.unwrap(),
+                ];
+
+                send.extend(
+                    bincode::serde::encode_to_vec((position, change), bincode::config::standard())
+                        .unwrap(),
+                );
+
+                socket.send(&send)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Asks the server to replace the entity of the tile at `position` with `entity`.
+    pub fn send_tile_entity_edit(
+        &self,
+        position: common::world::TilePos,
+        entity: &common::world::TileEntity,
+    ) -> anyhow::Result<(), NetworkError> {
+        if self.connected {
+            if let Some(socket) = &self.socket {
+                let mut send = vec![
+                    common::ClientPacket::EditTileEntity as u8,
+                    self.client_id.unwrap(),
+                ];
+
+                send.extend(
+                    bincode::serde::encode_to_vec((position, entity), bincode::config::standard())
+                        .unwrap(),
+                );
+
+                socket.send(&send)?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn send_client_world_click(
         &self,
-        position: glam::Vec2,
+        position: common::world::TilePos,
+        layer: common::world::Layer,
     ) -> anyhow::Result<(), NetworkError> {
         if self.connected {
             if let Some(socket) = &self.socket {
@@ -173,16 +244,14 @@ impl Network {
                     self.client_id.unwrap(),
                 ];
 
-                let p = common::Position {
-                    x: position.x as usize,
-                    y: position.y as usize,
-                };
-
                 send.extend(
-                    &mut bincode::serde::encode_to_vec(p, bincode::config::standard())
-                        .unwrap()
-                        .iter()
-                        .copied(),
+                    &mut bincode::serde::encode_to_vec(
+                        (position, layer),
+                        bincode::config::standard(),
+                    )
+                    .unwrap()
+                    .iter()
+                    .copied(),
                 );
 
                 socket.send(&send)?;
@@ -194,6 +263,33 @@ impl Network {
         Ok(())
     }
 
+    /// Asks for the chunks at `positions`, at most `MAX_CHUNK_REQUEST` at a time. They
+    /// arrive as `ChunkModified` packets.
+    pub fn request_chunks(
+        &self,
+        positions: &[common::world::ChunkPos],
+    ) -> anyhow::Result<(), NetworkError> {
+        if self.connected {
+            if let Some(socket) = &self.socket {
+                for positions in positions.chunks(MAX_CHUNK_REQUEST) {
+                    let mut send = vec![
+                        common::ClientPacket::RequestChunks as u8,
+                        self.client_id.unwrap(),
+                    ];
+
+                    send.extend(
+                        bincode::serde::encode_to_vec(positions, bincode::config::standard())
+                            .unwrap(),
+                    );
+
+                    socket.send(&send)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn server_ip(&self) -> Option<SocketAddr> {
         self.socket.as_ref().unwrap().peer_addr().ok()
     }
@@ -201,10 +297,14 @@ impl Network {
 
 #[derive(thiserror::Error, Debug)]
 pub enum NetworkError {
-    #[error("Username is empty")]
-    EmptyUsername,
+    #[error("{0}")]
+    InvalidUsername(#[from] common::UsernameError),
     #[error("Invalid IP")]
     InvalidIP,
+    #[error("{0}")]
+    Rejected(common::JoinRejection),
+    #[error("{0}")]
+    Disconnected(String),
     #[error("IO error")]
     NetworkError(#[from] io::Error),
 }
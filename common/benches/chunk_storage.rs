@@ -0,0 +1,147 @@
+//! Compares paletted chunks with the full grids chunks used to be stored in, for memory
+//! use, encoded size and encode time. Run with `cargo bench -p common`.
+
+use std::{
+    hint::black_box,
+    mem::size_of,
+    time::{Duration, Instant},
+};
+
+use common::world::{
+    BiomeGenerator, Chunk, ChunkPos, FlatGenerator, Layer, LocalPos, Tile, TileId, WorldGenerator,
+    CHUNK_SIZE,
+};
+
+/// How many times every chunk is encoded when timing.
+const ROUNDS: u32 = 50;
+
+/// A chunk as it was stored before palettes, a full grid per layer.
+#[derive(serde::Serialize)]
+struct ArrayChunk {
+    position: ChunkPos,
+    layers: Vec<ndarray::Array2<Option<Tile>>>,
+    heights: ndarray::Array2<u8>,
+}
+
+impl ArrayChunk {
+    fn new(chunk: &Chunk) -> Self {
+        let local = |(x, y)| LocalPos::new(x, y).unwrap();
+
+        Self {
+            position: chunk.position,
+            layers: Layer::ALL
+                .into_iter()
+                .map(|layer| {
+                    ndarray::Array2::from_shape_fn((CHUNK_SIZE, CHUNK_SIZE), |index| {
+                        chunk.tile(local(index), layer)
+                    })
+                })
+                .collect(),
+            heights: ndarray::Array2::from_shape_fn((CHUNK_SIZE, CHUNK_SIZE), |index| {
+                chunk.height(local(index))
+            }),
+        }
+    }
+
+    fn memory(&self) -> usize {
+        size_of::<Self>()
+            + self.layers.capacity() * size_of::<ndarray::Array2<Option<Tile>>>()
+            + self.layers.iter().map(|layer| layer.len()).sum::<usize>() * size_of::<Option<Tile>>()
+            + self.heights.len()
+    }
+}
+
+fn paletted_memory(chunk: &Chunk) -> usize {
+    size_of::<Chunk>()
+        + chunk
+            .layers
+            .iter()
+            .map(|layer| layer.heap_size())
+            .sum::<usize>()
+        + chunk.heights.heap_size()
+}
+
+/// Objects scattered over the ground, with a different tile on almost every position.
+#[derive(Debug)]
+struct NoisyGenerator;
+
+impl WorldGenerator for NoisyGenerator {
+    fn generate(&self, seed: u32, position: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(position);
+
+        let mut state = seed ^ (position.x as u32).wrapping_mul(31) ^ position.y as u32;
+        let mut random = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            state >> 16
+        };
+
+        for local in LocalPos::all() {
+            let ground = Tile {
+                ty: TileId((random() % 8) as u16),
+            };
+            chunk.set_tile(local, Layer::Ground, Some(ground));
+
+            if random() % 3 == 0 {
+                let object = Tile {
+                    ty: TileId(8 + (random() % 5) as u16),
+                };
+                chunk.set_tile(local, Layer::Object, Some(object));
+            }
+
+            chunk.set_height(local, (random() % 8) as u8);
+        }
+
+        chunk
+    }
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Vec<u8> {
+    bincode::serde::encode_to_vec(value, bincode::config::standard()).unwrap()
+}
+
+/// The average time to encode one of `values`.
+fn time_encoding<T: serde::Serialize>(values: &[T]) -> Duration {
+    let start = Instant::now();
+
+    for _ in 0..ROUNDS {
+        for value in values {
+            black_box(encode(value));
+        }
+    }
+
+    start.elapsed() / (ROUNDS * values.len() as u32)
+}
+
+fn compare(name: &str, generator: &dyn WorldGenerator) {
+    let chunks = (-8..8)
+        .flat_map(|y| (-8..8).map(move |x| ChunkPos::new(x, y)))
+        .map(|position| generator.generate(7, position))
+        .collect::<Vec<_>>();
+
+    let arrays = chunks.iter().map(ArrayChunk::new).collect::<Vec<_>>();
+
+    let average = |total: usize| total / chunks.len();
+
+    println!("{} ({} chunks)", name, chunks.len());
+    println!(
+        "  memory   arrays {:>6} B  paletted {:>6} B",
+        average(arrays.iter().map(ArrayChunk::memory).sum()),
+        average(chunks.iter().map(paletted_memory).sum()),
+    );
+    println!(
+        "  encoded  arrays {:>6} B  paletted {:>6} B",
+        average(arrays.iter().map(|chunk| encode(chunk).len()).sum()),
+        average(chunks.iter().map(|chunk| encode(chunk).len()).sum()),
+    );
+    println!(
+        "  encode   arrays {:>8.2?}  paletted {:>8.2?}",
+        time_encoding(&arrays),
+        time_encoding(&chunks),
+    );
+}
+
+fn main() {
+    compare("flat", &FlatGenerator { tile: TileId(0) });
+    compare("biomes", &BiomeGenerator::default());
+    compare("noisy", &NoisyGenerator);
+}
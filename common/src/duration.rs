@@ -0,0 +1,24 @@
+/// Formats seconds with the largest unit that fits, e.g. `90` as `1m`.
+pub fn format_duration(seconds: u64) -> String {
+    match seconds {
+        s if s >= 60 * 60 * 24 => format!("{}d", s / (60 * 60 * 24)),
+        s if s >= 60 * 60 => format!("{}h", s / (60 * 60)),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_largest_unit_that_fits_is_used() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(90), "1m");
+        assert_eq!(format_duration(60 * 60), "1h");
+        assert_eq!(format_duration(60 * 60 * 24 - 1), "23h");
+        assert_eq!(format_duration(60 * 60 * 24 * 3), "3d");
+    }
+}
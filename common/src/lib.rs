@@ -1,30 +1,47 @@
 use bincode::{Decode, Encode};
-use serde::{Deserialize, Serialize};
 
+mod duration;
+mod rejection;
+mod username;
 pub mod world;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct Position {
-    pub x: usize,
-    pub y: usize,
-}
+pub use duration::*;
+pub use rejection::*;
+pub use username::*;
+
+/// The largest UDP payload, whole worlds and chunks are sent in a single packet so
+/// receive buffers are this large.
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
 
 #[derive(Debug, Encode, Decode)]
 pub struct Player {
     pub username: String,
 }
 
-#[derive(Clone, Copy, num_enum::TryFromPrimitive)]
+/// Sent with `ServerPacket::JoinResult` after the client id.
+#[derive(Debug, Encode, Decode)]
+pub struct JoinInfo {
+    /// The world the client joined, without chunks.
+    pub world: world::World,
+    /// What the numeric tile ids in chunks mean on this server.
+    #[bincode(with_serde)]
+    pub tiles: world::TileRegistry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, num_enum::TryFromPrimitive)]
 #[repr(u8)]
 pub enum ClientPacket {
     Join,
     Leave,
     KeepAlive,
     Chat,
-    WorldClick,
+    WorldClick,     // a tile position and the layer that was clicked
+    RequestChunks,  // asks for the chunks at a list of chunk positions
+    EditTileEntity, // a tile position and the edited entity of the tile there
+    ChangeHeight,   // a tile position and whether to raise or lower it
 }
 
-#[derive(Clone, Copy, num_enum::TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, num_enum::TryFromPrimitive)]
 #[repr(u8)]
 pub enum ServerPacket {
     JoinResult,
@@ -32,4 +49,9 @@ pub enum ServerPacket {
     ClientLeave, // sent to all other clients when a client leaves the server
     Chat,
     ChunkModified,
+    SystemMessage, // sent only to a single client, e.g. command output
+    Disconnect,    // sent to a client that was removed by the server, with the reason
+    ClaimsModified,
+    JoinRejected, // sent instead of JoinResult, with the reason
+    WorldChanged, // sent to a client that was moved to another world, with that world
 }
@@ -0,0 +1,40 @@
+use std::fmt;
+
+use bincode::{Decode, Encode};
+
+/// Why the server refused to let a client join, sent with `ServerPacket::JoinRejected`.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum JoinRejection {
+    InvalidUsername(String),
+    UsernameTaken(String),
+    ServerFull,
+    Banned {
+        reason: String,
+        /// Seconds until the ban is lifted, `None` if it is permanent.
+        remaining: Option<u64>,
+    },
+    NotWhitelisted,
+    TooManyAttempts,
+    ShuttingDown,
+}
+
+impl fmt::Display for JoinRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUsername(reason) => write!(f, "{}", reason),
+            Self::UsernameTaken(username) => write!(f, "{} is already online", username),
+            Self::ServerFull => write!(f, "The server is full"),
+            Self::Banned { reason, remaining } => {
+                write!(f, "You are banned: {}", reason)?;
+
+                match remaining {
+                    Some(seconds) => write!(f, " ({} left)", crate::format_duration(*seconds)),
+                    None => Ok(()),
+                }
+            }
+            Self::NotWhitelisted => write!(f, "You are not whitelisted on this server"),
+            Self::TooManyAttempts => write!(f, "Too many join attempts, try again later"),
+            Self::ShuttingDown => write!(f, "The server is shutting down"),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 16;
+
+/// Names nobody may join as, since the server uses them for itself.
+pub const RESERVED_USERNAMES: &[&str] = &["server", "console", "system", "script", "admin"];
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum UsernameError {
+    #[error("Username must be at least {} characters", MIN_USERNAME_LENGTH)]
+    TooShort,
+    #[error("Username must be at most {} characters", MAX_USERNAME_LENGTH)]
+    TooLong,
+    #[error("Username can not contain {0:?}, only letters, digits and _")]
+    InvalidCharacter(char),
+    #[error("The username {0} is reserved")]
+    Reserved(String),
+}
+
+/// Checks that `username` is 3 to 16 ASCII letters, digits or underscores and not reserved.
+pub fn validate_username(username: &str) -> Result<(), UsernameError> {
+    if let Some(c) = username
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '_')
+    {
+        return Err(UsernameError::InvalidCharacter(c));
+    }
+
+    if username.len() < MIN_USERNAME_LENGTH {
+        return Err(UsernameError::TooShort);
+    }
+
+    if username.len() > MAX_USERNAME_LENGTH {
+        return Err(UsernameError::TooLong);
+    }
+
+    if RESERVED_USERNAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(username))
+    {
+        return Err(UsernameError::Reserved(username.to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_are_limited() {
+        assert_eq!(validate_username(""), Err(UsernameError::TooShort));
+        assert_eq!(validate_username("ab"), Err(UsernameError::TooShort));
+        assert_eq!(validate_username("abc"), Ok(()));
+        assert_eq!(validate_username(&"a".repeat(MAX_USERNAME_LENGTH)), Ok(()));
+        assert_eq!(
+            validate_username(&"a".repeat(MAX_USERNAME_LENGTH + 1)),
+            Err(UsernameError::TooLong)
+        );
+    }
+
+    #[test]
+    fn only_letters_digits_and_underscores_are_allowed() {
+        assert_eq!(validate_username("Alice_1984"), Ok(()));
+        assert_eq!(validate_username("___"), Ok(()));
+
+        assert_eq!(
+            validate_username("alice bob"),
+            Err(UsernameError::InvalidCharacter(' '))
+        );
+        assert_eq!(
+            validate_username("alice-bob"),
+            Err(UsernameError::InvalidCharacter('-'))
+        );
+        assert_eq!(
+            validate_username("al\0ce"),
+            Err(UsernameError::InvalidCharacter('\0'))
+        );
+        // letters outside ASCII are not allowed either
+        assert_eq!(
+            validate_username("äb"),
+            Err(UsernameError::InvalidCharacter('ä'))
+        );
+    }
+
+    #[test]
+    fn reserved_names_are_rejected_in_any_case() {
+        for reserved in RESERVED_USERNAMES {
+            assert_eq!(
+                validate_username(reserved),
+                Err(UsernameError::Reserved(reserved.to_string()))
+            );
+        }
+
+        assert_eq!(
+            validate_username("Console"),
+            Err(UsernameError::Reserved("Console".to_string()))
+        );
+        assert_eq!(validate_username("consoles"), Ok(()));
+    }
+}
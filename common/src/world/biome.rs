@@ -0,0 +1,511 @@
+//! Biomes picked from elevation, temperature and moisture noise.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use noise::{NoiseFn, Perlin, Seedable};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    fnv1a, Chunk, ChunkPos, Layer, LocalPos, Tile, TileId, TilePos, TileRegistry,
+    TileRegistryError, WorldGenerator, MAX_HEIGHT,
+};
+
+/// Fractal noise, every octave adds detail at twice the frequency of the one before.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NoiseLayer {
+    /// The size of the largest features in tiles.
+    pub scale: f64,
+    pub octaves: u32,
+    /// How much of the strength of the previous octave each octave keeps.
+    pub persistence: f64,
+}
+
+/// An inclusive range of noise values, noise is always between -1 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Range {
+    pub const ANY: Range = Range::new(-1.0, 1.0);
+
+    pub const fn new(min: f64, max: f64) -> Self {
+        Self { min, max }
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+impl Default for Range {
+    fn default() -> Self {
+        Self::ANY
+    }
+}
+
+/// A tile used where the climate is inside all three ranges, a range left out of the
+/// config matches everything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Biome {
+    pub name: String,
+    /// The string id of a tile in the tile registry.
+    pub tile: String,
+    #[serde(default)]
+    pub elevation: Range,
+    #[serde(default)]
+    pub temperature: Range,
+    #[serde(default)]
+    pub moisture: Range,
+}
+
+impl Biome {
+    fn new(name: &str, tile: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tile: tile.to_string(),
+            elevation: Range::ANY,
+            temperature: Range::ANY,
+            moisture: Range::ANY,
+        }
+    }
+
+    pub fn matches(&self, climate: &Climate) -> bool {
+        self.elevation.contains(climate.elevation)
+            && self.temperature.contains(climate.temperature)
+            && self.moisture.contains(climate.moisture)
+    }
+}
+
+/// How biomes are generated, loaded from a config file by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BiomeConfig {
+    pub elevation: NoiseLayer,
+    pub temperature: NoiseLayer,
+    pub moisture: NoiseLayer,
+    /// How far each tile's climate is randomly nudged, so biomes mix along their edges
+    /// instead of meeting in a hard line.
+    pub blend: f64,
+    /// The elevation of the coast, tiles below it are at height 0.
+    pub sea_level: f64,
+    /// The height of tiles at the highest elevation, in height steps.
+    pub relief: f64,
+    /// The tile where no biome matches.
+    pub fallback: String,
+    /// Checked in order, the first match wins.
+    pub biomes: Vec<Biome>,
+}
+
+impl Default for BiomeConfig {
+    fn default() -> Self {
+        Self {
+            elevation: NoiseLayer {
+                scale: 96.0,
+                octaves: 4,
+                persistence: 0.5,
+            },
+            temperature: NoiseLayer {
+                scale: 256.0,
+                octaves: 2,
+                persistence: 0.5,
+            },
+            moisture: NoiseLayer {
+                scale: 160.0,
+                octaves: 3,
+                persistence: 0.5,
+            },
+            blend: 0.03,
+            sea_level: -0.15,
+            relief: 12.0,
+            fallback: "grass".to_string(),
+            biomes: vec![
+                Biome {
+                    elevation: Range::new(-1.0, -0.4),
+                    ..Biome::new("deep ocean", "deep_water")
+                },
+                Biome {
+                    elevation: Range::new(-1.0, -0.15),
+                    ..Biome::new("ocean", "water")
+                },
+                Biome {
+                    elevation: Range::new(-0.15, -0.08),
+                    ..Biome::new("beach", "sand")
+                },
+                Biome {
+                    temperature: Range::new(-1.0, -0.35),
+                    ..Biome::new("tundra", "snow")
+                },
+                Biome {
+                    elevation: Range::new(0.45, 1.0),
+                    ..Biome::new("mountains", "stone")
+                },
+                Biome {
+                    temperature: Range::new(0.3, 1.0),
+                    moisture: Range::new(-1.0, -0.1),
+                    ..Biome::new("desert", "sand")
+                },
+                Biome {
+                    moisture: Range::new(0.15, 1.0),
+                    ..Biome::new("forest", "forest_floor")
+                },
+                Biome {
+                    moisture: Range::new(-1.0, -0.3),
+                    ..Biome::new("badlands", "dirt")
+                },
+                Biome::new("plains", "grass"),
+            ],
+        }
+    }
+}
+
+/// The noise values at a tile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Climate {
+    pub elevation: f64,
+    pub temperature: f64,
+    pub moisture: f64,
+}
+
+/// A `NoiseLayer` seeded for one world.
+pub(super) struct Fractal {
+    layer: NoiseLayer,
+    octaves: Vec<Perlin>,
+}
+
+impl Fractal {
+    pub(super) fn new(layer: NoiseLayer, seed: u32, index: u8) -> Self {
+        // every layer and octave gets its own permutation, derived from the world seed
+        let octaves = (0..layer.octaves.min(16))
+            .map(|octave| {
+                let mut bytes = seed.to_le_bytes().to_vec();
+                bytes.extend([index, octave as u8]);
+
+                Perlin::new().set_seed(fnv1a(&bytes))
+            })
+            .collect();
+
+        Self { layer, octaves }
+    }
+
+    /// Only multiplies and adds so the result is the same on every platform.
+    pub(super) fn get(&self, tile: TilePos) -> f64 {
+        let mut frequency = 1.0 / self.layer.scale;
+        let mut amplitude = 1.0;
+
+        let mut value = 0.0;
+        let mut total = 0.0;
+
+        for perlin in &self.octaves {
+            value += perlin.get([tile.x as f64 * frequency, tile.y as f64 * frequency]) * amplitude;
+            total += amplitude;
+
+            frequency *= 2.0;
+            amplitude *= self.layer.persistence;
+        }
+
+        if total > 0.0 {
+            value / total
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Keeps what a generator built for the last seed it was asked for, a world always
+/// asks with the same seed so noise is only seeded once.
+pub(super) struct SeedCache<T>(Mutex<Option<(u32, Arc<T>)>>);
+
+impl<T> SeedCache<T> {
+    pub(super) fn get(&self, seed: u32, build: impl FnOnce() -> T) -> Arc<T> {
+        let mut cached = self.0.lock().unwrap_or_else(|err| err.into_inner());
+
+        match &*cached {
+            Some((cached_seed, value)) if *cached_seed == seed => value.clone(),
+            _ => {
+                let value = Arc::new(build());
+                *cached = Some((seed, value.clone()));
+
+                value
+            }
+        }
+    }
+}
+
+impl<T> Default for SeedCache<T> {
+    fn default() -> Self {
+        Self(Mutex::new(None))
+    }
+}
+
+impl<T> Clone for SeedCache<T> {
+    /// Clones start out empty, they are cheap to fill again.
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<T> fmt::Debug for SeedCache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SeedCache")
+    }
+}
+
+/// Generates chunks by looking up the climate of every tile in a `BiomeConfig`.
+#[derive(Debug, Clone)]
+pub struct BiomeGenerator {
+    config: BiomeConfig,
+    /// The tile of each biome, resolved from the registry.
+    tiles: Vec<TileId>,
+    fallback: TileId,
+    noise: SeedCache<[Fractal; 3]>,
+}
+
+impl BiomeGenerator {
+    /// Fails if a biome uses a tile `tiles` does not know.
+    pub fn new(config: BiomeConfig, tiles: &TileRegistry) -> Result<Self, TileRegistryError> {
+        Ok(Self {
+            tiles: config
+                .biomes
+                .iter()
+                .map(|biome| tiles.resolve(&biome.tile))
+                .collect::<Result<_, _>>()?,
+            fallback: tiles.resolve(&config.fallback)?,
+            config,
+            noise: SeedCache::default(),
+        })
+    }
+
+    pub fn config(&self) -> &BiomeConfig {
+        &self.config
+    }
+
+    fn noise(&self, seed: u32) -> Arc<[Fractal; 3]> {
+        self.noise.get(seed, || {
+            [
+                Fractal::new(self.config.elevation, seed, 0),
+                Fractal::new(self.config.temperature, seed, 1),
+                Fractal::new(self.config.moisture, seed, 2),
+            ]
+        })
+    }
+
+    fn climate(&self, noise: &[Fractal; 3], seed: u32, tile: TilePos) -> Climate {
+        let nudge = |index: u8| self.config.blend * jitter(seed, tile, index);
+
+        Climate {
+            elevation: (noise[0].get(tile) + nudge(0)).clamp(-1.0, 1.0),
+            temperature: (noise[1].get(tile) + nudge(1)).clamp(-1.0, 1.0),
+            moisture: (noise[2].get(tile) + nudge(2)).clamp(-1.0, 1.0),
+        }
+    }
+
+    /// The tile of the first biome that matches `climate`.
+    pub fn tile_type(&self, climate: &Climate) -> TileId {
+        self.config
+            .biomes
+            .iter()
+            .position(|biome| biome.matches(climate))
+            .map_or(self.fallback, |i| self.tiles[i])
+    }
+}
+
+impl Default for BiomeGenerator {
+    /// The default biomes with the default tiles.
+    fn default() -> Self {
+        Self::new(BiomeConfig::default(), &TileRegistry::default()).unwrap()
+    }
+}
+
+impl WorldGenerator for BiomeGenerator {
+    /// Neighbouring chunks line up because the noise is sampled in world space.
+    fn generate(&self, seed: u32, position: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(position);
+
+        let noise = self.noise(seed);
+
+        for local in LocalPos::all() {
+            // tiles of chunks too far out to address keep the default tile
+            let tile = match position.tile(local) {
+                Some(tile) => tile,
+                None => continue,
+            };
+
+            let climate = self.climate(&noise, seed, tile);
+
+            chunk.set_tile(
+                local,
+                Layer::Ground,
+                Some(Tile {
+                    ty: self.tile_type(&climate),
+                }),
+            );
+            chunk.set_height(
+                local,
+                height(climate.elevation, self.config.sea_level, self.config.relief),
+            );
+        }
+
+        chunk
+    }
+
+    fn ground(&self, seed: u32, position: TilePos) -> Option<Tile> {
+        let climate = self.climate(&self.noise(seed), seed, position);
+
+        Some(Tile {
+            ty: self.tile_type(&climate),
+        })
+    }
+}
+
+/// The height of a tile at `elevation`, from 0 at the sea level up to `relief` at the
+/// highest elevation.
+pub(super) fn height(elevation: f64, sea_level: f64, relief: f64) -> u8 {
+    let above = (elevation - sea_level).max(0.0) / (1.0 - sea_level);
+
+    (above * relief).round().clamp(0.0, MAX_HEIGHT as f64) as u8
+}
+
+/// A value between -1 and 1 that only depends on the seed, tile and layer.
+fn jitter(seed: u32, tile: TilePos, index: u8) -> f64 {
+    let mut bytes = Vec::with_capacity(13);
+    bytes.extend(seed.to_le_bytes());
+    bytes.extend(tile.x.to_le_bytes());
+    bytes.extend(tile.y.to_le_bytes());
+    bytes.push(index);
+
+    fnv1a(&bytes) as f64 / u32::MAX as f64 * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_biome_wins() {
+        let generator = BiomeGenerator::default();
+        let tiles = TileRegistry::default();
+
+        let climate = |elevation, temperature, moisture| Climate {
+            elevation,
+            temperature,
+            moisture,
+        };
+
+        assert_eq!(
+            generator.tile_type(&climate(-0.9, -0.9, 0.0)),
+            tiles.resolve("deep_water").unwrap()
+        );
+        assert_eq!(
+            generator.tile_type(&climate(0.0, -0.9, 0.0)),
+            tiles.resolve("snow").unwrap()
+        );
+        assert_eq!(
+            generator.tile_type(&climate(0.0, 0.0, 0.0)),
+            tiles.resolve("grass").unwrap()
+        );
+    }
+
+    #[test]
+    fn ground_matches_the_chunk_whatever_the_seed() {
+        let generator = BiomeGenerator::default();
+        let position = ChunkPos::new(4, -7);
+
+        // switching seeds must not keep the noise of the last one
+        for seed in [1, 2, 1] {
+            let chunk = generator.generate(seed, position);
+
+            for local in LocalPos::all() {
+                assert_eq!(
+                    generator.ground(seed, position.tile(local).unwrap()),
+                    chunk.tile(local, Layer::Ground)
+                );
+            }
+        }
+
+        let first = generator.noise(5);
+        assert!(Arc::ptr_eq(&first, &generator.noise(5)));
+        assert!(!Arc::ptr_eq(&first, &generator.noise(6)));
+    }
+
+    #[test]
+    fn every_default_biome_is_generated() {
+        let generator = BiomeGenerator::default();
+
+        let mut found = Vec::new();
+
+        for y in (-60..60).step_by(6) {
+            for x in (-60..60).step_by(6) {
+                let chunk = generator.generate(3, ChunkPos::new(x, y));
+
+                for tile in chunk.layer(Layer::Ground).iter().flatten() {
+                    if !found.contains(&tile.ty) {
+                        found.push(tile.ty);
+                    }
+                }
+            }
+        }
+
+        for (biome, tile) in generator.config.biomes.iter().zip(&generator.tiles) {
+            assert!(found.contains(tile), "{} is never generated", biome.name);
+        }
+    }
+
+    #[test]
+    fn the_sea_is_flat() {
+        let generator = BiomeGenerator::default();
+        let tiles = TileRegistry::default();
+
+        let water = [
+            tiles.resolve("water").unwrap(),
+            tiles.resolve("deep_water").unwrap(),
+        ];
+
+        let mut highest = 0;
+
+        for y in (-30..30).step_by(5) {
+            for x in (-30..30).step_by(5) {
+                let chunk = generator.generate(3, ChunkPos::new(x, y));
+
+                for local in LocalPos::all() {
+                    let tile = chunk.tile(local, Layer::Ground).unwrap();
+
+                    if water.contains(&tile.ty) {
+                        assert_eq!(chunk.height(local), 0);
+                    }
+
+                    highest = highest.max(chunk.height(local));
+                }
+            }
+        }
+
+        assert!(highest > 3);
+        assert_eq!(height(1.0, -0.15, 40.0), MAX_HEIGHT);
+    }
+
+    #[test]
+    fn unknown_tiles_are_rejected() {
+        let config = BiomeConfig {
+            fallback: "lava".to_string(),
+            ..BiomeConfig::default()
+        };
+
+        assert_eq!(
+            BiomeGenerator::new(config, &TileRegistry::default()).unwrap_err(),
+            TileRegistryError::UnknownTile("lava".to_string())
+        );
+    }
+
+    #[test]
+    fn jitter_is_in_range() {
+        for x in -50..50 {
+            let value = jitter(9, TilePos::new(x, x * 7), 1);
+
+            assert!((-1.0..=1.0).contains(&value));
+        }
+    }
+}
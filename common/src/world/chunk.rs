@@ -1,27 +1,138 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::Position;
+use serde::{Deserialize, Serialize};
 
-use super::{Tile, TileType};
+use super::{ChunkPos, Layer, LocalPos, PalettedGrid, Tile, TileEntity, TileRegistry};
 
 pub const CHUNK_SIZE: usize = 12;
 
+/// The highest a tile can be raised, heights start at 0 for the sea.
+pub const MAX_HEIGHT: u8 = 16;
+
+/// What the raise and lower tools do to a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeightChange {
+    Raise,
+    Lower,
+}
+
+impl HeightChange {
+    /// The height after the change, kept between 0 and `MAX_HEIGHT`.
+    pub fn apply(self, height: u8) -> u8 {
+        match self {
+            HeightChange::Raise => height.saturating_add(1).min(MAX_HEIGHT),
+            HeightChange::Lower => height.saturating_sub(1),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
-    pub position: Position,
+    pub position: ChunkPos,
+
+    /// A grid of tiles per layer, by `Layer::index`, `None` where a layer is empty.
+    pub layers: [PalettedGrid<Option<Tile>>; Layer::COUNT],
 
-    pub tiles: ndarray::Array2<Tile>,
+    /// How high each tile is, all layers of a tile sit on its height.
+    pub heights: PalettedGrid<u8>,
+
+    /// The entities of tiles that have one, at most one per position whatever the layer.
+    pub entities: BTreeMap<LocalPos, TileEntity>,
 }
 
 impl Chunk {
-    pub fn new(position: Position) -> Self {
-        let tiles = ndarray::Array2::from_elem(
-            (CHUNK_SIZE, CHUNK_SIZE),
-            Tile {
-                ty: TileType::Grass,
-            },
-        );
-
-        Self { position, tiles }
+    /// A chunk with every layer empty and every tile at height 0.
+    pub fn new(position: ChunkPos) -> Self {
+        Self {
+            position,
+            layers: std::array::from_fn(|_| PalettedGrid::new(None)),
+            heights: PalettedGrid::new(0),
+            entities: BTreeMap::new(),
+        }
+    }
+
+    pub fn layer(&self, layer: Layer) -> &PalettedGrid<Option<Tile>> {
+        &self.layers[layer.index()]
+    }
+
+    pub fn tile(&self, local: LocalPos, layer: Layer) -> Option<Tile> {
+        self.layers[layer.index()].get(local.index())
+    }
+
+    /// Replaces the tile in a layer and returns the old one.
+    pub fn set_tile(&mut self, local: LocalPos, layer: Layer, tile: Option<Tile>) -> Option<Tile> {
+        self.layers[layer.index()].set(local.index(), tile)
+    }
+
+    /// The highest layer with a tile at `local`.
+    pub fn top(&self, local: LocalPos) -> Option<(Layer, Tile)> {
+        Layer::ALL
+            .into_iter()
+            .rev()
+            .find_map(|layer| Some((layer, self.tile(local, layer)?)))
+    }
+
+    pub fn height(&self, local: LocalPos) -> u8 {
+        self.heights.get(local.index())
+    }
+
+    /// Sets the height of a tile, at most `MAX_HEIGHT`.
+    pub fn set_height(&mut self, local: LocalPos, height: u8) {
+        self.heights.set(local.index(), height.min(MAX_HEIGHT));
+    }
+
+    pub fn entity(&self, local: LocalPos) -> Option<&TileEntity> {
+        self.entities.get(&local)
+    }
+
+    pub fn entity_mut(&mut self, local: LocalPos) -> Option<&mut TileEntity> {
+        self.entities.get_mut(&local)
+    }
+
+    /// Whether `entity` can replace the entity at `local` without the entities of the
+    /// chunk going over `TileEntity::MAX_CHUNK_SIZE`.
+    pub fn has_room_for(&self, local: LocalPos, entity: &TileEntity) -> bool {
+        let others = self
+            .entities
+            .iter()
+            .filter(|(other, _)| **other != local)
+            .map(|(_, other)| other.encoded_size())
+            .sum::<usize>();
+
+        others + entity.encoded_size() <= TileEntity::MAX_CHUNK_SIZE
+    }
+
+    /// Gives the highest tile at `local` that has an entity in `tiles` a new one, or
+    /// removes the entity if no tile there has one. An entity of the right kind is kept,
+    /// so changing a layer below a sign keeps its text.
+    pub fn update_entity(&mut self, local: LocalPos, tiles: &TileRegistry) {
+        let wanted = Layer::ALL
+            .into_iter()
+            .rev()
+            .filter_map(|layer| self.tile(local, layer))
+            .find_map(|tile| tiles.entity_of(tile.ty));
+
+        match wanted {
+            Some(wanted) => {
+                if self.entity(local).map(TileEntity::kind) != Some(wanted.kind()) {
+                    self.entities.insert(local, wanted.clone());
+                }
+            }
+            None => {
+                self.entities.remove(&local);
+            }
+        }
+    }
+
+    /// Runs `update_entity` on every tile, for after many of them changed at once.
+    pub fn update_entities(&mut self, tiles: &TileRegistry) {
+        for local in LocalPos::all() {
+            self.update_entity(local, tiles);
+        }
+    }
+
+    /// Sets every tile of a layer.
+    pub fn fill(&mut self, layer: Layer, tile: Option<Tile>) {
+        self.layers[layer.index()].fill(tile);
     }
 }
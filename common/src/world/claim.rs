@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use super::TilePos;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClaimOwner {
+    Player(String),
+    /// Everyone with this server role or a higher one, used for protected areas like spawn.
+    Group(String),
+}
+
+/// A rectangle of tiles that only its owner and members may edit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claim {
+    pub name: String,
+    pub owner: ClaimOwner,
+    /// Usernames that may edit besides the owner.
+    pub members: Vec<String>,
+
+    /// Inclusive corners, `min` is never greater than `max` on either axis.
+    pub min: TilePos,
+    pub max: TilePos,
+}
+
+impl Claim {
+    pub fn new(name: String, owner: ClaimOwner, a: TilePos, b: TilePos) -> Self {
+        Self {
+            name,
+            owner,
+            members: Vec::new(),
+
+            min: TilePos::new(a.x.min(b.x), a.y.min(b.y)),
+            max: TilePos::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    pub fn contains(&self, position: TilePos) -> bool {
+        (self.min.x..=self.max.x).contains(&position.x)
+            && (self.min.y..=self.max.y).contains(&position.y)
+    }
+
+    pub fn overlaps(&self, other: &Claim) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    /// How many tiles the claim covers, `u64::MAX` for the whole world which is one more.
+    pub fn area(&self) -> u64 {
+        let width = (self.max.x as i64 - self.min.x as i64 + 1) as u64;
+        let height = (self.max.y as i64 - self.min.y as i64 + 1) as u64;
+
+        width.saturating_mul(height)
+    }
+
+    /// Whether `username` is the owning player or a member, groups are resolved by the server.
+    pub fn is_member(&self, username: &str) -> bool {
+        let owner = match &self.owner {
+            ClaimOwner::Player(owner) => owner.eq_ignore_ascii_case(username),
+            ClaimOwner::Group(_) => false,
+        };
+
+        owner
+            || self
+                .members
+                .iter()
+                .any(|member| member.eq_ignore_ascii_case(username))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(a: (i32, i32), b: (i32, i32)) -> Claim {
+        Claim::new(
+            "test".to_string(),
+            ClaimOwner::Player("alice".to_string()),
+            TilePos::new(a.0, a.1),
+            TilePos::new(b.0, b.1),
+        )
+    }
+
+    #[test]
+    fn corners_are_inclusive() {
+        // corners in any order
+        let claim = claim((2, -1), (-3, -4));
+        assert_eq!(claim.min, TilePos::new(-3, -4));
+        assert_eq!(claim.max, TilePos::new(2, -1));
+
+        assert!(claim.contains(TilePos::new(-3, -4)));
+        assert!(claim.contains(TilePos::new(2, -1)));
+        assert!(claim.contains(TilePos::new(0, -2)));
+        assert!(!claim.contains(TilePos::new(-4, -2)));
+        assert!(!claim.contains(TilePos::new(0, 0)));
+        assert!(!claim.contains(TilePos::new(3, -1)));
+
+        assert_eq!(claim.area(), 6 * 4);
+        assert_eq!(self::claim((5, 5), (5, 5)).area(), 1);
+        // would wrap around to 0 and pass any limit
+        assert_eq!(
+            self::claim((i32::MIN, i32::MIN), (i32::MAX, i32::MAX)).area(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn claims_overlap_when_they_share_a_tile() {
+        let a = claim((-10, -10), (-1, -1));
+
+        assert!(a.overlaps(&claim((-1, -1), (5, 5))));
+        assert!(a.overlaps(&claim((-5, -20), (-4, 20))));
+        assert!(a.overlaps(&claim((-8, -8), (-7, -7))));
+        assert!(claim((-8, -8), (-7, -7)).overlaps(&a));
+
+        assert!(!a.overlaps(&claim((0, -10), (5, -1))));
+        assert!(!a.overlaps(&claim((-10, 0), (-1, 5))));
+        assert!(!a.overlaps(&claim((0, 0), (0, 0))));
+    }
+}
@@ -0,0 +1,599 @@
+//! Operations that change many tiles at once, each returns what it changed so it can be
+//! recorded and undone, and only the chunks it changed have to be sent again.
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ChunkPos, Layer, Structure, Tile, TileEntity, TilePos, TileRegistry, World, WorldGenerator,
+};
+
+/// What an edit did to a tile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Change {
+    /// `None` where the layer was or became empty.
+    Tile {
+        layer: Layer,
+        old: Option<Tile>,
+        new: Option<Tile>,
+    },
+    Height {
+        old: u8,
+        new: u8,
+    },
+    /// `None` where the tile had or was left without an entity.
+    Entity {
+        old: Option<TileEntity>,
+        new: Option<TileEntity>,
+    },
+}
+
+impl Change {
+    /// The change that undoes this one.
+    pub fn reversed(&self) -> Change {
+        match self {
+            Change::Tile { layer, old, new } => Change::Tile {
+                layer: *layer,
+                old: *new,
+                new: *old,
+            },
+            Change::Height { old, new } => Change::Height {
+                old: *new,
+                new: *old,
+            },
+            Change::Entity { old, new } => Change::Entity {
+                old: new.clone(),
+                new: old.clone(),
+            },
+        }
+    }
+
+    /// Whether the tile at `position` is as this change left it, false if its chunk has
+    /// not been generated.
+    pub fn is_current(&self, world: &World, position: TilePos) -> bool {
+        if world.chunk(position.chunk()).is_none() {
+            return false;
+        }
+
+        match self {
+            Change::Tile { layer, new, .. } => world.tile(position, *layer) == *new,
+            Change::Height { new, .. } => world.height(position) == Some(*new),
+            Change::Entity { new, .. } => world.entity(position) == new.as_ref(),
+        }
+    }
+
+    /// Makes this change to the tile at `position` if its chunk has been generated, a
+    /// changed tile gets the entity it wants.
+    pub fn apply(&self, world: &mut World, position: TilePos, tiles: &TileRegistry) {
+        match self {
+            Change::Tile { layer, new, .. } => {
+                world.set_tile(position, *layer, *new);
+                world.update_entity(position, tiles);
+            }
+            Change::Height { new, .. } => {
+                world.set_height(position, *new);
+            }
+            Change::Entity { new, .. } => {
+                world.set_entity(position, new.clone());
+            }
+        }
+    }
+}
+
+/// The chunks the tiles in `changes` are in.
+pub fn changed_chunks(changes: &[(TilePos, Change)]) -> BTreeSet<ChunkPos> {
+    changes
+        .iter()
+        .map(|(position, _)| position.chunk())
+        .collect()
+}
+
+/// An inclusive rectangle of tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    /// Never greater than `max` on either axis.
+    pub min: TilePos,
+    pub max: TilePos,
+}
+
+impl Region {
+    /// The region with `a` and `b` as opposite corners, in any order.
+    pub fn new(a: TilePos, b: TilePos) -> Self {
+        Self {
+            min: TilePos::new(a.x.min(b.x), a.y.min(b.y)),
+            max: TilePos::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    pub fn contains(&self, position: TilePos) -> bool {
+        (self.min.x..=self.max.x).contains(&position.x)
+            && (self.min.y..=self.max.y).contains(&position.y)
+    }
+
+    pub fn width(&self) -> u64 {
+        (self.max.x as i64 - self.min.x as i64 + 1) as u64
+    }
+
+    pub fn height(&self) -> u64 {
+        (self.max.y as i64 - self.min.y as i64 + 1) as u64
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width() * self.height()
+    }
+
+    /// Every tile, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = TilePos> {
+        let (min, max) = (self.min, self.max);
+
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| TilePos::new(x, y)))
+    }
+
+    /// Every chunk with at least one tile in the region.
+    pub fn chunks(&self) -> Vec<ChunkPos> {
+        let (min, max) = (self.min.chunk(), self.max.chunk());
+
+        (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| ChunkPos::new(x, y)))
+            .collect()
+    }
+}
+
+impl World {
+    /// Sets a tile, generating its chunk if needed, and remembers the change if the tile
+    /// changed.
+    fn edit(
+        &mut self,
+        position: TilePos,
+        layer: Layer,
+        tile: Option<Tile>,
+        generator: &dyn WorldGenerator,
+        changes: &mut Vec<(TilePos, Change)>,
+    ) {
+        let old = self.load_and_set_tile(position, layer, tile, generator);
+
+        if old != tile {
+            changes.push((
+                position,
+                Change::Tile {
+                    layer,
+                    old,
+                    new: tile,
+                },
+            ));
+        }
+    }
+
+    /// Sets every tile of a layer in `region`.
+    pub fn fill(
+        &mut self,
+        region: Region,
+        layer: Layer,
+        tile: Option<Tile>,
+        generator: &dyn WorldGenerator,
+    ) -> Vec<(TilePos, Change)> {
+        self.load_chunks(&region.chunks(), generator);
+
+        let mut changes = Vec::new();
+
+        for position in region.positions() {
+            self.edit(position, layer, tile, generator, &mut changes);
+        }
+
+        changes
+    }
+
+    /// Changes every `from` in a layer of `region` into `to`.
+    pub fn replace(
+        &mut self,
+        region: Region,
+        layer: Layer,
+        from: Option<Tile>,
+        to: Option<Tile>,
+        generator: &dyn WorldGenerator,
+    ) -> Vec<(TilePos, Change)> {
+        self.load_chunks(&region.chunks(), generator);
+
+        let mut changes = Vec::new();
+
+        for position in region.positions() {
+            if self.tile(position, layer) == from {
+                self.edit(position, layer, to, generator, &mut changes);
+            }
+        }
+
+        changes
+    }
+
+    /// Changes the tiles connected to `start` that are the same as it into `tile`, going
+    /// up, down, left and right. Stops after `limit` tiles since worlds have no edges.
+    pub fn flood_fill(
+        &mut self,
+        start: TilePos,
+        layer: Layer,
+        tile: Option<Tile>,
+        limit: usize,
+        generator: &dyn WorldGenerator,
+    ) -> Vec<(TilePos, Change)> {
+        let mut changes = Vec::new();
+
+        let target = self.load_tile(start, layer, generator);
+
+        if target == tile {
+            return changes;
+        }
+
+        let mut queue = VecDeque::from([start]);
+        let mut seen = HashSet::from([start]);
+        let mut filled = 0;
+
+        while let Some(position) = queue.pop_front() {
+            if filled == limit {
+                break;
+            }
+
+            if self.load_tile(position, layer, generator) != target {
+                continue;
+            }
+
+            self.edit(position, layer, tile, generator, &mut changes);
+            filled += 1;
+
+            for neighbour in position.neighbours() {
+                if seen.insert(neighbour) {
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// Sets the tiles on a straight line from `from` to `to`, both ends included.
+    pub fn line(
+        &mut self,
+        from: TilePos,
+        to: TilePos,
+        layer: Layer,
+        tile: Option<Tile>,
+        generator: &dyn WorldGenerator,
+    ) -> Vec<(TilePos, Change)> {
+        let mut changes = Vec::new();
+
+        for position in line(from, to) {
+            self.edit(position, layer, tile, generator, &mut changes);
+        }
+
+        changes
+    }
+
+    /// Sets the tiles of a circle around `center`, only its outline unless `filled`.
+    pub fn circle(
+        &mut self,
+        center: TilePos,
+        radius: u32,
+        layer: Layer,
+        tile: Option<Tile>,
+        filled: bool,
+        generator: &dyn WorldGenerator,
+    ) -> Vec<(TilePos, Change)> {
+        let mut changes = Vec::new();
+
+        for position in circle(center, radius, filled) {
+            self.edit(position, layer, tile, generator, &mut changes);
+        }
+
+        changes
+    }
+
+    /// Copies the tiles and entities of every layer in `region`.
+    pub fn copy(&mut self, region: Region, generator: &dyn WorldGenerator) -> Structure {
+        self.load_chunks(&region.chunks(), generator);
+
+        let mut structure = Structure::new(region.width() as u32, region.height() as u32);
+
+        for (x, y) in structure.offsets().collect::<Vec<_>>() {
+            let position = TilePos::new(
+                (region.min.x as i64 + x as i64) as i32,
+                (region.min.y as i64 + y as i64) as i32,
+            );
+
+            for layer in Layer::ALL {
+                structure.set_tile(x, y, layer, self.tile(position, layer));
+            }
+
+            if let Some(entity) = self.entity(position) {
+                structure.set_entity(x, y, entity.clone());
+            }
+        }
+
+        structure
+    }
+
+    /// Pastes `structure` with its top left corner at `origin`, leaving out the parts
+    /// that would be outside the world and the layers the structure leaves alone.
+    ///
+    /// Entities are pasted as they are, `Chunk::update_entities` drops the ones that
+    /// don't match their tiles and adds the missing ones. Entities a chunk has no room
+    /// for are left out.
+    pub fn paste(
+        &mut self,
+        structure: &Structure,
+        origin: TilePos,
+        generator: &dyn WorldGenerator,
+    ) -> Vec<(TilePos, Change)> {
+        let mut changes = Vec::new();
+
+        for (x, y) in structure.offsets() {
+            let position = match (i32::try_from(x), i32::try_from(y)) {
+                (Ok(x), Ok(y)) => origin.offset(x, y),
+                _ => None,
+            };
+
+            let Some(position) = position else {
+                continue;
+            };
+
+            for layer in Layer::ALL
+                .into_iter()
+                .filter(|layer| structure.has_layer(*layer))
+            {
+                self.edit(
+                    position,
+                    layer,
+                    structure.tile(x, y, layer),
+                    generator,
+                    &mut changes,
+                );
+            }
+
+            if let Some(entity) = structure.entity(x, y) {
+                let chunk = self.load_chunk(position.chunk(), generator);
+
+                if chunk.entity(position.local()) != Some(entity)
+                    && chunk.has_room_for(position.local(), entity)
+                {
+                    let old = chunk.entities.insert(position.local(), entity.clone());
+
+                    changes.push((
+                        position,
+                        Change::Entity {
+                            old,
+                            new: Some(entity.clone()),
+                        },
+                    ));
+                }
+            }
+        }
+
+        changes
+    }
+}
+
+/// The tiles on a line from `from` to `to` with Bresenham's algorithm.
+fn line(from: TilePos, to: TilePos) -> Vec<TilePos> {
+    let (x1, y1) = (to.x as i64, to.y as i64);
+    let (mut x, mut y) = (from.x as i64, from.y as i64);
+
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let step_x = if x < x1 { 1 } else { -1 };
+    let step_y = if y < y1 { 1 } else { -1 };
+
+    let mut error = dx + dy;
+    let mut positions = Vec::new();
+
+    loop {
+        // both ends are valid positions and every step lies between them
+        positions.push(TilePos::new(x as i32, y as i32));
+
+        if x == x1 && y == y1 {
+            return positions;
+        }
+
+        if 2 * error >= dy {
+            error += dy;
+            x += step_x;
+        }
+
+        if 2 * error <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// The tiles whose centre is within `radius` of the centre of `center`, the outline is
+/// the ones next to a tile outside the circle.
+fn circle(center: TilePos, radius: u32, filled: bool) -> Vec<TilePos> {
+    let radius = radius as i64;
+    // a little more than the radius, so the outline has no single tiles sticking out
+    let inside = |dx: i64, dy: i64| dx * dx + dy * dy <= radius * radius + radius;
+
+    let mut positions = Vec::new();
+
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if !inside(dx, dy) {
+                continue;
+            }
+
+            let edge = [(0, -1), (1, 0), (0, 1), (-1, 0)]
+                .into_iter()
+                .any(|(x, y)| !inside(dx + x, dy + y));
+
+            if !filled && !edge {
+                continue;
+            }
+
+            let position = match (i32::try_from(dx), i32::try_from(dy)) {
+                (Ok(dx), Ok(dy)) => center.offset(dx, dy),
+                _ => None,
+            };
+
+            positions.extend(position);
+        }
+    }
+
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{FlatGenerator, TileId};
+
+    fn tile(id: u16) -> Option<Tile> {
+        Some(Tile { ty: TileId(id) })
+    }
+
+    fn world() -> (World, FlatGenerator) {
+        (World::new(3), FlatGenerator { tile: TileId(0) })
+    }
+
+    #[test]
+    fn fill_and_replace_report_changed_chunks() {
+        let (mut world, generator) = world();
+        let region = Region::new(TilePos::new(10, 5), TilePos::new(-3, 0));
+
+        assert_eq!(region.area(), 14 * 6);
+
+        let changes = world.fill(region, Layer::Ground, tile(1), &generator);
+
+        assert_eq!(changes.len(), 14 * 6);
+        assert_eq!(
+            changes[0],
+            (
+                TilePos::new(-3, 0),
+                Change::Tile {
+                    layer: Layer::Ground,
+                    old: tile(0),
+                    new: tile(1)
+                }
+            )
+        );
+        assert_eq!(
+            changed_chunks(&changes),
+            BTreeSet::from([ChunkPos::new(-1, 0), ChunkPos::new(0, 0)])
+        );
+        assert!(region
+            .positions()
+            .all(|position| world.tile(position, Layer::Ground) == tile(1)));
+
+        // filling again changes nothing
+        assert!(world
+            .fill(region, Layer::Ground, tile(1), &generator)
+            .is_empty());
+
+        let small = Region::new(TilePos::new(0, 0), TilePos::new(11, 11));
+        let changes = world.replace(small, Layer::Ground, tile(1), tile(2), &generator);
+
+        assert_eq!(changes.len(), 11 * 6);
+        assert_eq!(
+            changed_chunks(&changes),
+            BTreeSet::from([ChunkPos::new(0, 0)])
+        );
+        assert_eq!(world.tile(TilePos::new(10, 5), Layer::Ground), tile(2));
+        assert_eq!(world.tile(TilePos::new(-1, 5), Layer::Ground), tile(1));
+        assert_eq!(world.tile(TilePos::new(11, 5), Layer::Ground), tile(0));
+    }
+
+    #[test]
+    fn flood_fill_stays_inside_walls() {
+        let (mut world, generator) = world();
+
+        // a 5 by 5 room of walls with the inside 3 by 3 open
+        let walls = Region::new(TilePos::new(-2, -2), TilePos::new(2, 2));
+        world.fill(walls, Layer::Object, tile(9), &generator);
+        world.fill(
+            Region::new(TilePos::new(-1, -1), TilePos::new(1, 1)),
+            Layer::Object,
+            None,
+            &generator,
+        );
+
+        world.flood_fill(TilePos::new(0, 0), Layer::Object, tile(4), 1000, &generator);
+
+        let filled = walls
+            .positions()
+            .filter(|position| world.tile(*position, Layer::Object) == tile(4))
+            .count();
+
+        assert_eq!(filled, 9);
+        assert_eq!(world.tile(TilePos::new(3, 0), Layer::Object), None);
+
+        // outside the room the flood only stops at the limit
+        world.flood_fill(TilePos::new(3, 0), Layer::Object, tile(5), 50, &generator);
+
+        let count = world
+            .chunks
+            .values()
+            .flat_map(|chunk| chunk.layer(Layer::Object).iter())
+            .filter(|found| *found == tile(5))
+            .count();
+
+        assert_eq!(count, 50);
+    }
+
+    #[test]
+    fn lines_and_circles_are_connected() {
+        let points = line(TilePos::new(0, 0), TilePos::new(7, -3));
+
+        assert_eq!(points.first(), Some(&TilePos::new(0, 0)));
+        assert_eq!(points.last(), Some(&TilePos::new(7, -3)));
+        assert_eq!(points.len(), 8);
+
+        for pair in points.windows(2) {
+            assert!((pair[0].x - pair[1].x).abs() <= 1 && (pair[0].y - pair[1].y).abs() <= 1);
+        }
+
+        let outline = circle(TilePos::new(0, 0), 4, false);
+        let disc = circle(TilePos::new(0, 0), 4, true);
+
+        assert!(outline.iter().all(|position| disc.contains(position)));
+        assert!(outline.contains(&TilePos::new(4, 0)));
+        assert!(!outline.contains(&TilePos::new(0, 0)));
+        assert!(disc.contains(&TilePos::new(0, 0)));
+        assert_eq!(circle(TilePos::new(i32::MAX, 0), 1, true).len(), 6);
+    }
+
+    #[test]
+    fn pasting_a_copy_reproduces_it() {
+        let (mut world, generator) = world();
+        let region = Region::new(TilePos::new(0, 0), TilePos::new(3, 2));
+
+        world.fill(region, Layer::Object, tile(9), &generator);
+        world.set_tile(TilePos::new(1, 1), Layer::Object, tile(10));
+        world
+            .chunks
+            .get_mut(&ChunkPos::new(0, 0))
+            .unwrap()
+            .entities
+            .insert(
+                TilePos::new(1, 1).local(),
+                crate::world::TileEntity::Sign {
+                    text: "Hi".to_string(),
+                },
+            );
+
+        let structure = world.copy(region, &generator);
+
+        assert_eq!((structure.width(), structure.height()), (4, 3));
+
+        let origin = TilePos::new(20, -1);
+        let changes = world.paste(&structure, origin, &generator);
+
+        assert_eq!(
+            changed_chunks(&changes),
+            BTreeSet::from([ChunkPos::new(1, -1), ChunkPos::new(1, 0)])
+        );
+
+        let pasted = Region::new(origin, origin.offset(3, 2).unwrap());
+
+        assert_eq!(world.copy(pasted, &generator), structure);
+
+        // pasting it again changes nothing
+        assert!(world.paste(&structure, origin, &generator).is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+/// State a tile keeps beyond its type, stored by its chunk and sent along with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileEntity {
+    Sign {
+        text: String,
+    },
+    /// Holds a list of things, what they are is up to the players for now.
+    Container {
+        items: Vec<String>,
+    },
+    Door {
+        open: bool,
+    },
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TileEntityError {
+    #[error("there is nothing to edit there")]
+    Missing,
+    #[error("a {0} can't be turned into a {1}")]
+    WrongKind(&'static str, &'static str),
+    #[error("the text is longer than {} characters", TileEntity::MAX_TEXT_LENGTH)]
+    TextTooLong,
+    #[error("there are more than {} items", TileEntity::MAX_ITEMS)]
+    TooManyItems,
+    #[error("the chunk holds too much text already")]
+    ChunkFull,
+}
+
+impl TileEntity {
+    /// The longest text of a sign or item, in characters.
+    pub const MAX_TEXT_LENGTH: usize = 200;
+    pub const MAX_ITEMS: usize = 16;
+    /// The most bytes the entities of a chunk may take encoded, so a chunk always fits
+    /// in a single packet with room to spare for its tiles. The entities tiles get on
+    /// their own are tiny and are not held to it.
+    pub const MAX_CHUNK_SIZE: usize = 32 * 1024;
+
+    /// How many bytes the entity takes when it is sent.
+    pub fn encoded_size(&self) -> usize {
+        bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .map(|encoded| encoded.len())
+            .unwrap_or(usize::MAX)
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TileEntity::Sign { .. } => "sign",
+            TileEntity::Container { .. } => "container",
+            TileEntity::Door { .. } => "door",
+        }
+    }
+
+    /// Checks that `edited` can replace this entity, players can change what an entity
+    /// holds but not what it is.
+    pub fn check_edit(&self, edited: &TileEntity) -> Result<(), TileEntityError> {
+        if self.kind() != edited.kind() {
+            return Err(TileEntityError::WrongKind(self.kind(), edited.kind()));
+        }
+
+        let too_long = |text: &String| text.chars().count() > Self::MAX_TEXT_LENGTH;
+
+        match edited {
+            TileEntity::Sign { text } if too_long(text) => Err(TileEntityError::TextTooLong),
+            TileEntity::Container { items } if items.len() > Self::MAX_ITEMS => {
+                Err(TileEntityError::TooManyItems)
+            }
+            TileEntity::Container { items } if items.iter().any(too_long) => {
+                Err(TileEntityError::TextTooLong)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Chunk, ChunkPos, LocalPos, Tile, TileId, CHUNK_SIZE};
+
+    #[test]
+    fn edits_keep_the_kind() {
+        let sign = TileEntity::Sign {
+            text: String::new(),
+        };
+
+        assert_eq!(
+            sign.check_edit(&TileEntity::Sign {
+                text: "Welcome".to_string()
+            }),
+            Ok(())
+        );
+        assert_eq!(
+            sign.check_edit(&TileEntity::Door { open: true }),
+            Err(TileEntityError::WrongKind("sign", "door"))
+        );
+        assert_eq!(
+            sign.check_edit(&TileEntity::Sign {
+                text: "a".repeat(TileEntity::MAX_TEXT_LENGTH + 1)
+            }),
+            Err(TileEntityError::TextTooLong)
+        );
+    }
+
+    #[test]
+    fn a_full_chunk_fits_a_packet() {
+        let largest = TileEntity::Container {
+            items: vec!["\u{1F4E6}".repeat(TileEntity::MAX_TEXT_LENGTH); TileEntity::MAX_ITEMS],
+        };
+        assert_eq!(largest.check_edit(&largest), Ok(()));
+
+        // every tile different so palettes are as large as they get
+        let mut chunk = Chunk::new(ChunkPos { x: 0, y: 0 });
+        for index in 0..CHUNK_SIZE * CHUNK_SIZE {
+            for (layer, grid) in chunk.layers.iter_mut().enumerate() {
+                let ty = TileId((layer * CHUNK_SIZE * CHUNK_SIZE + index) as u16);
+                grid.set(index, Some(Tile { ty }));
+            }
+            chunk.heights.set(index, index as u8);
+        }
+
+        let mut full = None;
+        for local in LocalPos::all() {
+            if !chunk.has_room_for(local, &largest) {
+                full = Some(local);
+                break;
+            }
+            chunk.entities.insert(local, largest.clone());
+        }
+        assert!(
+            full.is_some(),
+            "a chunk of the largest entities isn't held to the budget"
+        );
+
+        let encoded = bincode::serde::encode_to_vec(&chunk, bincode::config::standard()).unwrap();
+        // one byte for the packet type
+        assert!(encoded.len() < crate::MAX_DATAGRAM_SIZE);
+    }
+}
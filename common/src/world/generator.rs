@@ -0,0 +1,312 @@
+//! Generators turn a seed and a chunk position into a chunk, chunks never depend on
+//! each other so they can be generated in any order and on any thread.
+
+use std::fmt;
+
+use super::{
+    height, BiomeConfig, BiomeGenerator, Chunk, ChunkPos, Fractal, Layer, LocalPos, NoiseLayer,
+    SeedCache, Tile, TileId, TilePos, TileRegistry, TileRegistryError,
+};
+
+/// The names `generator_from_name` knows.
+pub const GENERATORS: [&str; 4] = ["biomes", "flat", "islands", "checkerboard"];
+
+pub trait WorldGenerator: fmt::Debug + Send + Sync {
+    /// The same seed and position must always give the same chunk.
+    fn generate(&self, seed: u32, position: ChunkPos) -> Chunk;
+
+    /// The ground tile `generate` gives a position, generators that can work out a
+    /// single tile faster than a whole chunk should.
+    fn ground(&self, seed: u32, position: TilePos) -> Option<Tile> {
+        self.generate(seed, position.chunk())
+            .tile(position.local(), Layer::Ground)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum GeneratorError {
+    #[error("there is no generator {0}, expected one of {}", GENERATORS.join(", "))]
+    UnknownGenerator(String),
+    #[error(transparent)]
+    Tiles(#[from] TileRegistryError),
+}
+
+/// The generator called `name`, biomes are generated from `biomes`. Fails if the
+/// generator needs a tile `tiles` does not have.
+pub fn generator_from_name(
+    name: &str,
+    biomes: &BiomeConfig,
+    tiles: &TileRegistry,
+) -> Result<Box<dyn WorldGenerator>, GeneratorError> {
+    let generator: Box<dyn WorldGenerator> = match name {
+        "biomes" => Box::new(BiomeGenerator::new(biomes.clone(), tiles)?),
+        "flat" => Box::new(FlatGenerator {
+            tile: tiles.resolve("grass")?,
+        }),
+        "islands" => Box::new(IslandsGenerator::new(tiles)?),
+        "checkerboard" => Box::new(CheckerboardGenerator::new(tiles)?),
+        _ => return Err(GeneratorError::UnknownGenerator(name.to_string())),
+    };
+
+    Ok(generator)
+}
+
+/// Every tile is the same, e.g. for a lobby.
+#[derive(Debug, Clone, Copy)]
+pub struct FlatGenerator {
+    pub tile: TileId,
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, _seed: u32, position: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(position);
+
+        chunk.fill(Layer::Ground, Some(Tile { ty: self.tile }));
+
+        chunk
+    }
+
+    fn ground(&self, _seed: u32, _position: TilePos) -> Option<Tile> {
+        Some(Tile { ty: self.tile })
+    }
+}
+
+/// Small islands in an endless sea.
+#[derive(Debug, Clone)]
+pub struct IslandsGenerator {
+    /// From the lowest to the highest.
+    tiles: [TileId; 5],
+    elevation: SeedCache<Fractal>,
+}
+
+impl IslandsGenerator {
+    const ELEVATION: NoiseLayer = NoiseLayer {
+        scale: 48.0,
+        octaves: 3,
+        persistence: 0.5,
+    };
+
+    pub fn new(tiles: &TileRegistry) -> Result<Self, TileRegistryError> {
+        Ok(Self {
+            tiles: [
+                tiles.resolve("deep_water")?,
+                tiles.resolve("water")?,
+                tiles.resolve("sand")?,
+                tiles.resolve("grass")?,
+                tiles.resolve("forest_floor")?,
+            ],
+            elevation: SeedCache::default(),
+        })
+    }
+
+    /// The tile at an elevation.
+    fn tile(&self, elevation: f64) -> TileId {
+        let level = match elevation {
+            value if value < -0.1 => 0,
+            value if value < 0.12 => 1,
+            value if value < 0.18 => 2,
+            value if value < 0.35 => 3,
+            _ => 4,
+        };
+
+        self.tiles[level]
+    }
+}
+
+impl WorldGenerator for IslandsGenerator {
+    fn generate(&self, seed: u32, position: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(position);
+
+        let elevation = self
+            .elevation
+            .get(seed, || Fractal::new(Self::ELEVATION, seed, 0));
+
+        for local in LocalPos::all() {
+            let tile = match position.tile(local) {
+                Some(tile) => tile,
+                None => continue,
+            };
+
+            let value = elevation.get(tile);
+
+            chunk.set_tile(
+                local,
+                Layer::Ground,
+                Some(Tile {
+                    ty: self.tile(value),
+                }),
+            );
+            // islands rise from the water's edge
+            chunk.set_height(local, height(value, 0.12, 8.0));
+        }
+
+        chunk
+    }
+
+    fn ground(&self, seed: u32, position: TilePos) -> Option<Tile> {
+        let elevation = self
+            .elevation
+            .get(seed, || Fractal::new(Self::ELEVATION, seed, 0));
+
+        Some(Tile {
+            ty: self.tile(elevation.get(position)),
+        })
+    }
+}
+
+/// Chunks alternate between grass and sand and the first tile of every chunk is
+/// stone, which shows where chunks start when debugging.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckerboardGenerator {
+    even: TileId,
+    odd: TileId,
+    corner: TileId,
+}
+
+impl CheckerboardGenerator {
+    pub fn new(tiles: &TileRegistry) -> Result<Self, TileRegistryError> {
+        Ok(Self {
+            even: tiles.resolve("grass")?,
+            odd: tiles.resolve("sand")?,
+            corner: tiles.resolve("stone")?,
+        })
+    }
+}
+
+impl CheckerboardGenerator {
+    fn tile(&self, position: ChunkPos) -> TileId {
+        if (position.x ^ position.y) & 1 == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+impl WorldGenerator for CheckerboardGenerator {
+    fn generate(&self, _seed: u32, position: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(position);
+
+        chunk.fill(
+            Layer::Ground,
+            Some(Tile {
+                ty: self.tile(position),
+            }),
+        );
+
+        chunk.set_tile(
+            LocalPos::ORIGIN,
+            Layer::Ground,
+            Some(Tile { ty: self.corner }),
+        );
+
+        chunk
+    }
+
+    fn ground(&self, _seed: u32, position: TilePos) -> Option<Tile> {
+        let ty = if position.local() == LocalPos::ORIGIN {
+            self.corner
+        } else {
+            self.tile(position.chunk())
+        };
+
+        Some(Tile { ty })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_name_has_a_generator() {
+        let biomes = BiomeConfig::default();
+        let tiles = TileRegistry::default();
+
+        for name in GENERATORS {
+            assert!(generator_from_name(name, &biomes, &tiles).is_ok());
+        }
+
+        assert_eq!(
+            generator_from_name("void", &biomes, &tiles).unwrap_err(),
+            GeneratorError::UnknownGenerator("void".to_string())
+        );
+    }
+
+    #[test]
+    fn ground_matches_the_generated_chunk() {
+        let biomes = BiomeConfig::default();
+        let tiles = TileRegistry::default();
+
+        for name in GENERATORS {
+            let generator = generator_from_name(name, &biomes, &tiles).unwrap();
+
+            for position in [
+                ChunkPos::new(0, 0),
+                ChunkPos::new(-3, 5),
+                ChunkPos::new(7, -2),
+            ] {
+                let chunk = generator.generate(9, position);
+
+                for local in LocalPos::all() {
+                    assert_eq!(
+                        generator.ground(9, position.tile(local).unwrap()),
+                        chunk.tile(local, Layer::Ground),
+                        "{} at {:?}",
+                        name,
+                        position.tile(local)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checkerboard_alternates() {
+        let tiles = TileRegistry::default();
+        let generator = CheckerboardGenerator::new(&tiles).unwrap();
+
+        let a = generator.generate(0, ChunkPos::new(-1, 0));
+        let b = generator.generate(0, ChunkPos::new(0, 0));
+
+        let local = LocalPos::new(1, 1).unwrap();
+
+        assert_ne!(a.tile(local, Layer::Ground), b.tile(local, Layer::Ground));
+        assert_eq!(
+            a.tile(LocalPos::ORIGIN, Layer::Ground).unwrap().ty,
+            tiles.resolve("stone").unwrap()
+        );
+        assert_eq!(a.tile(local, Layer::Object), None);
+    }
+
+    #[test]
+    fn islands_are_mostly_water() {
+        let tiles = TileRegistry::default();
+        let generator = IslandsGenerator::new(&tiles).unwrap();
+
+        let water_tiles = [
+            tiles.resolve("water").unwrap(),
+            tiles.resolve("deep_water").unwrap(),
+        ];
+
+        let mut water = 0;
+        let mut land = 0;
+
+        for y in -8..8 {
+            for x in -8..8 {
+                let chunk = generator.generate(5, ChunkPos::new(x, y));
+
+                for tile in chunk.layer(Layer::Ground).iter().flatten() {
+                    if water_tiles.contains(&tile.ty) {
+                        water += 1;
+                    } else {
+                        land += 1;
+                    }
+                }
+            }
+        }
+
+        assert!(land > 0);
+        assert!(water > land);
+    }
+}
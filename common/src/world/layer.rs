@@ -0,0 +1,53 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// The layers of a chunk from the bottom up, every tile position has a slot in each.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub enum Layer {
+    /// What everything stands on, e.g. grass or water.
+    #[default]
+    Ground,
+    /// Things on the ground, e.g. trees or walls.
+    Object,
+    /// Drawn over everything else.
+    Decoration,
+}
+
+impl Layer {
+    pub const COUNT: usize = 3;
+
+    /// From the bottom up.
+    pub const ALL: [Layer; Layer::COUNT] = [Layer::Ground, Layer::Object, Layer::Decoration];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Ground => "ground",
+            Layer::Object => "object",
+            Layer::Decoration => "decoration",
+        }
+    }
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Layer {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Layer::ALL
+            .into_iter()
+            .find(|layer| layer.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
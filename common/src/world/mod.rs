@@ -1,7 +1,31 @@
+mod biome;
 mod chunk;
+mod claim;
+mod edit;
+mod entity;
+mod generator;
+mod layer;
+mod palette;
+mod path;
+mod position;
+mod scatter;
+mod seed;
+mod structure;
 mod tile;
 mod world;
 
+pub use biome::*;
 pub use chunk::*;
+pub use claim::*;
+pub use edit::*;
+pub use entity::*;
+pub use generator::*;
+pub use layer::*;
+pub use palette::*;
+pub use path::*;
+pub use position::*;
+pub use scatter::*;
+pub use seed::*;
+pub use structure::*;
 pub use tile::*;
 pub use world::*;
@@ -0,0 +1,346 @@
+//! Chunk storage that keeps every distinct value once and refers to it with a few bits
+//! per tile, most chunks only use a handful of tiles and heights.
+
+use serde::{Deserialize, Serialize};
+
+use super::CHUNK_SIZE;
+
+/// The number of values in a grid.
+const CELLS: usize = CHUNK_SIZE * CHUNK_SIZE;
+
+/// A value for every tile of a chunk, indexed by `LocalPos::index`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    try_from = "Storage<T>",
+    into = "Storage<T>",
+    bound(
+        serialize = "T: Serialize + Copy + PartialEq",
+        deserialize = "T: Deserialize<'de> + Copy + PartialEq"
+    )
+)]
+pub struct PalettedGrid<T: Copy + PartialEq> {
+    storage: Storage<T>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Storage<T> {
+    /// Every tile has the same value, which needs no indices at all.
+    Single(T),
+    /// Each tile has a `bits` wide index into `palette`, packed into words without
+    /// crossing from one word into the next.
+    Packed {
+        palette: Vec<T>,
+        bits: u8,
+        words: Vec<u64>,
+    },
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PaletteError {
+    #[error("the palette is empty")]
+    EmptyPalette,
+    #[error("{bits} bits are wrong for a palette of {len}")]
+    WrongBits { bits: u8, len: usize },
+    #[error("expected {expected} words, found {found}")]
+    WrongLength { expected: usize, found: usize },
+    #[error("index {0} is outside the palette")]
+    IndexOutOfRange(usize),
+}
+
+/// The fewest bits that can index a palette of `len` values.
+fn bits_for(len: usize) -> u8 {
+    (usize::BITS - len.saturating_sub(1).leading_zeros()).max(1) as u8
+}
+
+fn per_word(bits: u8) -> usize {
+    64 / bits as usize
+}
+
+fn words_for(bits: u8) -> usize {
+    CELLS.div_ceil(per_word(bits))
+}
+
+fn read(words: &[u64], bits: u8, index: usize) -> usize {
+    let shift = (index % per_word(bits)) * bits as usize;
+
+    ((words[index / per_word(bits)] >> shift) & ((1 << bits) - 1)) as usize
+}
+
+fn write(words: &mut [u64], bits: u8, index: usize, value: usize) {
+    let shift = (index % per_word(bits)) * bits as usize;
+    let word = &mut words[index / per_word(bits)];
+
+    *word = (*word & !(((1 << bits) - 1) << shift)) | ((value as u64) << shift);
+}
+
+impl<T: Copy + PartialEq> PalettedGrid<T> {
+    /// A grid with every tile set to `value`.
+    pub fn new(value: T) -> Self {
+        Self {
+            storage: Storage::Single(value),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> T {
+        match &self.storage {
+            Storage::Single(value) => *value,
+            Storage::Packed {
+                palette,
+                bits,
+                words,
+            } => palette[read(words, *bits, index)],
+        }
+    }
+
+    /// Sets the value at `index` and returns the old one.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        let old = self.get(index);
+
+        if old == value {
+            return old;
+        }
+
+        if let Storage::Single(single) = self.storage {
+            self.storage = Storage::Packed {
+                palette: vec![single],
+                bits: 1,
+                words: vec![0; words_for(1)],
+            };
+        }
+
+        let slot = match self.slot(value) {
+            Some(slot) => slot,
+            None => self.add(value),
+        };
+
+        if let Storage::Packed { bits, words, .. } = &mut self.storage {
+            write(words, *bits, index, slot);
+        }
+
+        old
+    }
+
+    /// Sets every value.
+    pub fn fill(&mut self, value: T) {
+        self.storage = Storage::Single(value);
+    }
+
+    /// Every value, by index.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..CELLS).map(|index| self.get(index))
+    }
+
+    /// The distinct values of the grid, values that are no longer used may stay in it
+    /// until it fills up.
+    pub fn palette(&self) -> &[T] {
+        match &self.storage {
+            Storage::Single(value) => std::slice::from_ref(value),
+            Storage::Packed { palette, .. } => palette,
+        }
+    }
+
+    /// How many bytes the grid uses outside of itself.
+    pub fn heap_size(&self) -> usize {
+        match &self.storage {
+            Storage::Single(_) => 0,
+            Storage::Packed { palette, words, .. } => {
+                palette.capacity() * std::mem::size_of::<T>()
+                    + words.capacity() * std::mem::size_of::<u64>()
+            }
+        }
+    }
+
+    fn slot(&self, value: T) -> Option<usize> {
+        self.palette().iter().position(|other| *other == value)
+    }
+
+    /// Adds `value` to the palette of a packed grid and returns its slot, dropping
+    /// unused values or widening the indices first if the palette is full.
+    fn add(&mut self, value: T) -> usize {
+        if let Storage::Packed { palette, bits, .. } = &self.storage {
+            if palette.len() == 1 << *bits {
+                self.repack();
+            }
+        }
+
+        match &mut self.storage {
+            Storage::Single(single) => {
+                // repacking found a single used value
+                let palette = vec![*single, value];
+
+                self.storage = Storage::Packed {
+                    palette,
+                    bits: 1,
+                    words: vec![0; words_for(1)],
+                };
+
+                1
+            }
+            Storage::Packed { palette, .. } => {
+                palette.push(value);
+                palette.len() - 1
+            }
+        }
+    }
+
+    /// Rebuilds the grid with only the values still in use and room for one more.
+    fn repack(&mut self) {
+        let values = self.iter().collect::<Vec<_>>();
+
+        let mut palette = Vec::new();
+
+        for value in &values {
+            if !palette.contains(value) {
+                palette.push(*value);
+            }
+        }
+
+        if palette.len() == 1 {
+            self.storage = Storage::Single(palette[0]);
+            return;
+        }
+
+        let bits = bits_for(palette.len() + 1);
+
+        let mut words = vec![0; words_for(bits)];
+
+        for (index, value) in values.iter().enumerate() {
+            let slot = palette.iter().position(|other| other == value).unwrap();
+
+            write(&mut words, bits, index, slot);
+        }
+
+        self.storage = Storage::Packed {
+            palette,
+            bits,
+            words,
+        };
+    }
+}
+
+impl<T: Copy + PartialEq> TryFrom<Storage<T>> for PalettedGrid<T> {
+    type Error = PaletteError;
+
+    /// Checks data from disk or the network so lookups can't go out of bounds.
+    fn try_from(storage: Storage<T>) -> Result<Self, Self::Error> {
+        if let Storage::Packed {
+            palette,
+            bits,
+            words,
+        } = &storage
+        {
+            if palette.is_empty() {
+                return Err(PaletteError::EmptyPalette);
+            }
+
+            if !(bits_for(palette.len())..=8).contains(bits) {
+                return Err(PaletteError::WrongBits {
+                    bits: *bits,
+                    len: palette.len(),
+                });
+            }
+
+            if words.len() != words_for(*bits) {
+                return Err(PaletteError::WrongLength {
+                    expected: words_for(*bits),
+                    found: words.len(),
+                });
+            }
+
+            if let Some(index) = (0..CELLS)
+                .map(|index| read(words, *bits, index))
+                .find(|index| *index >= palette.len())
+            {
+                return Err(PaletteError::IndexOutOfRange(index));
+            }
+        }
+
+        Ok(Self { storage })
+    }
+}
+
+impl<T: Copy + PartialEq> From<PalettedGrid<T>> for Storage<T> {
+    fn from(grid: PalettedGrid<T>) -> Self {
+        grid.storage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_grids_need_no_indices() {
+        let mut grid = PalettedGrid::new(3u8);
+
+        grid.set(5, 3);
+
+        assert_eq!(grid.palette(), &[3]);
+        assert_eq!(grid.heap_size(), 0);
+
+        grid.set(5, 4);
+        grid.set(5, 3);
+
+        assert!(grid.iter().all(|value| value == 3));
+    }
+
+    #[test]
+    fn values_survive_growing_and_repacking() {
+        let mut grid = PalettedGrid::new(0u16);
+        let mut expected = vec![0u16; CELLS];
+
+        // enough distinct values to widen the indices a few times, then overwrite them
+        // so repacking has unused values to drop
+        for round in 0..3u16 {
+            for (index, expected) in expected.iter_mut().enumerate() {
+                let value = (index as u16 * 7 + round * 40) % 50;
+
+                grid.set(index, value);
+                *expected = value;
+
+                assert_eq!(grid.get(index), value);
+            }
+        }
+
+        assert_eq!(grid.iter().collect::<Vec<_>>(), expected);
+
+        for index in 0..CELLS {
+            grid.set(index, 9);
+        }
+        grid.set(0, 1);
+
+        assert!(grid.palette().len() <= 64);
+        assert_eq!(grid.get(0), 1);
+        assert_eq!(grid.get(1), 9);
+    }
+
+    #[test]
+    fn round_trips_through_bincode() {
+        let mut grid = PalettedGrid::new(None);
+
+        grid.set(3, Some(2u16));
+        grid.set(143, Some(7));
+
+        let encoded = bincode::serde::encode_to_vec(&grid, bincode::config::standard()).unwrap();
+        let decoded: PalettedGrid<Option<u16>> =
+            bincode::serde::decode_from_slice(&encoded, bincode::config::standard())
+                .unwrap()
+                .0;
+
+        assert!(decoded.iter().eq(grid.iter()));
+    }
+
+    #[test]
+    fn bad_indices_are_rejected() {
+        let storage = Storage::Packed {
+            palette: vec![1u8, 2, 3],
+            bits: 2,
+            words: vec![u64::MAX; words_for(2)],
+        };
+
+        assert_eq!(
+            PalettedGrid::try_from(storage).unwrap_err(),
+            PaletteError::IndexOutOfRange(3)
+        );
+    }
+}
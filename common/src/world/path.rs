@@ -0,0 +1,442 @@
+//! Walkability and A* pathfinding over the tiles of a world.
+//!
+//! A tile can be walked on if it has a ground tile and every tile on it is walkable by
+//! the tile registry, closed doors block the way even though door tiles are walkable.
+//! Steps go up, down, left and right and can climb or drop at most `MAX_STEP`.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap, HashMap},
+};
+
+use super::{
+    Chunk, ChunkPos, Layer, LocalPos, TileEntity, TilePos, TileRegistry, World, WorldGenerator,
+};
+
+/// The most a path climbs or drops between two neighbouring tiles.
+pub const MAX_STEP: u8 = 1;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    #[error("the start can't be walked on")]
+    BlockedStart,
+    #[error("the goal can't be walked on")]
+    BlockedGoal,
+    #[error("there is no way there")]
+    NoPath,
+    /// There may be a way through chunks that have not been generated yet.
+    #[error("the way there leads through chunks that have not been generated")]
+    Unloaded(Vec<ChunkPos>),
+    #[error("gave up after looking at {0} tiles")]
+    TooFar(usize),
+}
+
+/// What the search knows about a tile.
+enum Step {
+    /// It can be walked on at this height.
+    Open(u8),
+    Blocked,
+    Unloaded,
+}
+
+impl Chunk {
+    /// Whether the tile at `local` can be walked on, see the module documentation.
+    pub fn walkable(&self, local: LocalPos, tiles: &TileRegistry) -> bool {
+        if self.tile(local, Layer::Ground).is_none() {
+            return false;
+        }
+
+        let tiles_walkable = Layer::ALL
+            .into_iter()
+            .filter_map(|layer| self.tile(local, layer))
+            .all(|tile| tiles.get(tile.ty).is_some_and(|tile| tile.walkable));
+
+        tiles_walkable && !matches!(self.entity(local), Some(TileEntity::Door { open: false }))
+    }
+}
+
+impl World {
+    /// Whether a tile can be walked on, `None` if its chunk has not been generated.
+    pub fn walkable(&self, position: TilePos, tiles: &TileRegistry) -> Option<bool> {
+        Some(
+            self.chunk(position.chunk())?
+                .walkable(position.local(), tiles),
+        )
+    }
+
+    /// The shortest path from `from` to `to`, both included, looking at no more than
+    /// `limit` tiles. Chunks that have not been generated are in the way, if the search
+    /// failed because of them it says which ones it ran into.
+    pub fn find_path(
+        &self,
+        from: TilePos,
+        to: TilePos,
+        tiles: &TileRegistry,
+        limit: usize,
+    ) -> Result<Vec<TilePos>, PathError> {
+        search(from, to, limit, |position| {
+            match self.chunk(position.chunk()) {
+                Some(chunk) => step(chunk, position, tiles),
+                None => Step::Unloaded,
+            }
+        })
+    }
+
+    /// Like `find_path`, but generates the chunks the search runs into.
+    pub fn load_path(
+        &mut self,
+        from: TilePos,
+        to: TilePos,
+        tiles: &TileRegistry,
+        limit: usize,
+        generator: &dyn WorldGenerator,
+    ) -> Result<Vec<TilePos>, PathError> {
+        search(from, to, limit, |position| {
+            step(
+                self.load_chunk(position.chunk(), generator),
+                position,
+                tiles,
+            )
+        })
+    }
+}
+
+fn step(chunk: &Chunk, position: TilePos, tiles: &TileRegistry) -> Step {
+    let local = position.local();
+
+    if chunk.walkable(local, tiles) {
+        Step::Open(chunk.height(local))
+    } else {
+        Step::Blocked
+    }
+}
+
+fn distance(a: TilePos, b: TilePos) -> u64 {
+    a.x.abs_diff(b.x) as u64 + a.y.abs_diff(b.y) as u64
+}
+
+/// A* with the manhattan distance, which never overestimates on a grid without
+/// diagonal steps so the first path found is a shortest one.
+fn search(
+    from: TilePos,
+    to: TilePos,
+    limit: usize,
+    mut look: impl FnMut(TilePos) -> Step,
+) -> Result<Vec<TilePos>, PathError> {
+    let start_height = match look(from) {
+        Step::Open(height) => height,
+        Step::Blocked => return Err(PathError::BlockedStart),
+        Step::Unloaded => return Err(PathError::Unloaded(vec![from.chunk()])),
+    };
+
+    match look(to) {
+        Step::Open(_) => {}
+        Step::Blocked => return Err(PathError::BlockedGoal),
+        Step::Unloaded => return Err(PathError::Unloaded(vec![to.chunk()])),
+    }
+
+    // the tile each reached tile was reached from, its steps from the start and height
+    let mut reached = HashMap::from([(from, (from, 0u64, start_height))]);
+    // ties go to the tile closer to the goal, which keeps the search narrow
+    let mut open = BinaryHeap::from([Reverse((distance(from, to), distance(from, to), from))]);
+
+    let mut unloaded = BTreeSet::new();
+    let mut looked_at = 0;
+
+    while let Some(Reverse((_, _, position))) = open.pop() {
+        if position == to {
+            let mut path = vec![to];
+
+            while let Some(&last) = path.last() {
+                if last == from {
+                    break;
+                }
+
+                path.push(reached[&last].0);
+            }
+
+            path.reverse();
+
+            return Ok(path);
+        }
+
+        let (_, steps, height) = reached[&position];
+
+        for neighbour in position.neighbours() {
+            if reached
+                .get(&neighbour)
+                .is_some_and(|(_, known, _)| *known <= steps + 1)
+            {
+                continue;
+            }
+
+            looked_at += 1;
+
+            if looked_at > limit {
+                return Err(PathError::TooFar(limit));
+            }
+
+            let neighbour_height = match look(neighbour) {
+                Step::Open(neighbour_height) => neighbour_height,
+                Step::Blocked => continue,
+                Step::Unloaded => {
+                    unloaded.insert(neighbour.chunk());
+                    continue;
+                }
+            };
+
+            if height.abs_diff(neighbour_height) > MAX_STEP {
+                continue;
+            }
+
+            reached.insert(neighbour, (position, steps + 1, neighbour_height));
+
+            let remaining = distance(neighbour, to);
+            open.push(Reverse((steps + 1 + remaining, remaining, neighbour)));
+        }
+    }
+
+    if unloaded.is_empty() {
+        Err(PathError::NoPath)
+    } else {
+        Err(PathError::Unloaded(unloaded.into_iter().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::world::{BiomeGenerator, FlatGenerator, Tile, TileId};
+
+    /// A map drawn with characters from tile 0, 0, surrounded by deep water. `.` is
+    /// grass, `#` a wall, `D` a closed door and digits grass raised that high.
+    #[derive(Debug)]
+    struct MapGenerator {
+        rows: Vec<&'static str>,
+        tiles: TileRegistry,
+    }
+
+    impl WorldGenerator for MapGenerator {
+        fn generate(&self, _seed: u32, position: ChunkPos) -> Chunk {
+            let mut chunk = Chunk::new(position);
+            let tile = |id| {
+                Some(Tile {
+                    ty: self.tiles.resolve(id).unwrap(),
+                })
+            };
+
+            for local in LocalPos::all() {
+                let position = position.tile(local).unwrap();
+
+                let symbol = usize::try_from(position.y)
+                    .ok()
+                    .and_then(|y| self.rows.get(y))
+                    .zip(usize::try_from(position.x).ok())
+                    .and_then(|(row, x)| row.chars().nth(x));
+
+                let ground = if symbol.is_some() {
+                    "grass"
+                } else {
+                    "deep_water"
+                };
+                chunk.set_tile(local, Layer::Ground, tile(ground));
+
+                match symbol {
+                    Some('#') => {
+                        chunk.set_tile(local, Layer::Object, tile("wall"));
+                    }
+                    Some('D') => {
+                        chunk.set_tile(local, Layer::Object, tile("door"));
+                    }
+                    Some(height @ '0'..='9') => {
+                        chunk.set_height(local, height.to_digit(10).unwrap() as u8);
+                    }
+                    _ => {}
+                }
+
+                chunk.update_entity(local, &self.tiles);
+            }
+
+            chunk
+        }
+    }
+
+    fn map(rows: &[&'static str]) -> (World, TileRegistry) {
+        let tiles = TileRegistry::default();
+        let generator = MapGenerator {
+            rows: rows.to_vec(),
+            tiles: tiles.clone(),
+        };
+
+        let mut world = World::new(0);
+        let chunks = (-1..3)
+            .flat_map(|y| (-1..3).map(move |x| ChunkPos::new(x, y)))
+            .collect::<Vec<_>>();
+
+        world.load_chunks(&chunks, &generator);
+
+        (world, tiles)
+    }
+
+    fn check(world: &World, tiles: &TileRegistry, path: &[TilePos]) {
+        for pair in path.windows(2) {
+            assert_eq!(distance(pair[0], pair[1]), 1);
+
+            let heights = (world.height(pair[0]), world.height(pair[1]));
+            assert!(heights.0.unwrap().abs_diff(heights.1.unwrap()) <= MAX_STEP);
+        }
+
+        assert!(path
+            .iter()
+            .all(|position| world.walkable(*position, tiles) == Some(true)));
+    }
+
+    #[test]
+    fn walls_doors_and_cliffs_are_in_the_way() {
+        let (mut world, tiles) = map(&[
+            "...#...", //
+            "...D...", //
+            "...#...", //
+            ".......", //
+        ]);
+
+        let (from, to) = (TilePos::new(0, 0), TilePos::new(6, 0));
+
+        // around the bottom of the wall, 6 steps there and 6 back up
+        let path = world.find_path(from, to, &tiles, 1000).unwrap();
+
+        assert_eq!(path.len(), 13);
+        assert!(path.contains(&TilePos::new(3, 3)));
+        check(&world, &tiles, &path);
+
+        *world.entity_mut(TilePos::new(3, 1)).unwrap() = TileEntity::Door { open: true };
+
+        let path = world.find_path(from, to, &tiles, 1000).unwrap();
+
+        assert_eq!(path.len(), 9);
+        assert!(path.contains(&TilePos::new(3, 1)));
+
+        assert_eq!(
+            world.find_path(from, TilePos::new(3, 0), &tiles, 1000),
+            Err(PathError::BlockedGoal)
+        );
+        assert_eq!(world.find_path(from, from, &tiles, 1000), Ok(vec![from]));
+
+        // stairs can be climbed, a cliff can't
+        let (world, tiles) = map(&["0123210", "0000000", "0009000"]);
+
+        let path = world
+            .find_path(TilePos::new(0, 0), TilePos::new(6, 0), &tiles, 1000)
+            .unwrap();
+
+        assert_eq!(path.len(), 7);
+        check(&world, &tiles, &path);
+
+        assert_eq!(
+            world.find_path(TilePos::new(0, 2), TilePos::new(3, 2), &tiles, 1000),
+            Err(PathError::NoPath)
+        );
+    }
+
+    #[test]
+    fn searches_stop_at_unloaded_chunks_and_the_limit() {
+        let tiles = TileRegistry::default();
+        let flat = FlatGenerator { tile: TileId(0) };
+
+        let mut world = World::new(0);
+        world.load_chunk(ChunkPos::new(0, 0), &flat);
+        world.load_chunk(ChunkPos::new(2, 0), &flat);
+
+        let (from, to) = (TilePos::new(0, 0), TilePos::new(30, 0));
+
+        assert_eq!(
+            world.find_path(from, to, &tiles, 10_000),
+            Err(PathError::Unloaded(vec![
+                ChunkPos::new(-1, 0),
+                ChunkPos::new(0, -1),
+                ChunkPos::new(0, 1),
+                ChunkPos::new(1, 0),
+            ]))
+        );
+        assert_eq!(
+            world.find_path(from, TilePos::new(0, 50), &tiles, 10_000),
+            Err(PathError::Unloaded(vec![ChunkPos::new(0, 4)]))
+        );
+
+        let path = world.load_path(from, to, &tiles, 10_000, &flat).unwrap();
+
+        assert_eq!(path.len(), 31);
+        assert!(world.chunk(ChunkPos::new(1, 0)).is_some());
+
+        assert_eq!(
+            world.load_path(from, TilePos::new(500, 500), &tiles, 100, &flat),
+            Err(PathError::TooFar(100))
+        );
+    }
+
+    /// The number of steps of the shortest path by breadth first search, which is slow
+    /// but obviously right.
+    fn shortest(world: &World, tiles: &TileRegistry, from: TilePos, to: TilePos) -> Option<usize> {
+        let mut steps = HashMap::from([(from, 0)]);
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(position) = queue.pop_front() {
+            if position == to {
+                return Some(steps[&position]);
+            }
+
+            for neighbour in position.neighbours() {
+                let open = world.walkable(neighbour, tiles) == Some(true)
+                    && world
+                        .height(position)
+                        .unwrap()
+                        .abs_diff(world.height(neighbour).unwrap())
+                        <= MAX_STEP;
+
+                if open && !steps.contains_key(&neighbour) {
+                    steps.insert(neighbour, steps[&position] + 1);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+
+        None
+    }
+
+    #[test]
+    fn paths_on_generated_worlds_are_shortest() {
+        let tiles = TileRegistry::default();
+        let generator = BiomeGenerator::default();
+
+        for seed in [1, 7, 42] {
+            let mut world = World::new(seed);
+            let chunks = (-3..3)
+                .flat_map(|y| (-3..3).map(move |x| ChunkPos::new(x, y)))
+                .collect::<Vec<_>>();
+
+            world.load_chunks(&chunks, &generator);
+
+            let open = (-36..36)
+                .flat_map(|y| (-36..36).map(move |x| TilePos::new(x, y)))
+                .filter(|position| world.walkable(*position, &tiles) == Some(true))
+                .step_by(97)
+                .collect::<Vec<_>>();
+
+            for pair in open.windows(2) {
+                let (from, to) = (pair[0], pair[1]);
+
+                match world.find_path(from, to, &tiles, 100_000) {
+                    Ok(path) => {
+                        check(&world, &tiles, &path);
+                        assert_eq!(path.first(), Some(&from));
+                        assert_eq!(path.last(), Some(&to));
+                        assert_eq!(Some(path.len() - 1), shortest(&world, &tiles, from, to));
+                    }
+                    Err(_) => assert_eq!(shortest(&world, &tiles, from, to), None),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::CHUNK_SIZE;
+
+/// The four positions next to `(x, y)`, skipping the ones that would overflow.
+fn orthogonal(x: i32, y: i32) -> impl Iterator<Item = (i32, i32)> {
+    [(0, -1), (1, 0), (0, 1), (-1, 0)]
+        .into_iter()
+        .filter_map(move |(dx, dy)| Some((x.checked_add(dx)?, y.checked_add(dy)?)))
+}
+
+/// A tile in world space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct TilePos {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TilePos {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The tile a point in world space falls on, `None` if it is not finite or too far out.
+    pub fn from_f32(x: f32, y: f32) -> Option<Self> {
+        let coordinate = |value: f32| {
+            let value = value.floor();
+
+            (value.is_finite() && value >= i32::MIN as f32 && value < i32::MAX as f32)
+                .then_some(value as i32)
+        };
+
+        Some(Self::new(coordinate(x)?, coordinate(y)?))
+    }
+
+    /// The chunk this tile is in.
+    pub fn chunk(self) -> ChunkPos {
+        let size = CHUNK_SIZE as i32;
+
+        ChunkPos::new(self.x.div_euclid(size), self.y.div_euclid(size))
+    }
+
+    /// Where this tile is inside its chunk.
+    pub fn local(self) -> LocalPos {
+        let size = CHUNK_SIZE as i32;
+
+        LocalPos {
+            x: self.x.rem_euclid(size) as u8,
+            y: self.y.rem_euclid(size) as u8,
+        }
+    }
+
+    /// The tile `dx` and `dy` tiles away, `None` if that overflows.
+    pub fn offset(self, dx: i32, dy: i32) -> Option<Self> {
+        Some(Self::new(self.x.checked_add(dx)?, self.y.checked_add(dy)?))
+    }
+
+    /// The tiles above, right of, below and left of this one.
+    pub fn neighbours(self) -> impl Iterator<Item = TilePos> {
+        orthogonal(self.x, self.y).map(|(x, y)| Self::new(x, y))
+    }
+}
+
+impl fmt::Display for TilePos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.x, self.y)
+    }
+}
+
+/// A chunk, one unit is `CHUNK_SIZE` tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The tile at `local` in this chunk, `None` for chunks too far out to address
+    /// their tiles with an `i32`.
+    pub fn tile(self, local: LocalPos) -> Option<TilePos> {
+        // the corner of a chunk at the edge can be out of range while its tiles are not
+        let coordinate = |chunk: i32, local: u8| {
+            i32::try_from(chunk as i64 * CHUNK_SIZE as i64 + local as i64).ok()
+        };
+
+        Some(TilePos::new(
+            coordinate(self.x, local.x)?,
+            coordinate(self.y, local.y)?,
+        ))
+    }
+
+    /// The tile in the corner with the lowest coordinates.
+    pub fn origin(self) -> Option<TilePos> {
+        self.tile(LocalPos::ORIGIN)
+    }
+
+    /// The chunks above, right of, below and left of this one.
+    pub fn neighbours(self) -> impl Iterator<Item = ChunkPos> {
+        orthogonal(self.x, self.y).map(|(x, y)| Self::new(x, y))
+    }
+}
+
+impl fmt::Display for ChunkPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.x, self.y)
+    }
+}
+
+/// A tile inside a chunk, both coordinates are always below `CHUNK_SIZE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "(u8, u8)", into = "(u8, u8)")]
+pub struct LocalPos {
+    x: u8,
+    y: u8,
+}
+
+impl LocalPos {
+    pub const ORIGIN: LocalPos = LocalPos { x: 0, y: 0 };
+
+    /// `None` unless both coordinates are inside a chunk.
+    pub fn new(x: usize, y: usize) -> Option<Self> {
+        (x < CHUNK_SIZE && y < CHUNK_SIZE).then_some(Self {
+            x: x as u8,
+            y: y as u8,
+        })
+    }
+
+    pub fn x(self) -> usize {
+        self.x as usize
+    }
+
+    pub fn y(self) -> usize {
+        self.y as usize
+    }
+
+    /// The index of the tile in the grids of a chunk, row by row like `all`.
+    pub fn index(self) -> usize {
+        self.y() * CHUNK_SIZE + self.x()
+    }
+
+    /// Every tile of a chunk, row by row.
+    pub fn all() -> impl Iterator<Item = LocalPos> {
+        (0..CHUNK_SIZE as u8).flat_map(|y| (0..CHUNK_SIZE as u8).map(move |x| LocalPos { x, y }))
+    }
+
+    /// The tiles next to this one that are in the same chunk.
+    pub fn neighbours(self) -> impl Iterator<Item = LocalPos> {
+        orthogonal(self.x as i32, self.y as i32)
+            .filter_map(|(x, y)| Self::new(usize::try_from(x).ok()?, usize::try_from(y).ok()?))
+    }
+}
+
+impl TryFrom<(u8, u8)> for LocalPos {
+    type Error = String;
+
+    fn try_from((x, y): (u8, u8)) -> Result<Self, Self::Error> {
+        Self::new(x as usize, y as usize).ok_or_else(|| format!("{}, {} is outside a chunk", x, y))
+    }
+}
+
+impl From<LocalPos> for (u8, u8) {
+    fn from(local: LocalPos) -> Self {
+        (local.x, local.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_tiles_round_down() {
+        assert_eq!(TilePos::new(-1, -12).chunk(), ChunkPos::new(-1, -1));
+        assert_eq!(TilePos::new(-13, 11).chunk(), ChunkPos::new(-2, 0));
+        assert_eq!(TilePos::new(-1, -12).local().index(), 11);
+    }
+
+    #[test]
+    fn chunk_and_local_round_trip() {
+        for tile in [
+            TilePos::new(0, 0),
+            TilePos::new(-1, 5),
+            TilePos::new(37, -100),
+            TilePos::new(i32::MIN, i32::MAX),
+        ] {
+            assert_eq!(tile.chunk().tile(tile.local()), Some(tile));
+        }
+    }
+
+    #[test]
+    fn conversions_are_checked() {
+        assert_eq!(LocalPos::new(CHUNK_SIZE, 0), None);
+        assert_eq!(ChunkPos::new(i32::MAX, 0).origin(), None);
+        assert_eq!(TilePos::from_f32(f32::NAN, 0.0), None);
+        assert_eq!(TilePos::from_f32(1e12, 0.0), None);
+        assert_eq!(TilePos::from_f32(-0.5, 2.9), Some(TilePos::new(-1, 2)));
+    }
+
+    #[test]
+    fn neighbours_skip_overflow() {
+        assert_eq!(TilePos::new(0, 0).neighbours().count(), 4);
+        assert_eq!(TilePos::new(i32::MAX, 0).neighbours().count(), 3);
+        assert_eq!(LocalPos::ORIGIN.neighbours().count(), 2);
+        assert_eq!(LocalPos::all().count(), CHUNK_SIZE * CHUNK_SIZE);
+    }
+}
@@ -0,0 +1,399 @@
+//! Scatters structures like houses and ruins over the chunks of another generator.
+//!
+//! The world is divided into squares `spacing` tiles wide and every square gets at most
+//! one of each structure, somewhere inside it with `separation` tiles to spare. Whether
+//! and where only depends on the seed and the square, so every chunk a structure
+//! overlaps places the same part of it without knowing about the others.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    fnv1a, Chunk, ChunkPos, Layer, LocalPos, Rotation, Structure, TileId, TilePos, TileRegistry,
+    TileRegistryError, WorldGenerator, CHUNK_SIZE,
+};
+
+/// Where and how often a structure is scattered over a world.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    /// The name of the structure file, without the extension.
+    pub structure: String,
+    /// The size of the squares the world is divided into, in tiles.
+    pub spacing: u32,
+    /// The fewest tiles between two copies of the structure.
+    #[serde(default)]
+    pub separation: u32,
+    /// The chance that a square gets the structure, from 0 to 1.
+    pub chance: f64,
+    /// String ids of the ground tiles the middle of the structure may be on, any if
+    /// empty.
+    #[serde(default)]
+    pub ground: Vec<String>,
+    /// Whether the structure is turned at random.
+    #[serde(default)]
+    pub rotate: bool,
+    /// Whether the structure is mirrored at random.
+    #[serde(default)]
+    pub mirror: bool,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ScatterError {
+    #[error("{structure} does not fit in squares of {spacing} tiles with {separation} to spare")]
+    TooLarge {
+        structure: String,
+        spacing: u32,
+        separation: u32,
+    },
+    #[error(transparent)]
+    Tiles(#[from] TileRegistryError),
+}
+
+/// A placement with its structure in every way it can be turned.
+#[derive(Debug)]
+struct Scattered {
+    placement: Placement,
+    variants: Vec<Structure>,
+    ground: Vec<TileId>,
+}
+
+/// Where a structure goes, in tiles since it may not fit in an `i32` near the edges.
+struct Site<'a> {
+    x: i64,
+    y: i64,
+    structure: &'a Structure,
+}
+
+impl Site<'_> {
+    fn overlaps(&self, other: &Site) -> bool {
+        let (width, height) = (
+            self.structure.width() as i64,
+            self.structure.height() as i64,
+        );
+        let (other_width, other_height) = (
+            other.structure.width() as i64,
+            other.structure.height() as i64,
+        );
+
+        self.x < other.x + other_width
+            && other.x < self.x + width
+            && self.y < other.y + other_height
+            && other.y < self.y + height
+    }
+}
+
+/// Wraps a generator and places structures on top of what it generates.
+#[derive(Debug)]
+pub struct ScatterGenerator {
+    inner: Box<dyn WorldGenerator>,
+    scattered: Vec<Scattered>,
+    /// Gives the tiles of structures that have no entity in their file one.
+    tiles: TileRegistry,
+}
+
+impl ScatterGenerator {
+    /// Scatters each structure by its placement, earlier placements win where two
+    /// would overlap. Fails if a structure does not fit in its squares.
+    pub fn new(
+        inner: Box<dyn WorldGenerator>,
+        placements: Vec<(Placement, Structure)>,
+        tiles: &TileRegistry,
+    ) -> Result<Self, ScatterError> {
+        let scattered = placements
+            .into_iter()
+            .map(|(placement, structure)| {
+                let rotations: &[Rotation] = if placement.rotate {
+                    &Rotation::ALL
+                } else {
+                    &[Rotation::None]
+                };
+                let mirrors: &[bool] = if placement.mirror {
+                    &[false, true]
+                } else {
+                    &[false]
+                };
+
+                let variants = rotations
+                    .iter()
+                    .flat_map(|rotation| {
+                        mirrors
+                            .iter()
+                            .map(|mirrored| structure.transformed(*rotation, *mirrored))
+                    })
+                    .collect::<Vec<_>>();
+
+                let size = variants
+                    .iter()
+                    .map(|variant| variant.width().max(variant.height()))
+                    .max()
+                    .unwrap_or(0);
+
+                if size as u64 + placement.separation as u64 > placement.spacing as u64
+                    || placement.spacing == 0
+                {
+                    return Err(ScatterError::TooLarge {
+                        structure: placement.structure.clone(),
+                        spacing: placement.spacing,
+                        separation: placement.separation,
+                    });
+                }
+
+                let ground = placement
+                    .ground
+                    .iter()
+                    .map(|id| tiles.resolve(id))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Scattered {
+                    placement,
+                    variants,
+                    ground,
+                })
+            })
+            .collect::<Result<_, ScatterError>>()?;
+
+        Ok(Self {
+            inner,
+            scattered,
+            tiles: tiles.clone(),
+        })
+    }
+
+    /// Where the placement at `index` puts its structure in the square at `square`, if
+    /// it does, ignoring earlier placements.
+    fn site(&self, seed: u32, index: usize, square: (i64, i64)) -> Option<Site<'_>> {
+        let scattered = &self.scattered[index];
+        let placement = &scattered.placement;
+
+        let random = |draw: u8| {
+            let mut bytes = Vec::with_capacity(22);
+            bytes.extend(seed.to_le_bytes());
+            bytes.extend((index as u32).to_le_bytes());
+            bytes.extend(square.0.to_le_bytes());
+            bytes.extend(square.1.to_le_bytes());
+            bytes.push(draw);
+
+            fnv1a(&bytes)
+        };
+
+        if random(0) as f64 / u32::MAX as f64 >= placement.chance {
+            return None;
+        }
+
+        let structure = &scattered.variants[random(1) as usize % scattered.variants.len()];
+
+        // room to move around in while keeping clear of the next square
+        let room = |size: u32| (placement.spacing - placement.separation - size) as u64 + 1;
+
+        let spacing = placement.spacing as i64;
+        let x = square.0 * spacing + (random(2) as u64 % room(structure.width())) as i64;
+        let y = square.1 * spacing + (random(3) as u64 % room(structure.height())) as i64;
+
+        if !scattered.ground.is_empty() {
+            let middle = TilePos::new(
+                i32::try_from(x + structure.width() as i64 / 2).ok()?,
+                i32::try_from(y + structure.height() as i64 / 2).ok()?,
+            );
+
+            let ground = self.inner.ground(seed, middle)?;
+
+            if !scattered.ground.contains(&ground.ty) {
+                return None;
+            }
+        }
+
+        Some(Site { x, y, structure })
+    }
+
+    /// The squares of a placement that overlap the tiles from `min` to `max`.
+    fn squares(&self, index: usize, min: (i64, i64), max: (i64, i64)) -> Vec<(i64, i64)> {
+        let spacing = self.scattered[index].placement.spacing as i64;
+
+        (min.1.div_euclid(spacing)..=max.1.div_euclid(spacing))
+            .flat_map(|y| {
+                (min.0.div_euclid(spacing)..=max.0.div_euclid(spacing)).map(move |x| (x, y))
+            })
+            .collect()
+    }
+
+    /// Whether a site of an earlier placement than `index` overlaps `site`.
+    fn blocked(&self, seed: u32, index: usize, site: &Site) -> bool {
+        let min = (site.x, site.y);
+        let max = (
+            site.x + site.structure.width() as i64 - 1,
+            site.y + site.structure.height() as i64 - 1,
+        );
+
+        (0..index).any(|earlier| {
+            self.squares(earlier, min, max)
+                .into_iter()
+                .filter_map(|square| self.site(seed, earlier, square))
+                .any(|other| other.overlaps(site))
+        })
+    }
+}
+
+impl WorldGenerator for ScatterGenerator {
+    fn generate(&self, seed: u32, position: ChunkPos) -> Chunk {
+        let mut chunk = self.inner.generate(seed, position);
+
+        let size = CHUNK_SIZE as i64;
+        let min = (position.x as i64 * size, position.y as i64 * size);
+        let max = (min.0 + size - 1, min.1 + size - 1);
+
+        let mut placed = false;
+
+        for index in 0..self.scattered.len() {
+            for square in self.squares(index, min, max) {
+                let site = match self.site(seed, index, square) {
+                    Some(site) if !self.blocked(seed, index, &site) => site,
+                    _ => continue,
+                };
+
+                for (x, y) in site.structure.offsets() {
+                    let tile = (site.x + x as i64, site.y + y as i64);
+
+                    if !(min.0..=max.0).contains(&tile.0) || !(min.1..=max.1).contains(&tile.1) {
+                        continue;
+                    }
+
+                    let local = LocalPos::new((tile.0 - min.0) as usize, (tile.1 - min.1) as usize)
+                        .expect("the tile is in the chunk");
+
+                    for layer in Layer::ALL
+                        .into_iter()
+                        .filter(|layer| site.structure.has_layer(*layer))
+                    {
+                        chunk.set_tile(local, layer, site.structure.tile(x, y, layer));
+                    }
+
+                    match site.structure.entity(x, y) {
+                        Some(entity) if chunk.has_room_for(local, entity) => {
+                            chunk.entities.insert(local, entity.clone());
+                        }
+                        _ => {}
+                    }
+
+                    placed = true;
+                }
+            }
+        }
+
+        if placed {
+            chunk.update_entities(&self.tiles);
+        }
+
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{FlatGenerator, Tile, World};
+
+    /// A ring of walls around a stone floor.
+    fn hut(tiles: &TileRegistry) -> Structure {
+        let mut hut = Structure::new(3, 3);
+
+        for (x, y) in hut.offsets().collect::<Vec<_>>() {
+            let tile = |id| {
+                Some(Tile {
+                    ty: tiles.resolve(id).unwrap(),
+                })
+            };
+
+            hut.set_tile(x, y, Layer::Ground, tile("stone"));
+            hut.set_tile(
+                x,
+                y,
+                Layer::Object,
+                if (x, y) == (1, 1) { None } else { tile("wall") },
+            );
+        }
+
+        hut
+    }
+
+    fn placement(ground: &[&str]) -> Placement {
+        Placement {
+            structure: "hut".to_string(),
+            spacing: 10,
+            separation: 2,
+            chance: 1.0,
+            ground: ground.iter().map(|id| id.to_string()).collect(),
+            rotate: true,
+            mirror: true,
+        }
+    }
+
+    fn generate(placements: Vec<Placement>) -> (World, TileRegistry) {
+        let tiles = TileRegistry::default();
+        let flat = FlatGenerator {
+            tile: tiles.resolve("grass").unwrap(),
+        };
+
+        let placements = placements
+            .into_iter()
+            .map(|placement| (placement, hut(&tiles)))
+            .collect();
+        let generator = ScatterGenerator::new(Box::new(flat), placements, &tiles).unwrap();
+
+        let mut world = World::new(11);
+        let chunks = (0..4)
+            .flat_map(|y| (0..4).map(move |x| ChunkPos::new(x, y)))
+            .collect::<Vec<_>>();
+
+        world.load_chunks(&chunks, &generator);
+
+        (world, tiles)
+    }
+
+    fn count(world: &World, tiles: &TileRegistry, id: &str) -> usize {
+        let ty = tiles.resolve(id).unwrap();
+
+        (0..40)
+            .flat_map(|y| (0..40).map(move |x| TilePos::new(x, y)))
+            .filter(|position| world.top(*position).map(|(_, tile)| tile.ty) == Some(ty))
+            .count()
+    }
+
+    #[test]
+    fn every_square_gets_a_whole_structure() {
+        let (world, tiles) = generate(vec![placement(&[])]);
+
+        // 16 squares of 10 tiles, chunks of 12 cut through many of the huts
+        assert_eq!(count(&world, &tiles, "wall"), 16 * 8);
+        assert_eq!(count(&world, &tiles, "stone"), 16);
+    }
+
+    #[test]
+    fn earlier_placements_win() {
+        let (world, tiles) = generate(vec![placement(&[]), placement(&[])]);
+
+        // the second placement only fits where it misses the first
+        let walls = count(&world, &tiles, "wall");
+
+        assert_eq!(walls % 8, 0);
+        assert!((16 * 8..=32 * 8).contains(&walls));
+        assert_eq!(walls / 8, count(&world, &tiles, "stone"));
+    }
+
+    #[test]
+    fn placement_rules_are_checked() {
+        let (world, tiles) = generate(vec![placement(&["sand"])]);
+
+        assert_eq!(count(&world, &tiles, "wall"), 0);
+
+        let tiles = TileRegistry::default();
+        let flat = FlatGenerator { tile: TileId(0) };
+        let crowded = Placement {
+            separation: 8,
+            ..placement(&[])
+        };
+
+        assert!(matches!(
+            ScatterGenerator::new(Box::new(flat), vec![(crowded, hut(&tiles))], &tiles),
+            Err(ScatterError::TooLarge { .. })
+        ));
+    }
+}
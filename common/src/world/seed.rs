@@ -0,0 +1,34 @@
+/// Derives a world seed from a config string.
+///
+/// Numbers are used as they are, so a seed shown by `/seed` can be copied into the
+/// config, anything else is hashed.
+pub fn seed_from_str(text: &str) -> u32 {
+    let text = text.trim();
+
+    text.parse().unwrap_or_else(|_| fnv1a(text.as_bytes()))
+}
+
+/// 32 bit FNV-1a, unlike `DefaultHasher` its output is specified and never changes
+/// between Rust versions or platforms.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_used_as_they_are() {
+        assert_eq!(seed_from_str("42"), 42);
+        assert_eq!(seed_from_str(" 42 "), 42);
+    }
+
+    #[test]
+    fn text_is_hashed() {
+        assert_eq!(seed_from_str(""), 0x811c_9dc5);
+        assert_eq!(seed_from_str("wanhope"), 241_524_003);
+    }
+}
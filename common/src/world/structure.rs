@@ -0,0 +1,497 @@
+//! Structures are rectangles of tiles that can be placed anywhere, copied out of a world
+//! or saved as templates like houses, ruins and paths.
+//!
+//! Templates are saved as RON in the format of `StructureFile`, which is meant to be
+//! written by hand as well:
+//!
+//! ```ron
+//! (
+//!     // every character used in `layers` and the string id of its tile, `.` is
+//!     // always an empty tile
+//!     legend: {
+//!         'w': "wall",
+//!         'd': "door",
+//!         's': "stone",
+//!     },
+//!     // rows from the top down, every row of every layer must be as long as the
+//!     // others. Layers that are left out are not touched when placing the structure.
+//!     layers: {
+//!         Ground: [
+//!             "sssss",
+//!             "sssss",
+//!             "sssss",
+//!         ],
+//!         Object: [
+//!             "wwdww",
+//!             "w...w",
+//!             "wwwww",
+//!         ],
+//!     },
+//!     // optional, by offset from the top left corner. Tiles that need an entity get
+//!     // the one their kind starts with if they have none here.
+//!     entities: [
+//!         (x: 2, y: 0, entity: Door(open: true)),
+//!     ],
+//! )
+//! ```
+//!
+//! Tiles are saved by their string id so templates keep working when numeric ids are
+//! added to the tile registry.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Layer, Tile, TileEntity, TileRegistry, TileRegistryError};
+
+/// The character for an empty tile in structure files.
+pub const EMPTY_SYMBOL: char = '.';
+
+/// Characters given to tiles when saving a structure, after the first letter of their id.
+const SYMBOLS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789#%&*+=?@";
+
+/// How far a structure is turned when it is placed, in steps of 90 degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise,
+    Half,
+    CounterClockwise,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Clockwise,
+        Rotation::Half,
+        Rotation::CounterClockwise,
+    ];
+
+    /// A clockwise rotation by `degrees`, which must be a multiple of 90.
+    pub fn from_degrees(degrees: i64) -> Option<Self> {
+        (degrees % 90 == 0).then(|| Self::ALL[(degrees / 90).rem_euclid(4) as usize])
+    }
+
+    pub fn degrees(self) -> u32 {
+        self as u32 * 90
+    }
+}
+
+/// A rectangle of tiles copied out of a world or loaded from a file, placed with its
+/// top left corner at any position. Heights are not part of it, a structure sits on
+/// whatever terrain it is placed on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Structure {
+    width: u32,
+    height: u32,
+
+    /// The tiles of each layer row by row, `None` clears the layer where it is pasted.
+    /// Layers the structure leaves alone have no tiles at all.
+    layers: [Option<Vec<Option<Tile>>>; Layer::COUNT],
+
+    /// Entities by their offset from the top left corner.
+    entities: BTreeMap<(u32, u32), TileEntity>,
+}
+
+impl Structure {
+    /// A structure that leaves every layer alone until tiles are set.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+
+            layers: Default::default(),
+
+            entities: BTreeMap::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of positions it covers.
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y as usize * self.width as usize + x as usize)
+    }
+
+    /// Whether placing the structure changes `layer`.
+    pub fn has_layer(&self, layer: Layer) -> bool {
+        self.layers[layer.index()].is_some()
+    }
+
+    /// The tile at an offset, `None` if the layer is empty there, the structure leaves
+    /// the layer alone or the offset is outside.
+    pub fn tile(&self, x: u32, y: u32, layer: Layer) -> Option<Tile> {
+        self.layers[layer.index()].as_ref()?[self.index(x, y)?]
+    }
+
+    /// Adds the layer to the structure if it did not have it, does nothing if the
+    /// offset is outside.
+    pub fn set_tile(&mut self, x: u32, y: u32, layer: Layer, tile: Option<Tile>) {
+        let area = self.area() as usize;
+
+        if let Some(index) = self.index(x, y) {
+            self.layers[layer.index()].get_or_insert_with(|| vec![None; area])[index] = tile;
+        }
+    }
+
+    pub fn entity(&self, x: u32, y: u32) -> Option<&TileEntity> {
+        self.entities.get(&(x, y))
+    }
+
+    /// Does nothing if the offset is outside.
+    pub fn set_entity(&mut self, x: u32, y: u32, entity: TileEntity) {
+        if self.index(x, y).is_some() {
+            self.entities.insert((x, y), entity);
+        }
+    }
+
+    /// Every offset, row by row.
+    pub fn offsets(&self) -> impl Iterator<Item = (u32, u32)> {
+        let width = self.width;
+
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+    }
+
+    /// A copy flipped from left to right if `mirrored`, then turned by `rotation`.
+    pub fn transformed(&self, rotation: Rotation, mirrored: bool) -> Structure {
+        let (width, height) = (self.width, self.height);
+
+        let flip = |x: u32| if mirrored { width - 1 - x } else { x };
+
+        // where each offset of the turned structure comes from
+        let (new_width, new_height) = match rotation {
+            Rotation::None | Rotation::Half => (width, height),
+            Rotation::Clockwise | Rotation::CounterClockwise => (height, width),
+        };
+
+        let source = |x: u32, y: u32| match rotation {
+            Rotation::None => (flip(x), y),
+            Rotation::Clockwise => (flip(y), height - 1 - x),
+            Rotation::Half => (flip(width - 1 - x), height - 1 - y),
+            Rotation::CounterClockwise => (flip(width - 1 - y), x),
+        };
+
+        let mut transformed = Structure::new(new_width, new_height);
+
+        for (x, y) in transformed.offsets().collect::<Vec<_>>() {
+            let (from_x, from_y) = source(x, y);
+
+            for layer in Layer::ALL
+                .into_iter()
+                .filter(|layer| self.has_layer(*layer))
+            {
+                transformed.set_tile(x, y, layer, self.tile(from_x, from_y, layer));
+            }
+
+            if let Some(entity) = self.entity(from_x, from_y) {
+                transformed.set_entity(x, y, entity.clone());
+            }
+        }
+
+        transformed
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum StructureError {
+    #[error("the structure has no layers")]
+    NoLayers,
+    #[error(
+        "the structure is larger than {} by {} tiles",
+        StructureFile::MAX_SIZE,
+        StructureFile::MAX_SIZE
+    )]
+    TooLarge,
+    #[error("the {0} layer is not the same size as the others, or its rows are uneven")]
+    WrongSize(Layer),
+    #[error("'{0}' is not in the legend")]
+    UnknownSymbol(char),
+    #[error("'{}' always means an empty tile", EMPTY_SYMBOL)]
+    ReservedSymbol,
+    #[error("the entity at {0}, {1} is outside the structure")]
+    EntityOutside(u32, u32),
+    #[error(transparent)]
+    Tiles(#[from] TileRegistryError),
+}
+
+/// An entity in a structure file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructureEntity {
+    pub x: u32,
+    pub y: u32,
+    pub entity: TileEntity,
+}
+
+/// A structure as it is saved, see the module documentation for an example.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructureFile {
+    /// The string id of the tile each character in `layers` stands for.
+    pub legend: BTreeMap<char, String>,
+    /// Rows of characters from the top down, by layer.
+    pub layers: BTreeMap<Layer, Vec<String>>,
+    #[serde(default)]
+    pub entities: Vec<StructureEntity>,
+}
+
+impl StructureFile {
+    /// The widest and tallest a structure file can be.
+    pub const MAX_SIZE: u32 = 256;
+
+    /// Writes a structure with the string ids of `tiles`, giving every tile a character.
+    pub fn new(structure: &Structure, tiles: &TileRegistry) -> Self {
+        let mut legend = BTreeMap::new();
+        let mut symbols = BTreeMap::new();
+
+        let mut symbol_of = |tile: Option<Tile>| {
+            let tile = match tile {
+                Some(tile) => tile,
+                None => return EMPTY_SYMBOL,
+            };
+
+            *symbols.entry(tile.ty).or_insert_with(|| {
+                let id = tiles.id_of(tile.ty);
+
+                // the first letter of the id reads best, then anything still free
+                let symbol = id
+                    .chars()
+                    .next()
+                    .filter(|symbol| SYMBOLS.contains(*symbol))
+                    .into_iter()
+                    .chain(SYMBOLS.chars())
+                    .chain((0xc0..=char::MAX as u32).filter_map(char::from_u32))
+                    .find(|symbol| !legend.contains_key(symbol))
+                    .expect("there are more characters than tiles");
+
+                legend.insert(symbol, id);
+
+                symbol
+            })
+        };
+
+        let layers = Layer::ALL
+            .into_iter()
+            .filter(|layer| structure.has_layer(*layer))
+            .map(|layer| {
+                let rows = (0..structure.height)
+                    .map(|y| {
+                        (0..structure.width)
+                            .map(|x| symbol_of(structure.tile(x, y, layer)))
+                            .collect()
+                    })
+                    .collect();
+
+                (layer, rows)
+            })
+            .collect();
+
+        let entities = structure
+            .entities
+            .iter()
+            .map(|(&(x, y), entity)| StructureEntity {
+                x,
+                y,
+                entity: entity.clone(),
+            })
+            .collect();
+
+        Self {
+            legend,
+            layers,
+            entities,
+        }
+    }
+
+    /// Reads the structure, resolving the tiles of the legend with `tiles`.
+    pub fn to_structure(&self, tiles: &TileRegistry) -> Result<Structure, StructureError> {
+        if self.legend.contains_key(&EMPTY_SYMBOL) {
+            return Err(StructureError::ReservedSymbol);
+        }
+
+        let legend = self
+            .legend
+            .iter()
+            .map(|(symbol, id)| {
+                Ok((
+                    *symbol,
+                    Tile {
+                        ty: tiles.resolve(id)?,
+                    },
+                ))
+            })
+            .collect::<Result<BTreeMap<_, _>, StructureError>>()?;
+
+        let (height, width) = match self.layers.values().next() {
+            Some(rows) => (
+                rows.len(),
+                rows.first().map_or(0, |row| row.chars().count()),
+            ),
+            None => return Err(StructureError::NoLayers),
+        };
+
+        let max = Self::MAX_SIZE as usize;
+
+        if width > max || height > max {
+            return Err(StructureError::TooLarge);
+        }
+
+        let mut structure = Structure::new(width as u32, height as u32);
+
+        for (layer, rows) in &self.layers {
+            if rows.len() != height || rows.iter().any(|row| row.chars().count() != width) {
+                return Err(StructureError::WrongSize(*layer));
+            }
+
+            for (y, row) in rows.iter().enumerate() {
+                for (x, symbol) in row.chars().enumerate() {
+                    let tile = match symbol {
+                        EMPTY_SYMBOL => None,
+                        symbol => Some(
+                            *legend
+                                .get(&symbol)
+                                .ok_or(StructureError::UnknownSymbol(symbol))?,
+                        ),
+                    };
+
+                    structure.set_tile(x as u32, y as u32, *layer, tile);
+                }
+            }
+        }
+
+        for StructureEntity { x, y, entity } in &self.entities {
+            if structure.index(*x, *y).is_none() {
+                return Err(StructureError::EntityOutside(*x, *y));
+            }
+
+            structure.set_entity(*x, *y, entity.clone());
+        }
+
+        Ok(structure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example of the module documentation.
+    const EXAMPLE: &str = r#"(
+        legend: { 'w': "wall", 'd': "door", 's': "stone" },
+        layers: {
+            Ground: ["sssss", "sssss", "sssss"],
+            Object: ["wwdww", "w...w", "wwwww"],
+        },
+        entities: [(x: 2, y: 0, entity: Door(open: true))],
+    )"#;
+
+    fn example() -> (Structure, TileRegistry) {
+        let tiles = TileRegistry::default();
+        let file: StructureFile = ron::from_str(EXAMPLE).unwrap();
+
+        (file.to_structure(&tiles).unwrap(), tiles)
+    }
+
+    #[test]
+    fn the_documented_format_loads() {
+        let (house, tiles) = example();
+        let tile = |id| {
+            Some(Tile {
+                ty: tiles.resolve(id).unwrap(),
+            })
+        };
+
+        assert_eq!((house.width(), house.height()), (5, 3));
+        assert!(!house.has_layer(Layer::Decoration));
+        assert_eq!(house.tile(2, 0, Layer::Object), tile("door"));
+        assert_eq!(house.tile(2, 1, Layer::Object), None);
+        assert_eq!(house.tile(2, 1, Layer::Ground), tile("stone"));
+        assert_eq!(house.entity(2, 0), Some(&TileEntity::Door { open: true }));
+
+        // saving and loading again gives the same structure
+        let saved = StructureFile::new(&house, &tiles);
+
+        assert_eq!(saved.to_structure(&tiles), Ok(house));
+    }
+
+    #[test]
+    fn bad_files_are_rejected() {
+        let tiles = TileRegistry::default();
+        let load = |text: &str| {
+            ron::from_str::<StructureFile>(text)
+                .unwrap()
+                .to_structure(&tiles)
+        };
+
+        assert_eq!(
+            load(r#"(legend: {}, layers: { Ground: ["..", "."] })"#),
+            Err(StructureError::WrongSize(Layer::Ground))
+        );
+        assert_eq!(
+            load(r#"(legend: {}, layers: { Ground: ["x"] })"#),
+            Err(StructureError::UnknownSymbol('x'))
+        );
+        assert_eq!(
+            load(r#"(legend: { '.': "sand" }, layers: { Ground: ["."] })"#),
+            Err(StructureError::ReservedSymbol)
+        );
+        assert_eq!(
+            load(r#"(legend: { 'l': "lava" }, layers: { Ground: ["l"] })"#),
+            Err(StructureError::Tiles(TileRegistryError::UnknownTile(
+                "lava".to_string()
+            )))
+        );
+        assert_eq!(
+            load(r#"(legend: {}, layers: {})"#),
+            Err(StructureError::NoLayers)
+        );
+    }
+
+    #[test]
+    fn turning_four_times_is_no_turn() {
+        let (house, _) = example();
+
+        let turned = house.transformed(Rotation::Clockwise, false);
+
+        assert_eq!((turned.width(), turned.height()), (3, 5));
+        // the door in the middle of the top wall ends up in the middle of the right one
+        assert_eq!(turned.entity(2, 2), Some(&TileEntity::Door { open: true }));
+        assert_eq!(turned.transformed(Rotation::CounterClockwise, false), house);
+
+        let mut around = house.clone();
+
+        for _ in 0..4 {
+            around = around.transformed(Rotation::Clockwise, false);
+        }
+
+        assert_eq!(around, house);
+        assert_eq!(
+            house.transformed(Rotation::Half, false),
+            house
+                .transformed(Rotation::Half, true)
+                .transformed(Rotation::None, true)
+        );
+
+        let mirrored = house.transformed(Rotation::None, true);
+
+        assert_eq!(
+            mirrored.tile(0, 0, Layer::Object),
+            house.tile(4, 0, Layer::Object)
+        );
+        assert_eq!(
+            Rotation::from_degrees(-90),
+            Some(Rotation::CounterClockwise)
+        );
+        assert_eq!(Rotation::from_degrees(450), Some(Rotation::Clockwise));
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+}
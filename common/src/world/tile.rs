@@ -1,12 +1,249 @@
+use std::{collections::HashMap, fmt};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum TileType {
-    Grass,
-    Sand,
+use super::TileEntity;
+
+/// The numeric id of a tile, what chunks store and send. What it means is up to the
+/// `TileRegistry` of the server.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct TileId(pub u16);
+
+impl fmt::Display for TileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Tile {
-    pub ty: TileType,
+    pub ty: TileId,
+}
+
+/// Everything the server and clients need to know about a kind of tile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileDef {
+    /// Used in configs, commands and scripts, e.g. `deep_water`.
+    pub id: String,
+    /// Stored in chunks, must never change once worlds use it.
+    pub numeric_id: u16,
+    /// The name of a texture in the client's assets, without the extension.
+    pub texture: String,
+    pub walkable: bool,
+    /// Shown to players.
+    pub name: String,
+    /// The colour of the tile on maps.
+    pub colour: [u8; 3],
+    /// The entity placed tiles of this kind start with.
+    #[serde(default)]
+    pub entity: Option<TileEntity>,
+}
+
+impl TileDef {
+    pub fn new(id: &str, numeric_id: u16, name: &str, walkable: bool, colour: [u8; 3]) -> Self {
+        Self {
+            id: id.to_string(),
+            numeric_id,
+            texture: id.to_string(),
+            walkable,
+            name: name.to_string(),
+            colour,
+            entity: None,
+        }
+    }
+
+    pub fn with_entity(mut self, entity: TileEntity) -> Self {
+        self.entity = Some(entity);
+        self
+    }
+
+    pub fn tile_id(&self) -> TileId {
+        TileId(self.numeric_id)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TileRegistryError {
+    #[error("there are no tiles")]
+    Empty,
+    #[error("the tile id {0} is used twice")]
+    DuplicateId(String),
+    #[error("the numeric tile id {0} is used twice")]
+    DuplicateNumericId(u16),
+    #[error("there is no tile {0}")]
+    UnknownTile(String),
+}
+
+/// The tiles a server knows, loaded from a config file and sent to clients when they
+/// join so both agree on what numeric ids mean.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "Vec<TileDef>", into = "Vec<TileDef>")]
+pub struct TileRegistry {
+    tiles: Vec<TileDef>,
+    /// Indices into `tiles`.
+    by_numeric_id: HashMap<u16, usize>,
+    /// Indices into `tiles`, by lowercase id.
+    by_id: HashMap<String, usize>,
+}
+
+impl TileRegistry {
+    pub fn new(tiles: Vec<TileDef>) -> Result<Self, TileRegistryError> {
+        if tiles.is_empty() {
+            return Err(TileRegistryError::Empty);
+        }
+
+        let mut by_numeric_id = HashMap::new();
+        let mut by_id = HashMap::new();
+
+        for (i, tile) in tiles.iter().enumerate() {
+            if by_numeric_id.insert(tile.numeric_id, i).is_some() {
+                return Err(TileRegistryError::DuplicateNumericId(tile.numeric_id));
+            }
+
+            if by_id.insert(tile.id.to_lowercase(), i).is_some() {
+                return Err(TileRegistryError::DuplicateId(tile.id.clone()));
+            }
+        }
+
+        Ok(Self {
+            tiles,
+            by_numeric_id,
+            by_id,
+        })
+    }
+
+    /// Every tile, in the order they were configured.
+    pub fn tiles(&self) -> &[TileDef] {
+        &self.tiles
+    }
+
+    pub fn get(&self, id: TileId) -> Option<&TileDef> {
+        Some(&self.tiles[*self.by_numeric_id.get(&id.0)?])
+    }
+
+    /// The tile with the string id `id`, ignoring case.
+    pub fn find(&self, id: &str) -> Option<&TileDef> {
+        Some(&self.tiles[*self.by_id.get(&id.to_lowercase())?])
+    }
+
+    /// The numeric id of the tile with the string id `id`.
+    pub fn resolve(&self, id: &str) -> Result<TileId, TileRegistryError> {
+        self.find(id)
+            .map(TileDef::tile_id)
+            .ok_or_else(|| TileRegistryError::UnknownTile(id.to_string()))
+    }
+
+    /// The string id of a tile, its number for tiles this registry does not know.
+    pub fn id_of(&self, id: TileId) -> String {
+        self.get(id)
+            .map_or_else(|| id.to_string(), |tile| tile.id.clone())
+    }
+
+    /// The entity a new tile of this kind starts with, if it has one.
+    pub fn entity_of(&self, id: TileId) -> Option<&TileEntity> {
+        self.get(id)?.entity.as_ref()
+    }
+
+    /// The display name of a tile, its number for tiles this registry does not know.
+    pub fn name_of(&self, id: TileId) -> String {
+        self.get(id)
+            .map_or_else(|| id.to_string(), |tile| tile.name.clone())
+    }
+}
+
+impl Default for TileRegistry {
+    fn default() -> Self {
+        Self::new(vec![
+            TileDef::new("grass", 0, "Grass", true, [86, 160, 60]),
+            TileDef::new("sand", 1, "Sand", true, [222, 200, 130]),
+            TileDef::new("water", 2, "Water", false, [60, 110, 200]),
+            TileDef::new("deep_water", 3, "Deep water", false, [30, 60, 140]),
+            TileDef::new("forest_floor", 4, "Forest floor", true, [50, 100, 40]),
+            TileDef::new("snow", 5, "Snow", true, [240, 245, 250]),
+            TileDef::new("stone", 6, "Stone", false, [130, 130, 135]),
+            TileDef::new("dirt", 7, "Dirt", true, [120, 85, 55]),
+            TileDef::new("tree", 8, "Tree", false, [30, 80, 30]),
+            TileDef::new("wall", 9, "Wall", false, [150, 140, 125]),
+            TileDef::new("sign", 10, "Sign", false, [170, 130, 80]).with_entity(TileEntity::Sign {
+                text: String::new(),
+            }),
+            TileDef::new("chest", 11, "Chest", false, [140, 95, 45])
+                .with_entity(TileEntity::Container { items: Vec::new() }),
+            TileDef::new("door", 12, "Door", true, [110, 70, 40])
+                .with_entity(TileEntity::Door { open: false }),
+        ])
+        .unwrap()
+    }
+}
+
+impl TryFrom<Vec<TileDef>> for TileRegistry {
+    type Error = TileRegistryError;
+
+    fn try_from(tiles: Vec<TileDef>) -> Result<Self, Self::Error> {
+        Self::new(tiles)
+    }
+}
+
+impl From<TileRegistry> for Vec<TileDef> {
+    fn from(registry: TileRegistry) -> Self {
+        registry.tiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_agree() {
+        let registry = TileRegistry::default();
+
+        let water = registry.resolve("Deep_Water").unwrap();
+
+        assert_eq!(registry.get(water).unwrap().id, "deep_water");
+        assert_eq!(registry.id_of(TileId(999)), "#999");
+        assert_eq!(
+            registry.resolve("lava"),
+            Err(TileRegistryError::UnknownTile("lava".to_string()))
+        );
+    }
+
+    #[test]
+    fn ids_must_be_unique() {
+        let grass = TileDef::new("grass", 0, "Grass", true, [0, 0, 0]);
+
+        assert_eq!(
+            TileRegistry::new(vec![
+                grass.clone(),
+                TileDef::new("GRASS", 1, "Grass", true, [0, 0, 0])
+            ])
+            .unwrap_err(),
+            TileRegistryError::DuplicateId("GRASS".to_string())
+        );
+        assert_eq!(
+            TileRegistry::new(vec![
+                grass,
+                TileDef::new("sand", 0, "Sand", true, [0, 0, 0])
+            ])
+            .unwrap_err(),
+            TileRegistryError::DuplicateNumericId(0)
+        );
+    }
+
+    #[test]
+    fn round_trips_through_bincode() {
+        let registry = TileRegistry::default();
+
+        let encoded =
+            bincode::serde::encode_to_vec(&registry, bincode::config::standard()).unwrap();
+        let decoded: TileRegistry =
+            bincode::serde::decode_from_slice(&encoded, bincode::config::standard())
+                .unwrap()
+                .0;
+
+        assert_eq!(decoded.tiles(), registry.tiles());
+    }
 }
@@ -1,53 +1,431 @@
-use bincode::{Decode, Encode};
-use noise::{
-    utils::{NoiseMapBuilder, PlaneMapBuilder},
-    Perlin, Seedable,
-};
+use std::{collections::HashMap, num::NonZeroUsize, panic, thread};
 
-use crate::Position;
+use bincode::{Decode, Encode};
 
-use super::{Chunk, Tile, TileType, CHUNK_SIZE};
+use super::{
+    Chunk, ChunkPos, Claim, HeightChange, Layer, Tile, TileEntity, TilePos, TileRegistry,
+    WorldGenerator,
+};
 
 #[derive(Debug, Clone, Encode, Decode)]
 pub struct Player {
     pub username: String,
+    /// The name of the world the player is in.
+    pub world: String,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+/// A world without edges, chunks are generated from the seed wherever they are needed.
+#[derive(Debug, Clone, Default, Encode, Decode)]
 pub struct World {
+    /// The chunks that have been generated, by chunk position.
     #[bincode(with_serde)]
-    pub chunks: ndarray::Array2<Chunk>,
+    pub chunks: HashMap<ChunkPos, Chunk>,
+
+    pub seed: u32,
 
-    pub width: usize,
-    pub height: usize,
+    #[bincode(with_serde)]
+    pub claims: Vec<Claim>,
 }
 
 impl World {
-    pub fn new(width: usize, height: usize) -> Self {
-        let mut chunks =
-            ndarray::Array2::from_shape_fn((width, height), |(x, y)| Chunk::new(Position { x, y }));
-
-        let perlin = Perlin::default().set_seed(rand::random());
-
-        let map = PlaneMapBuilder::new(&perlin)
-            .set_size(width * CHUNK_SIZE, height * CHUNK_SIZE)
-            .set_x_bounds(0.0, 1.0)
-            .set_y_bounds(0.0, 1.0)
-            .build();
-
-        for ((chunk_x, chunk_y), chunk) in chunks.indexed_iter_mut() {
-            for ((tile_x, tile_y), tile) in chunk.tiles.indexed_iter_mut() {
-                if map.get_value(chunk_x * CHUNK_SIZE + tile_x, chunk_y * CHUNK_SIZE + tile_y) > 0.2 {
-                    tile.ty = TileType::Sand;
+    pub fn new(seed: u32) -> Self {
+        Self {
+            chunks: HashMap::new(),
+
+            seed,
+
+            claims: Vec::new(),
+        }
+    }
+
+    pub fn chunk(&self, position: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&position)
+    }
+
+    /// The chunk at a chunk position, generated first if it does not exist yet.
+    pub fn load_chunk(&mut self, position: ChunkPos, generator: &dyn WorldGenerator) -> &mut Chunk {
+        let seed = self.seed;
+
+        self.chunks
+            .entry(position)
+            .or_insert_with(|| generator.generate(seed, position))
+    }
+
+    /// Generates the chunks in `positions` that do not exist yet, spread over a thread
+    /// per core.
+    pub fn load_chunks(&mut self, positions: &[ChunkPos], generator: &dyn WorldGenerator) {
+        let mut missing: Vec<ChunkPos> = positions
+            .iter()
+            .copied()
+            .filter(|position| !self.chunks.contains_key(position))
+            .collect();
+
+        missing.sort();
+        missing.dedup();
+
+        if missing.is_empty() {
+            return;
+        }
+
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let batch_size = missing.len().div_ceil(threads);
+
+        let seed = self.seed;
+
+        let generated: Vec<Chunk> = thread::scope(|scope| {
+            let handles: Vec<_> = missing
+                .chunks(batch_size)
+                .map(|batch| {
+                    scope.spawn(move || {
+                        batch
+                            .iter()
+                            .map(|&position| generator.generate(seed, position))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|err| panic::resume_unwind(err))
+                })
+                .collect()
+        });
+
+        for chunk in generated {
+            self.chunks.insert(chunk.position, chunk);
+        }
+    }
+
+    /// The tile in a layer at a position, `None` if the layer is empty there or its
+    /// chunk has not been generated.
+    pub fn tile(&self, position: TilePos, layer: Layer) -> Option<Tile> {
+        self.chunk(position.chunk())?.tile(position.local(), layer)
+    }
+
+    /// Replaces the tile in a layer at a position and returns the old one, `None` if
+    /// its chunk has not been generated.
+    pub fn set_tile(
+        &mut self,
+        position: TilePos,
+        layer: Layer,
+        tile: Option<Tile>,
+    ) -> Option<Option<Tile>> {
+        Some(
+            self.chunks
+                .get_mut(&position.chunk())?
+                .set_tile(position.local(), layer, tile),
+        )
+    }
+
+    /// The tile in a layer at a position, generating its chunk if needed.
+    pub fn load_tile(
+        &mut self,
+        position: TilePos,
+        layer: Layer,
+        generator: &dyn WorldGenerator,
+    ) -> Option<Tile> {
+        self.load_chunk(position.chunk(), generator)
+            .tile(position.local(), layer)
+    }
+
+    /// Replaces the tile in a layer at a position and returns the old one, generating its
+    /// chunk first if needed.
+    pub fn load_and_set_tile(
+        &mut self,
+        position: TilePos,
+        layer: Layer,
+        tile: Option<Tile>,
+        generator: &dyn WorldGenerator,
+    ) -> Option<Tile> {
+        self.load_chunk(position.chunk(), generator)
+            .set_tile(position.local(), layer, tile)
+    }
+
+    /// The highest layer with a tile at a position, if its chunk has been generated.
+    pub fn top(&self, position: TilePos) -> Option<(Layer, Tile)> {
+        self.chunk(position.chunk())?.top(position.local())
+    }
+
+    /// The height of a tile, `None` if its chunk has not been generated.
+    pub fn height(&self, position: TilePos) -> Option<u8> {
+        Some(self.chunk(position.chunk())?.height(position.local()))
+    }
+
+    /// Changes the height of a tile and returns its old height, `None` if its chunk has
+    /// not been generated.
+    pub fn change_height(&mut self, position: TilePos, change: HeightChange) -> Option<u8> {
+        let old = self.height(position)?;
+
+        self.set_height(position, change.apply(old))
+    }
+
+    /// Sets the height of a tile, at most `MAX_HEIGHT`, and returns its old height,
+    /// `None` if its chunk has not been generated.
+    pub fn set_height(&mut self, position: TilePos, height: u8) -> Option<u8> {
+        let chunk = self.chunks.get_mut(&position.chunk())?;
+
+        let old = chunk.height(position.local());
+        chunk.set_height(position.local(), height);
+
+        Some(old)
+    }
+
+    /// The entity of the tile at a position, if its chunk has been generated.
+    pub fn entity(&self, position: TilePos) -> Option<&TileEntity> {
+        self.chunk(position.chunk())?.entity(position.local())
+    }
+
+    pub fn entity_mut(&mut self, position: TilePos) -> Option<&mut TileEntity> {
+        self.chunks
+            .get_mut(&position.chunk())?
+            .entity_mut(position.local())
+    }
+
+    /// Replaces the entity at a position and returns the old one, `None` if its chunk
+    /// has not been generated. Whether the tiles there want that entity is not checked.
+    pub fn set_entity(
+        &mut self,
+        position: TilePos,
+        entity: Option<TileEntity>,
+    ) -> Option<Option<TileEntity>> {
+        let chunk = self.chunks.get_mut(&position.chunk())?;
+
+        Some(match entity {
+            Some(entity) => chunk.entities.insert(position.local(), entity),
+            None => chunk.entities.remove(&position.local()),
+        })
+    }
+
+    /// Adds or removes the entity at a position to match its tiles after they changed,
+    /// see `Chunk::update_entity`.
+    pub fn update_entity(&mut self, position: TilePos, tiles: &TileRegistry) {
+        if let Some(chunk) = self.chunks.get_mut(&position.chunk()) {
+            chunk.update_entity(position.local(), tiles);
+        }
+    }
+
+    /// The claim covering a tile, claims never overlap.
+    pub fn claim_at(&self, position: TilePos) -> Option<&Claim> {
+        self.claims.iter().find(|claim| claim.contains(position))
+    }
+
+    /// A copy without any chunks, clients request the chunks they need separately.
+    pub fn without_chunks(&self) -> Self {
+        Self {
+            chunks: HashMap::new(),
+
+            seed: self.seed,
+
+            claims: self.claims.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        fnv1a, BiomeGenerator, CheckerboardGenerator, LocalPos, TileRegistry, CHUNK_SIZE,
+    };
+
+    /// Hashes every tile of the chunks from `min` to `max` in row order, so any change to
+    /// generation shows up. Tiles within a chunk go column by column, the order chunks
+    /// were stored in when the hashes were pinned.
+    fn tile_hash(world: &World, min: i32, max: i32) -> u32 {
+        let generator = BiomeGenerator::default();
+
+        let mut tiles = Vec::new();
+
+        for y in min..=max {
+            for x in min..=max {
+                let chunk = generator.generate(world.seed, ChunkPos::new(x, y));
+
+                for x in 0..CHUNK_SIZE {
+                    for y in 0..CHUNK_SIZE {
+                        let tile = chunk.tile(LocalPos::new(x, y).unwrap(), Layer::Ground);
+
+                        tiles.push(tile.map_or(u8::MAX, |tile| tile.ty.0 as u8));
+                    }
                 }
             }
         }
 
-        Self {
-            chunks,
+        fnv1a(&tiles)
+    }
+
+    #[test]
+    fn same_seed_same_world() {
+        let a = World::new(1234);
+        let b = World::new(1234);
+
+        assert_eq!(tile_hash(&a, -2, 2), tile_hash(&b, -2, 2));
+    }
+
+    #[test]
+    fn different_seed_different_world() {
+        assert_ne!(
+            tile_hash(&World::new(1), -2, 2),
+            tile_hash(&World::new(2), -2, 2)
+        );
+    }
+
+    #[test]
+    fn loaded_chunks_match_generated_ones() {
+        let generator = BiomeGenerator::default();
+        let mut world = World::new(7);
+
+        let position = TilePos::new(-13, 5);
+        let tile = world.load_tile(position, Layer::Ground, &generator);
+
+        let chunk = generator.generate(7, ChunkPos::new(-2, 0));
+
+        assert!(tile.is_some());
+        assert_eq!(
+            tile,
+            chunk.tile(LocalPos::new(11, 5).unwrap(), Layer::Ground)
+        );
+        assert_eq!(world.chunks.len(), 1);
+    }
+
+    #[test]
+    fn parallel_loading_matches_one_by_one() {
+        let generator = BiomeGenerator::default();
+
+        let positions: Vec<ChunkPos> = (-4..4)
+            .flat_map(|y| (-4..4).map(move |x| ChunkPos::new(x, y)))
+            .collect();
+
+        let mut parallel = World::new(11);
+        parallel.load_chunks(&positions, &generator);
+
+        let mut sequential = World::new(11);
+
+        for &position in &positions {
+            sequential.load_chunk(position, &generator);
+        }
+
+        assert_eq!(parallel.chunks.len(), positions.len());
+
+        for position in positions {
+            for local in LocalPos::all() {
+                assert_eq!(
+                    parallel.chunks[&position].tile(local, Layer::Ground),
+                    sequential.chunks[&position].tile(local, Layer::Ground)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn loading_keeps_existing_chunks() {
+        let tiles = TileRegistry::default();
+        let checkerboard = CheckerboardGenerator::new(&tiles).unwrap();
+
+        let mut world = World::new(3);
+        let position = ChunkPos::new(2, -1);
+
+        world.load_chunk(position, &checkerboard);
+        world.load_chunks(&[position, ChunkPos::new(0, 0)], &BiomeGenerator::default());
+
+        assert_eq!(
+            world.chunks[&position]
+                .tile(LocalPos::ORIGIN, Layer::Ground)
+                .unwrap()
+                .ty,
+            tiles.resolve("stone").unwrap()
+        );
+        assert_eq!(world.chunks.len(), 2);
+    }
+
+    #[test]
+    fn tiles_of_missing_chunks_are_none() {
+        let mut world = World::new(7);
+        let position = TilePos::new(3, 3);
+        let tree = Tile {
+            ty: TileRegistry::default().resolve("tree").unwrap(),
+        };
+
+        assert!(world.tile(position, Layer::Ground).is_none());
+        assert!(world
+            .set_tile(position, Layer::Object, Some(tree))
+            .is_none());
+
+        world.load_chunk(ChunkPos::new(0, 0), &BiomeGenerator::default());
+
+        assert_eq!(
+            world.set_tile(position, Layer::Object, Some(tree)),
+            Some(None)
+        );
+        assert_eq!(world.top(position), Some((Layer::Object, tree)));
+        assert!(world.tile(position, Layer::Ground).is_some());
+    }
+
+    #[test]
+    fn entities_follow_their_tiles() {
+        let tiles = TileRegistry::default();
+        let tile = |id| {
+            Some(Tile {
+                ty: tiles.resolve(id).unwrap(),
+            })
+        };
+
+        let mut world = World::new(7);
+        let position = TilePos::new(-4, 9);
+
+        world.load_chunk(position.chunk(), &BiomeGenerator::default());
+
+        world.set_tile(position, Layer::Object, tile("sign"));
+        world.update_entity(position, &tiles);
+
+        *world.entity_mut(position).unwrap() = TileEntity::Sign {
+            text: "Welcome".to_string(),
+        };
+
+        // changing the ground under the sign keeps its text
+        world.set_tile(position, Layer::Ground, tile("sand"));
+        world.update_entity(position, &tiles);
+
+        assert_eq!(
+            world.entity(position),
+            Some(&TileEntity::Sign {
+                text: "Welcome".to_string()
+            })
+        );
+
+        world.set_tile(position, Layer::Object, tile("door"));
+        world.update_entity(position, &tiles);
+
+        assert_eq!(
+            world.entity(position),
+            Some(&TileEntity::Door { open: false })
+        );
+
+        world.set_tile(position, Layer::Object, None);
+        world.update_entity(position, &tiles);
+
+        assert_eq!(world.entity(position), None);
+    }
 
-            width,
-            height,
+    #[test]
+    fn golden_worlds() {
+        for (seed, min, max, expected) in [
+            (0, 0, 1, 3_013_194_642),
+            (1, -2, 1, 3_886_466_178),
+            (42, -2, 1, 844_704_139),
+            (3_735_928_559, -5, 5, 2_205_173_448),
+        ] {
+            assert_eq!(
+                tile_hash(&World::new(seed), min, max),
+                expected,
+                "chunks {} to {} with seed {}",
+                min,
+                max,
+                seed
+            );
         }
     }
 }
@@ -0,0 +1,357 @@
+use std::{
+    collections::BTreeSet,
+    fmt, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("'{0}' is not an IP address or CIDR range")]
+pub struct InvalidAddress(String);
+
+/// An IP address or a range of them, written like `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// How many leading bits are fixed, 0 covers every address.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+
+                u32::from(range) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+
+                u128::from(range) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidAddress;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidAddress(s.to_string());
+
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+
+        let address = address.parse::<IpAddr>().map_err(|_| invalid())?;
+
+        let max = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+
+        if prefix > max {
+            return Err(invalid());
+        }
+
+        Ok(Self { address, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = InvalidAddress;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.address, self.prefix) {
+            (IpAddr::V4(_), 32) | (IpAddr::V6(_), 128) => write!(f, "{}", self.address),
+            _ => write!(f, "{}/{}", self.address, self.prefix),
+        }
+    }
+}
+
+/// Keeps a username, an address or both out of the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub username: Option<String>,
+    pub address: Option<Cidr>,
+    pub reason: String,
+    /// Who issued the ban.
+    pub by: String,
+    pub created: u64,
+    /// Unix time the ban is lifted, `None` if it is permanent.
+    pub expires: Option<u64>,
+}
+
+impl Ban {
+    pub fn matches(&self, username: &str, address: IpAddr) -> bool {
+        let username = match &self.username {
+            Some(banned) => banned.eq_ignore_ascii_case(username),
+            None => false,
+        };
+
+        let address = match &self.address {
+            Some(banned) => banned.contains(address),
+            None => false,
+        };
+
+        username || address
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// The username or address the ban is for.
+    pub fn target(&self) -> String {
+        match (&self.username, &self.address) {
+            (Some(username), Some(address)) => format!("{} ({})", username, address),
+            (Some(username), None) => username.clone(),
+            (None, Some(address)) => address.to_string(),
+            (None, None) => "nobody".to_string(),
+        }
+    }
+}
+
+/// The whitelist and ban list, saved as RON.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Access {
+    /// Only lets in players on the whitelist when enabled.
+    pub whitelist_enabled: bool,
+    /// Lowercase usernames.
+    pub whitelist: BTreeSet<String>,
+    pub bans: Vec<Ban>,
+
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Access {
+    /// Loads the access lists at `path`, starting empty if it does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        let mut access = if path.exists() {
+            ron::from_str::<Self>(&fs::read_to_string(path)?)?
+        } else {
+            Self::default()
+        };
+
+        access.path = path.to_path_buf();
+
+        Ok(access)
+    }
+
+    /// Drops expired bans and writes the lists to disk.
+    pub fn save(&mut self, now: u64) -> crate::Result<()> {
+        self.bans.retain(|ban| !ban.is_expired(now));
+
+        let serialized = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        fs::write(&self.path, serialized)?;
+
+        Ok(())
+    }
+
+    /// Checks whether `username` connecting from `address` may join.
+    pub fn check(
+        &self,
+        username: &str,
+        address: IpAddr,
+        now: u64,
+    ) -> Result<(), common::JoinRejection> {
+        if let Some(ban) = self
+            .bans
+            .iter()
+            .find(|ban| !ban.is_expired(now) && ban.matches(username, address))
+        {
+            return Err(common::JoinRejection::Banned {
+                reason: ban.reason.clone(),
+                remaining: ban.expires.map(|expires| expires - now),
+            });
+        }
+
+        if self.whitelist_enabled && !self.whitelist.contains(&username.to_lowercase()) {
+            return Err(common::JoinRejection::NotWhitelisted);
+        }
+
+        Ok(())
+    }
+
+    /// Removes every ban for the username or address `target`, returning how many there were.
+    pub fn unban(&mut self, target: &str) -> usize {
+        let address = target.parse::<Cidr>().ok();
+
+        let count = self.bans.len();
+
+        self.bans.retain(|ban| {
+            let username = ban
+                .username
+                .as_ref()
+                .is_some_and(|username| username.eq_ignore_ascii_case(target));
+
+            !username && (address.is_none() || ban.address != address)
+        });
+
+        count - self.bans.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn ipv4_ranges_contain_their_addresses() {
+        let range = cidr("10.1.0.0/16");
+        assert!(range.contains(ip("10.1.0.0")));
+        assert!(range.contains(ip("10.1.255.255")));
+        assert!(!range.contains(ip("10.2.0.0")));
+        assert!(!range.contains(ip("::1")));
+        // clients on dual stack sockets show up as mapped addresses
+        assert!(range.contains(ip("::ffff:10.1.2.3")));
+
+        let single = cidr("192.168.0.7");
+        assert_eq!(single, cidr("192.168.0.7/32"));
+        assert!(single.contains(ip("192.168.0.7")));
+        assert!(!single.contains(ip("192.168.0.8")));
+
+        let all = cidr("0.0.0.0/0");
+        assert!(all.contains(ip("1.2.3.4")));
+        assert!(all.contains(ip("255.255.255.255")));
+        assert!(!all.contains(ip("::2")));
+    }
+
+    #[test]
+    fn ipv6_ranges_contain_their_addresses() {
+        let range = cidr("2001:db8::/32");
+        assert!(range.contains(ip("2001:db8::1")));
+        assert!(range.contains(ip("2001:db8:ffff::")));
+        assert!(!range.contains(ip("2001:db9::")));
+        assert!(!range.contains(ip("10.0.0.1")));
+
+        assert_eq!(cidr("::1"), cidr("::1/128"));
+        assert!(cidr("::1").contains(ip("::1")));
+        assert!(!cidr("::1").contains(ip("::2")));
+
+        assert!(cidr("::/0").contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn malformed_ranges_are_rejected() {
+        for malformed in [
+            "",
+            "localhost",
+            "10.0.0",
+            "10.0.0.256",
+            "10.0.0.0/",
+            "10.0.0.0/33",
+            "10.0.0.0/-1",
+            "10.0.0.0/8/8",
+            "::/129",
+            "/8",
+        ] {
+            assert_eq!(
+                malformed.parse::<Cidr>(),
+                Err(InvalidAddress(malformed.to_string())),
+                "{}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn ranges_are_written_as_parsed() {
+        for written in ["10.0.0.0/8", "10.0.0.1", "2001:db8::/32", "::1"] {
+            assert_eq!(cidr(written).to_string(), written);
+        }
+    }
+
+    #[test]
+    fn only_bans_that_did_not_expire_keep_players_out() {
+        let ban = |username: &str, expires| Ban {
+            username: Some(username.to_string()),
+            address: None,
+            reason: "griefing".to_string(),
+            by: "alice".to_string(),
+            created: 100,
+            expires,
+        };
+
+        let access = Access {
+            bans: vec![ban("bob", None), ban("carol", Some(200))],
+            ..Access::default()
+        };
+        let address = ip("10.0.0.1");
+
+        assert_eq!(
+            access.check("Bob", address, 1_000_000),
+            Err(common::JoinRejection::Banned {
+                reason: "griefing".to_string(),
+                remaining: None,
+            })
+        );
+        assert_eq!(
+            access.check("carol", address, 150),
+            Err(common::JoinRejection::Banned {
+                reason: "griefing".to_string(),
+                remaining: Some(50),
+            })
+        );
+        assert_eq!(access.check("carol", address, 200), Ok(()));
+        assert_eq!(access.check("dave", address, 150), Ok(()));
+    }
+
+    #[test]
+    fn address_bans_cover_their_range() {
+        let access = Access {
+            bans: vec![Ban {
+                username: None,
+                address: Some(cidr("10.0.0.0/8")),
+                reason: String::new(),
+                by: "alice".to_string(),
+                created: 0,
+                expires: None,
+            }],
+            ..Access::default()
+        };
+
+        assert!(access.check("bob", ip("10.20.30.40"), 0).is_err());
+        assert_eq!(access.check("bob", ip("11.0.0.1"), 0), Ok(()));
+    }
+}
@@ -0,0 +1,275 @@
+//! An append-only record of every tile change, used to find and undo griefing.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use common::world::{Change, TilePos, TileRegistry};
+
+use crate::worlds::Worlds;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edit {
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    /// The name of the world the tile is in.
+    pub world: String,
+    pub player: String,
+    pub position: TilePos,
+    pub change: Change,
+}
+
+/// How many of the latest edits are kept in memory for `/history` and `/rollback`,
+/// older ones are only in the file.
+const MAX_EDITS: usize = 100_000;
+
+/// The latest edits in memory, every edit is also written to a file with one RON edit
+/// per line.
+#[derive(Debug)]
+pub struct AuditLog {
+    edits: VecDeque<Edit>,
+    capacity: usize,
+    file: File,
+}
+
+impl AuditLog {
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        Self::open(path.as_ref(), MAX_EDITS)
+    }
+
+    /// Opens the log at `path`, keeping up to `capacity` of its latest edits in memory.
+    fn open(path: &Path, capacity: usize) -> crate::Result<Self> {
+        let mut audit = Self {
+            edits: VecDeque::new(),
+            capacity,
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+        };
+
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            match ron::from_str::<Edit>(&line?) {
+                Ok(edit) => audit.push(edit),
+                Err(err) => log::warn!("skipping line {} of {}: {}", i + 1, path.display(), err),
+            }
+        }
+
+        Ok(audit)
+    }
+
+    fn push(&mut self, edit: Edit) {
+        if self.edits.len() == self.capacity {
+            self.edits.pop_front();
+        }
+
+        self.edits.push_back(edit);
+    }
+
+    /// Makes sure every recorded edit is on disk.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.file.sync_data()
+    }
+
+    pub fn record(&mut self, world: &str, player: &str, position: TilePos, change: Change) {
+        let edit = Edit {
+            time: now(),
+            world: world.to_string(),
+            player: player.to_string(),
+            position,
+            change,
+        };
+
+        match ron::to_string(&edit) {
+            Ok(line) => {
+                if let Err(err) = writeln!(self.file, "{}", line) {
+                    log::warn!("failed to write audit log: {}", err);
+                }
+            }
+            Err(err) => log::warn!("failed to serialize edit: {}", err),
+        }
+
+        self.push(edit);
+    }
+
+    /// Edits of the tile at `position` in `world`, newest first.
+    pub fn history<'a>(
+        &'a self,
+        world: &'a str,
+        position: TilePos,
+    ) -> impl Iterator<Item = &'a Edit> {
+        self.edits
+            .iter()
+            .rev()
+            .filter(move |edit| edit.world == world && edit.position == position)
+    }
+
+    /// Undoes the edits `player` made in the last `seconds`, newest first.
+    ///
+    /// A tile is only restored while it still is what the player made it, so later
+    /// edits by others are kept. Restored tiles are recorded as edits by `by`, returns
+    /// how many were restored and the worlds and chunk positions they are in.
+    pub fn rollback(
+        &mut self,
+        worlds: &mut Worlds,
+        tiles: &TileRegistry,
+        player: &str,
+        seconds: u64,
+        by: &str,
+    ) -> (usize, BTreeSet<(String, common::world::ChunkPos)>) {
+        let since = now().saturating_sub(seconds);
+
+        let edits = self
+            .edits
+            .iter()
+            .rev()
+            .take_while(|edit| edit.time >= since)
+            .filter(|edit| edit.player.eq_ignore_ascii_case(player))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut chunks = BTreeSet::new();
+        let mut restored = 0;
+
+        for edit in edits {
+            let world = match worlds.get_mut(&edit.world) {
+                Some(world) => world,
+                None => continue,
+            };
+
+            if !edit.change.is_current(world, edit.position) {
+                continue;
+            }
+
+            let undo = edit.change.reversed();
+            undo.apply(world, edit.position, tiles);
+
+            self.record(&edit.world, by, edit.position, undo);
+
+            chunks.insert((edit.world, edit.position.chunk()));
+            restored += 1;
+        }
+
+        (restored, chunks)
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use common::world::{Layer, Tile, TileId};
+
+    use super::*;
+
+    #[test]
+    fn only_the_latest_edits_are_kept() {
+        let path = env::temp_dir().join(format!("audit-{}.log", std::process::id()));
+
+        let mut log = AuditLog::open(&path, 3).unwrap();
+
+        for x in 0..5 {
+            let change = Change::Tile {
+                layer: Layer::Ground,
+                old: None,
+                new: Some(Tile { ty: TileId(x) }),
+            };
+
+            log.record("overworld", "carol", TilePos::new(0, 0), change);
+        }
+
+        let kept = |log: &AuditLog| {
+            log.history("overworld", TilePos::new(0, 0))
+                .map(|edit| match edit.change {
+                    Change::Tile { new, .. } => new.unwrap().ty.0,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(kept(&log), [4, 3, 2]);
+
+        // the file still has every edit, loading it keeps the latest again
+        log.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 5);
+        assert_eq!(kept(&AuditLog::open(&path, 2).unwrap()), [4, 3]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn heights_are_rolled_back() {
+        let mut state = crate::tests::state("heights");
+        let position = TilePos::new(3, 4);
+
+        assert!(state.set_height("test", "alice", position, 5));
+
+        let crate::State {
+            worlds,
+            audit,
+            tiles,
+            ..
+        } = &mut state;
+
+        let (restored, chunks) = audit.rollback(worlds, tiles, "alice", 60, "bob");
+
+        assert_eq!(restored, 1);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(worlds.get("test").unwrap().height(position), Some(0));
+        assert_eq!(
+            audit
+                .history("test", position)
+                .map(|edit| edit.change.clone())
+                .collect::<Vec<_>>(),
+            [
+                Change::Height { old: 5, new: 0 },
+                Change::Height { old: 0, new: 5 }
+            ]
+        );
+    }
+
+    #[test]
+    fn entity_edits_are_rolled_back() {
+        let mut state = crate::tests::state("entities");
+        let position = TilePos::new(1, 1);
+
+        let sign = |text: &str| common::world::TileEntity::Sign {
+            text: text.to_string(),
+        };
+        let sign_id = state.tiles.resolve("sign").unwrap();
+
+        state.set_tile(
+            "test",
+            "alice",
+            position,
+            Layer::Object,
+            Some(Tile { ty: sign_id }),
+        );
+        assert!(state.set_entity("test", "bob", position, Some(sign("griefed"))));
+
+        let crate::State {
+            worlds,
+            audit,
+            tiles,
+            ..
+        } = &mut state;
+
+        // only bob's edit is undone, the sign alice placed stays
+        assert_eq!(audit.rollback(worlds, tiles, "bob", 60, "alice").0, 1);
+        assert_eq!(
+            worlds.get("test").unwrap().entity(position),
+            Some(&sign(""))
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{
+    access::{Ban, Cidr},
+    audit, permissions, State,
+};
+
+use super::{
+    Argument, Command, CommandError, Commands, Context, Parameter, ParameterKind, Response,
+};
+
+/// Registers the commands for banning players and managing the whitelist.
+pub fn register_access(commands: &mut Commands) {
+    commands.register(
+        Command::new(
+            "ban",
+            "Bans a username, IP address or range",
+            |ctx, args| match args {
+                [Argument::Word(target), rest @ ..] => {
+                    let reason = match rest {
+                        [Argument::Text(reason)] => reason.clone(),
+                        _ => "Banned by a moderator".to_string(),
+                    };
+
+                    ban(ctx, target, None, reason)
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_BAN)
+        .parameter(Parameter::required("target", ParameterKind::Word))
+        .parameter(Parameter::optional("reason", ParameterKind::Text)),
+    );
+
+    commands.register(
+        Command::new(
+            "tempban",
+            "Bans a username, IP address or range for a while",
+            |ctx, args| match args {
+                [Argument::Word(target), Argument::Duration(duration), rest @ ..] => {
+                    let reason = match rest {
+                        [Argument::Text(reason)] => reason.clone(),
+                        _ => "Banned by a moderator".to_string(),
+                    };
+
+                    ban(ctx, target, Some(duration.as_secs()), reason)
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_BAN)
+        .parameter(Parameter::required("target", ParameterKind::Word))
+        .parameter(Parameter::required("time", ParameterKind::Duration))
+        .parameter(Parameter::optional("reason", ParameterKind::Text)),
+    );
+
+    commands.register(
+        Command::new(
+            "unban",
+            "Lifts the bans of a username, IP address or range",
+            |ctx, args| match args {
+                [Argument::Word(target)] => {
+                    let count = ctx.state.access.unban(target);
+
+                    if count == 0 {
+                        return Err(CommandError::Failed(format!("{} is not banned", target)));
+                    }
+
+                    save(ctx.state)?;
+
+                    log::info!("{} unbanned {}", ctx.username(), target);
+
+                    Ok(vec![Response::Reply(format!("Unbanned {}", target))])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_BAN)
+        .parameter(Parameter::required("target", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new("bans", "Lists active bans", |ctx, _| {
+            let now = audit::now();
+
+            let bans = ctx
+                .state
+                .access
+                .bans
+                .iter()
+                .filter(|ban| !ban.is_expired(now))
+                .map(|ban| {
+                    let expires = match ban.expires {
+                        Some(expires) => format!("for {}s", expires - now),
+                        None => "forever".to_string(),
+                    };
+
+                    Response::Reply(format!(
+                        "{} by {} {}: {}",
+                        ban.target(),
+                        ban.by,
+                        expires,
+                        ban.reason
+                    ))
+                })
+                .collect::<Vec<_>>();
+
+            if bans.is_empty() {
+                return Ok(vec![Response::Reply("Nobody is banned".to_string())]);
+            }
+
+            Ok(bans)
+        })
+        .permission(permissions::ADMIN_BAN),
+    );
+
+    commands.register(
+        Command::new(
+            "whitelist",
+            "Turns the whitelist on or off, or changes who is on it",
+            |ctx, args| {
+                let access = &mut ctx.state.access;
+
+                let reply = match args {
+                    [Argument::Word(action)] if action == "on" => {
+                        access.whitelist_enabled = true;
+                        "The whitelist is on".to_string()
+                    }
+                    [Argument::Word(action)] if action == "off" => {
+                        access.whitelist_enabled = false;
+                        "The whitelist is off".to_string()
+                    }
+                    [Argument::Word(action)] if action == "list" => {
+                        return Ok(vec![Response::Reply(format!(
+                            "The whitelist is {} with {} players: {}",
+                            if access.whitelist_enabled {
+                                "on"
+                            } else {
+                                "off"
+                            },
+                            access.whitelist.len(),
+                            access
+                                .whitelist
+                                .iter()
+                                .cloned()
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ))]);
+                    }
+                    [Argument::Word(action), Argument::Word(username)] if action == "add" => {
+                        common::validate_username(username)
+                            .map_err(|err| CommandError::Failed(err.to_string()))?;
+
+                        access.whitelist.insert(username.to_lowercase());
+                        format!("Added {} to the whitelist", username)
+                    }
+                    [Argument::Word(action), Argument::Word(username)] if action == "remove" => {
+                        if !access.whitelist.remove(&username.to_lowercase()) {
+                            return Err(CommandError::Failed(format!(
+                                "{} is not on the whitelist",
+                                username
+                            )));
+                        }
+
+                        format!("Removed {} from the whitelist", username)
+                    }
+                    _ => {
+                        return Err(CommandError::Usage(
+                            "/whitelist <on|off|list|add|remove> [username]".to_string(),
+                        ))
+                    }
+                };
+
+                save(ctx.state)?;
+
+                log::info!("{}: {}", ctx.username(), reply);
+
+                Ok(vec![Response::Reply(reply), Response::AccessModified])
+            },
+        )
+        .permission(permissions::ADMIN_WHITELIST)
+        .parameter(Parameter::required("action", ParameterKind::Word))
+        .parameter(Parameter::optional("username", ParameterKind::Word)),
+    );
+}
+
+/// Bans `target`, which is an address or range if it parses as one and a username otherwise.
+///
+/// Players can only ban usernames of a lower role than their own, and no one can ban
+/// every address or a range they are in themselves.
+fn ban(
+    ctx: &mut Context,
+    target: &str,
+    seconds: Option<u64>,
+    reason: String,
+) -> Result<Vec<Response>, CommandError> {
+    let (username, address) = match target.parse::<Cidr>() {
+        Ok(address) if address.prefix() == 0 => {
+            return Err(CommandError::Failed(format!(
+                "{} would ban everyone",
+                address
+            )));
+        }
+        Ok(address) if ctx.address().is_some_and(|own| address.contains(own)) => {
+            return Err(CommandError::Failed(format!(
+                "{} would ban yourself",
+                address
+            )));
+        }
+        Ok(address) => (None, Some(address)),
+        Err(_) => {
+            common::validate_username(target).map_err(|_| {
+                CommandError::Failed(format!("{} is not a username, IP address or range", target))
+            })?;
+
+            if !ctx.outranks(target) {
+                return Err(CommandError::Failed(format!(
+                    "You can't ban {}, their role is not below yours",
+                    target
+                )));
+            }
+
+            (Some(target.to_string()), None)
+        }
+    };
+
+    let by = ctx.username().to_string();
+    let state = &mut *ctx.state;
+
+    let now = audit::now();
+
+    let ban = Ban {
+        username,
+        address,
+        reason,
+        by,
+        created: now,
+        expires: seconds.map(|seconds| now + seconds),
+    };
+
+    log::info!("{} banned {}: {}", ban.by, ban.target(), ban.reason);
+
+    let reply = format!("Banned {}", ban.target());
+
+    state.access.bans.push(ban);
+    save(state)?;
+
+    Ok(vec![Response::Reply(reply), Response::AccessModified])
+}
+
+fn save(state: &mut State) -> Result<(), CommandError> {
+    state
+        .access
+        .save(audit::now())
+        .map_err(|err| CommandError::Failed(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::register_builtin, permissions::Role, plugin::Plugins, tests::state};
+
+    /// `state` with the moderator `mod` in slot 1, joined from 10.1.2.3.
+    fn moderated(name: &str) -> State {
+        let mut state = state(name);
+
+        state.players[1] = Some(common::world::Player {
+            username: "mod".to_string(),
+            world: "test".to_string(),
+        });
+        state.addresses[1] = "10.1.2.3".parse().ok();
+        state.permissions.set_role("mod", Role::Moderator);
+        state.permissions.set_role("other_mod", Role::Moderator);
+
+        state
+    }
+
+    fn commands() -> Commands {
+        let mut commands = Commands::new();
+        register_builtin(&mut commands);
+        register_access(&mut commands);
+
+        commands
+    }
+
+    #[test]
+    fn only_lower_roles_can_be_banned_by_name() {
+        let mut state = moderated("ban-roles");
+        let commands = commands();
+        let mut run = |client_id, input: &str| {
+            commands.execute(client_id, &mut state, &mut Plugins::new(), input)
+        };
+
+        assert_eq!(
+            run(Some(1), "ban alice"),
+            Err(CommandError::Failed(
+                "You can't ban alice, their role is not below yours".to_string()
+            ))
+        );
+        assert!(run(Some(1), "tempban Other_Mod 1h").is_err());
+        assert!(run(Some(1), "ban mod").is_err());
+
+        assert!(run(Some(1), "ban carol griefing").is_ok());
+        assert!(run(Some(0), "ban other_mod").is_ok());
+        // the console is above every role
+        assert!(run(None, "ban alice").is_ok());
+
+        let banned = state
+            .access
+            .bans
+            .iter()
+            .filter_map(|ban| ban.username.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(banned, ["carol", "other_mod", "alice"]);
+    }
+
+    #[test]
+    fn ranges_may_not_ban_everyone_or_the_issuer() {
+        let mut state = moderated("ban-ranges");
+        let commands = commands();
+        let mut run = |client_id, input: &str| {
+            commands.execute(client_id, &mut state, &mut Plugins::new(), input)
+        };
+
+        for everyone in ["0.0.0.0/0", "::/0"] {
+            assert_eq!(
+                run(None, &format!("ban {}", everyone)),
+                Err(CommandError::Failed(format!(
+                    "{} would ban everyone",
+                    everyone
+                )))
+            );
+        }
+
+        assert_eq!(
+            run(Some(1), "ban 10.0.0.0/8"),
+            Err(CommandError::Failed(
+                "10.0.0.0/8 would ban yourself".to_string()
+            ))
+        );
+        assert!(run(Some(1), "tempban 10.1.2.3 1h").is_err());
+
+        assert!(run(Some(1), "ban 11.0.0.0/8").is_ok());
+        assert!(run(None, "ban 10.0.0.0/8").is_ok());
+        assert_eq!(state.access.bans.len(), 2);
+    }
+}
@@ -0,0 +1,260 @@
+use std::time::Duration;
+
+use super::CommandError;
+
+/// The kind of value a command parameter accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    /// The username of a player that is currently online.
+    Player,
+    Integer,
+    /// A length of time such as `30s`, `10m`, `2h` or `1d`.
+    Duration,
+    /// A single whitespace separated word.
+    Word,
+    /// Everything that is left of the input, must be the last parameter.
+    Text,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub kind: ParameterKind,
+    pub optional: bool,
+}
+
+impl Parameter {
+    pub const fn required(name: &'static str, kind: ParameterKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub const fn optional(name: &'static str, kind: ParameterKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: true,
+        }
+    }
+
+    pub fn usage(&self) -> String {
+        if self.optional {
+            format!("[{}]", self.name)
+        } else {
+            format!("<{}>", self.name)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Argument {
+    /// The slot of the player.
+    Player(usize),
+    Integer(i64),
+    Duration(Duration),
+    Word(String),
+    Text(String),
+}
+
+/// Parses `input` against `parameters`, `find_player` resolves a username to a slot.
+pub(super) fn parse(
+    parameters: &[Parameter],
+    input: &str,
+    usage: impl Fn() -> String,
+    find_player: impl Fn(&str) -> Option<usize>,
+) -> Result<Vec<Argument>, CommandError> {
+    let mut rest = input;
+    let mut arguments = Vec::with_capacity(parameters.len());
+
+    for parameter in parameters {
+        let value = if parameter.kind == ParameterKind::Text {
+            let text = rest.trim();
+            rest = "";
+
+            if text.is_empty() {
+                None
+            } else {
+                Some(text)
+            }
+        } else {
+            next_word(&mut rest)
+        };
+
+        let value = match value {
+            Some(value) => value,
+            None if parameter.optional => break,
+            None => return Err(CommandError::Usage(usage())),
+        };
+
+        let argument = match parameter.kind {
+            ParameterKind::Player => Argument::Player(
+                find_player(value)
+                    .ok_or_else(|| CommandError::PlayerNotFound(value.to_string()))?,
+            ),
+            ParameterKind::Integer => {
+                Argument::Integer(value.parse().map_err(|_| CommandError::InvalidArgument {
+                    name: parameter.name,
+                    value: value.to_string(),
+                })?)
+            }
+            ParameterKind::Duration => {
+                Argument::Duration(parse_duration(value).ok_or_else(|| {
+                    CommandError::InvalidArgument {
+                        name: parameter.name,
+                        value: value.to_string(),
+                    }
+                })?)
+            }
+            ParameterKind::Word => Argument::Word(value.to_string()),
+            ParameterKind::Text => Argument::Text(value.to_string()),
+        };
+
+        arguments.push(argument);
+    }
+
+    if !rest.trim().is_empty() {
+        return Err(CommandError::Usage(usage()));
+    }
+
+    Ok(arguments)
+}
+
+/// Parses a number followed by `s`, `m`, `h` or `d`, a plain number is in seconds.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => return None,
+    };
+
+    number
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+}
+
+/// Splits the next whitespace separated word off `rest`.
+pub(super) fn next_word<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let trimmed = rest.trim_start();
+
+    if trimmed.is_empty() {
+        *rest = trimmed;
+        return None;
+    }
+
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    let (word, remaining) = trimmed.split_at(end);
+
+    *rest = remaining;
+
+    Some(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KICK: [Parameter; 2] = [
+        Parameter::required("player", ParameterKind::Player),
+        Parameter::optional("reason", ParameterKind::Text),
+    ];
+
+    const TELEPORT: [Parameter; 3] = [
+        Parameter::required("x", ParameterKind::Integer),
+        Parameter::required("y", ParameterKind::Integer),
+        Parameter::optional("for", ParameterKind::Duration),
+    ];
+
+    fn run(parameters: &[Parameter], input: &str) -> Result<Vec<Argument>, CommandError> {
+        parse(
+            parameters,
+            input,
+            || "usage".to_string(),
+            |username| ["alice", "bob"].iter().position(|name| *name == username),
+        )
+    }
+
+    #[test]
+    fn text_takes_the_rest_as_written() {
+        assert_eq!(
+            run(&KICK, "bob   \"stop it\"  or 'else' "),
+            Ok(vec![
+                Argument::Player(1),
+                Argument::Text("\"stop it\"  or 'else'".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn players_and_numbers_are_resolved() {
+        assert_eq!(run(&KICK, "alice"), Ok(vec![Argument::Player(0)]));
+        assert_eq!(
+            run(&KICK, "carol"),
+            Err(CommandError::PlayerNotFound("carol".to_string()))
+        );
+
+        assert_eq!(
+            run(&TELEPORT, " -12 7 "),
+            Ok(vec![Argument::Integer(-12), Argument::Integer(7)])
+        );
+        assert_eq!(
+            run(&TELEPORT, "12 seven"),
+            Err(CommandError::InvalidArgument {
+                name: "y",
+                value: "seven".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn optional_parameters_can_be_left_out() {
+        assert_eq!(
+            run(&TELEPORT, "1 2 5m"),
+            Ok(vec![
+                Argument::Integer(1),
+                Argument::Integer(2),
+                Argument::Duration(Duration::from_secs(5 * 60))
+            ])
+        );
+        assert_eq!(
+            run(&TELEPORT, "1 2 5y"),
+            Err(CommandError::InvalidArgument {
+                name: "for",
+                value: "5y".to_string()
+            })
+        );
+        assert_eq!(run(&KICK, "bob   "), Ok(vec![Argument::Player(1)]));
+    }
+
+    #[test]
+    fn argument_counts_are_checked() {
+        assert_eq!(
+            run(&KICK, ""),
+            Err(CommandError::Usage("usage".to_string()))
+        );
+        assert_eq!(
+            run(&TELEPORT, "1"),
+            Err(CommandError::Usage("usage".to_string()))
+        );
+        assert_eq!(
+            run(&TELEPORT, "1 2 3 4"),
+            Err(CommandError::Usage("usage".to_string()))
+        );
+        assert_eq!(
+            run(&[], "anything"),
+            Err(CommandError::Usage("usage".to_string()))
+        );
+        assert_eq!(run(&[], "  "), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,490 @@
+use common::format_duration;
+
+use crate::{
+    audit,
+    permissions::{self, Role},
+    State,
+};
+
+use super::{Argument, Command, CommandError, Commands, Parameter, ParameterKind, Response};
+
+/// How many edits `/history` shows.
+const HISTORY_LENGTH: usize = 10;
+
+/// Registers the commands every server ships with.
+pub fn register_builtin(commands: &mut Commands) {
+    commands.register(
+        Command::new(
+            "help",
+            "Lists commands or shows how to use one",
+            |ctx, args| match args {
+                [Argument::Word(name)] => {
+                    let name = name.trim_start_matches('/');
+
+                    let command = ctx
+                        .commands
+                        .get(name)
+                        .ok_or_else(|| CommandError::UnknownCommand(name.to_string()))?;
+
+                    Ok(vec![Response::Reply(format!(
+                        "{} - {}",
+                        command.usage(),
+                        command.description
+                    ))])
+                }
+                _ => Ok(ctx
+                    .commands
+                    .iter()
+                    .filter(|command| match command.permission {
+                        Some(permission) => ctx.has_permission(permission),
+                        None => true,
+                    })
+                    .map(|command| format!("{} - {}", command.usage(), command.description))
+                    .chain(
+                        ctx.client_id
+                            .map(|client_id| ctx.plugins.help(ctx.state, client_id))
+                            .unwrap_or_default(),
+                    )
+                    .map(Response::Reply)
+                    .collect()),
+            },
+        )
+        .parameter(Parameter::optional("command", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "msg",
+            "Sends a private message to a player",
+            |ctx, args| match args {
+                [Argument::Player(target), Argument::Text(message)] => {
+                    let target_username = ctx.state.players[*target]
+                        .as_ref()
+                        .map(|player| player.username.clone())
+                        .unwrap_or_default();
+
+                    Ok(vec![
+                        Response::Whisper(
+                            *target,
+                            format!("{} whispers: {}", ctx.username(), message),
+                        ),
+                        Response::Reply(format!("You whisper to {}: {}", target_username, message)),
+                    ])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::CHAT_SEND)
+        .parameter(Parameter::required("player", ParameterKind::Player))
+        .parameter(Parameter::required("message", ParameterKind::Text)),
+    );
+
+    commands.register(
+        Command::new(
+            "me",
+            "Describes an action you are doing",
+            |ctx, args| match args {
+                [Argument::Text(action)] => Ok(vec![Response::BroadcastWorld(
+                    ctx.world_name(),
+                    format!("* {} {}", ctx.username(), action),
+                )]),
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::CHAT_SEND)
+        .parameter(Parameter::required("action", ParameterKind::Text)),
+    );
+
+    commands.register(Command::new("who", "Lists online players", |ctx, _| {
+        let usernames = ctx
+            .state
+            .players
+            .iter()
+            .flatten()
+            .map(|player| player.username.as_str())
+            .collect::<Vec<_>>();
+
+        Ok(vec![Response::Reply(format!(
+            "{} online: {}",
+            usernames.len(),
+            usernames.join(", ")
+        ))])
+    }));
+
+    commands.register(Command::new("seed", "Shows the world seed", |ctx, _| {
+        Ok(vec![Response::Reply(format!("Seed: {}", ctx.world().seed))])
+    }));
+
+    commands.register(
+        Command::new(
+            "world",
+            "Lists the worlds or moves you or a player to one",
+            |ctx, args| match args {
+                [] => {
+                    let worlds = ctx
+                        .state
+                        .worlds
+                        .names()
+                        .map(|name| {
+                            let players = ctx
+                                .state
+                                .players
+                                .iter()
+                                .flatten()
+                                .filter(|player| player.world == name)
+                                .count();
+
+                            format!("{} ({})", name, players)
+                        })
+                        .collect::<Vec<_>>();
+
+                    Ok(vec![Response::Reply(format!(
+                        "You are in {}, worlds: {}",
+                        ctx.world_name(),
+                        worlds.join(", ")
+                    ))])
+                }
+                [Argument::Word(name), rest @ ..] => {
+                    if ctx.state.worlds.get(name).is_none() {
+                        return Err(CommandError::Failed(format!("There is no world {}", name)));
+                    }
+
+                    let target = match (rest, ctx.client_id) {
+                        ([Argument::Player(target)], _) if Some(*target) != ctx.client_id => {
+                            if !ctx.has_permission(permissions::ADMIN_WORLD) {
+                                return Err(CommandError::Failed(
+                                    "You do not have permission to move other players".to_string(),
+                                ));
+                            }
+
+                            *target
+                        }
+                        ([Argument::Player(target)], _) => *target,
+                        ([], Some(target)) => target,
+                        _ => {
+                            return Err(CommandError::Failed(
+                                "Name a player to move from the console".to_string(),
+                            ))
+                        }
+                    };
+
+                    if ctx.state.world_of(target) == Some(name.as_str()) {
+                        return Err(CommandError::Failed(format!("Already in {}", name)));
+                    }
+
+                    let mut responses = vec![Response::MoveWorld(target, name.clone())];
+
+                    if Some(target) != ctx.client_id {
+                        responses.push(Response::Reply(format!("Moved them to {}", name)));
+                    }
+
+                    Ok(responses)
+                }
+                _ => unreachable!(),
+            },
+        )
+        .parameter(Parameter::optional("name", ParameterKind::Word))
+        .parameter(Parameter::optional("player", ParameterKind::Player)),
+    );
+
+    commands.register(
+        Command::new("role", "Shows the role of a player", |ctx, args| {
+            let username = match args {
+                [Argument::Word(username)] => username.clone(),
+                _ => ctx.username().to_string(),
+            };
+
+            Ok(vec![Response::Reply(format!(
+                "{} is {}",
+                username,
+                ctx.state.permissions.role(&username)
+            ))])
+        })
+        .parameter(Parameter::optional("username", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new("seen", "Shows when a player was around", |ctx, args| {
+            let username = match args {
+                [Argument::Word(username)] => username.clone(),
+                _ => ctx.username().to_string(),
+            };
+
+            let identities = &ctx.state.identities;
+
+            let identity = identities
+                .get(&username)
+                .ok_or_else(|| CommandError::Failed(format!("{} has never joined", username)))?;
+
+            let now = audit::now();
+
+            let last_seen = if identities.is_online(&username) {
+                "online now".to_string()
+            } else {
+                format!(
+                    "last seen {} ago",
+                    format_duration(now.saturating_sub(identity.last_seen))
+                )
+            };
+
+            Ok(vec![Response::Reply(format!(
+                "{} first joined {} ago, {}, played {} and made {} edits",
+                identity.username,
+                format_duration(now.saturating_sub(identity.first_seen)),
+                last_seen,
+                format_duration(identities.playtime(&username, now)),
+                identity.edits
+            ))])
+        })
+        .parameter(Parameter::optional("username", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "setrole",
+            "Assigns a role to a player",
+            |ctx, args| match args {
+                [Argument::Word(username), Argument::Word(role)] => {
+                    let role = role
+                        .parse::<Role>()
+                        .map_err(|_| CommandError::InvalidArgument {
+                            name: "role",
+                            value: role.clone(),
+                        })?;
+
+                    ctx.state.permissions.set_role(username, role);
+                    ctx.state
+                        .permissions
+                        .save()
+                        .map_err(|err| CommandError::Failed(err.to_string()))?;
+
+                    let mut responses =
+                        vec![Response::Reply(format!("{} is now {}", username, role))];
+
+                    if let Some(target) = ctx.state.find_player(username) {
+                        responses.push(Response::System(target, format!("You are now {}", role)));
+                    }
+
+                    Ok(responses)
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_ROLE)
+        .parameter(Parameter::required("username", ParameterKind::Word))
+        .parameter(Parameter::required("role", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "kick",
+            "Disconnects a player of a lower role from the server",
+            |ctx, args| match args {
+                [Argument::Player(target), rest @ ..] => {
+                    let username = ctx.state.players[*target]
+                        .as_ref()
+                        .map(|player| player.username.clone())
+                        .unwrap_or_default();
+
+                    if !ctx.outranks(&username) {
+                        return Err(CommandError::Failed(format!(
+                            "You can't kick {}, their role is not below yours",
+                            username
+                        )));
+                    }
+
+                    let reason = match rest {
+                        [Argument::Text(reason)] => reason.clone(),
+                        _ => "Kicked by a moderator".to_string(),
+                    };
+
+                    Ok(vec![Response::Kick(*target, reason)])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_KICK)
+        .parameter(Parameter::required("player", ParameterKind::Player))
+        .parameter(Parameter::optional("reason", ParameterKind::Text)),
+    );
+
+    commands.register(
+        Command::new(
+            "history",
+            "Shows who changed a tile",
+            |ctx, args| match args {
+                [Argument::Integer(x), Argument::Integer(y)] => {
+                    let position = match (i32::try_from(*x), i32::try_from(*y)) {
+                        (Ok(x), Ok(y)) => common::world::TilePos::new(x, y),
+                        _ => {
+                            return Err(CommandError::Failed(
+                                "That tile is outside the world".into(),
+                            ))
+                        }
+                    };
+
+                    let now = audit::now();
+                    let world = ctx.world_name();
+
+                    let tiles = &ctx.state.tiles;
+                    let name = |tile: Option<common::world::Tile>| {
+                        tile.map_or_else(|| "nothing".to_string(), |tile| tiles.name_of(tile.ty))
+                    };
+                    let describe = |change: &common::world::Change| match change {
+                        common::world::Change::Tile { layer, old, new } => format!(
+                            "changed {} to {} on the {} layer",
+                            name(*old),
+                            name(*new),
+                            layer
+                        ),
+                        common::world::Change::Height { old, new } => {
+                            format!("changed the height from {} to {}", old, new)
+                        }
+                        common::world::Change::Entity { old, new } => match (old, new) {
+                            (_, Some(entity)) => format!("edited the {}", entity.kind()),
+                            (Some(entity), None) => format!("removed the {}", entity.kind()),
+                            (None, None) => "changed nothing".to_string(),
+                        },
+                    };
+
+                    let mut responses = ctx
+                        .state
+                        .audit
+                        .history(&world, position)
+                        .take(HISTORY_LENGTH)
+                        .map(|edit| {
+                            Response::Reply(format!(
+                                "{} ago {} {}",
+                                format_duration(now.saturating_sub(edit.time)),
+                                edit.player,
+                                describe(&edit.change)
+                            ))
+                        })
+                        .collect::<Vec<_>>();
+
+                    if responses.is_empty() {
+                        responses.push(Response::Reply(format!("Nobody has changed {}, {}", x, y)));
+                    }
+
+                    Ok(responses)
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_AUDIT)
+        .parameter(Parameter::required("x", ParameterKind::Integer))
+        .parameter(Parameter::required("y", ParameterKind::Integer)),
+    );
+
+    commands.register(
+        Command::new(
+            "rollback",
+            "Undoes the edits a player of a lower role made within a time",
+            |ctx, args| match args {
+                [Argument::Word(username), Argument::Duration(duration)] => {
+                    if !ctx.outranks(username) {
+                        return Err(CommandError::Failed(format!(
+                            "You can't roll back {}, their role is not below yours",
+                            username
+                        )));
+                    }
+
+                    let by = ctx.username().to_string();
+
+                    let State {
+                        worlds,
+                        audit,
+                        tiles,
+                        ..
+                    } = &mut *ctx.state;
+
+                    let (restored, chunks) =
+                        audit.rollback(worlds, tiles, username, duration.as_secs(), &by);
+
+                    log::info!("{} rolled back {} tiles of {}", by, restored, username);
+
+                    Ok(chunks
+                        .into_iter()
+                        .map(|(world, position)| Response::ChunkModified(world, position))
+                        .chain(std::iter::once(Response::Reply(format!(
+                            "Restored {} tiles changed by {} in the last {}",
+                            restored,
+                            username,
+                            format_duration(duration.as_secs())
+                        ))))
+                        .collect())
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_ROLLBACK)
+        .parameter(Parameter::required("username", ParameterKind::Word))
+        .parameter(Parameter::required("time", ParameterKind::Duration)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{plugin::Plugins, tests::state};
+
+    #[test]
+    fn me_is_sent_to_the_world_of_the_player() {
+        let mut state = state("me");
+        let mut commands = Commands::new();
+        register_builtin(&mut commands);
+
+        assert_eq!(
+            commands
+                .execute(Some(0), &mut state, &mut Plugins::new(), "me waves")
+                .unwrap(),
+            [Response::BroadcastWorld(
+                "test".to_string(),
+                "* alice waves".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn moderators_only_act_against_lower_roles() {
+        let mut state = state("ranks");
+        for (slot, username, role) in [(1, "mod", Role::Moderator), (2, "carol", Role::Member)] {
+            state.players[slot] = Some(common::world::Player {
+                username: username.to_string(),
+                world: "test".to_string(),
+            });
+            state.permissions.set_role(username, role);
+        }
+        state.permissions.set_role("other_mod", Role::Moderator);
+
+        let mut commands = Commands::new();
+        register_builtin(&mut commands);
+        let mut run = |client_id, input: &str| {
+            commands.execute(client_id, &mut state, &mut Plugins::new(), input)
+        };
+
+        assert_eq!(
+            run(Some(1), "kick alice"),
+            Err(CommandError::Failed(
+                "You can't kick alice, their role is not below yours".to_string()
+            ))
+        );
+        assert!(run(Some(1), "kick mod").is_err());
+        assert_eq!(
+            run(Some(1), "kick carol spam"),
+            Ok(vec![Response::Kick(2, "spam".to_string())])
+        );
+        assert!(run(Some(0), "kick mod").is_ok());
+
+        assert_eq!(
+            run(Some(1), "rollback alice 1h"),
+            Err(CommandError::Failed(
+                "You can't roll back alice, their role is not below yours".to_string()
+            ))
+        );
+        assert!(run(Some(1), "rollback other_mod 1h").is_err());
+        assert!(run(Some(1), "rollback carol 1h").is_ok());
+        assert!(run(Some(0), "rollback other_mod 1h").is_ok());
+        assert!(run(None, "rollback alice 1h").is_ok());
+    }
+}
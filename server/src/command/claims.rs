@@ -0,0 +1,408 @@
+use common::world::{Claim, ClaimOwner};
+
+use common::world::World;
+
+use crate::permissions;
+
+use super::{
+    Argument, Command, CommandError, Commands, Context, Parameter, ParameterKind, Response,
+};
+
+/// The most tiles a single player claim may cover.
+const MAX_CLAIM_AREA: u64 = 32 * 32;
+/// How many claims a player may own.
+const MAX_CLAIMS: usize = 3;
+
+/// Registers the commands for claiming land and protecting areas.
+pub fn register_claims(commands: &mut Commands) {
+    commands.register(
+        Command::new("claim", "Claims an area so only you can edit it", |ctx, args| {
+            match args {
+                [Argument::Word(name), Argument::Integer(x1), Argument::Integer(y1), Argument::Integer(x2), Argument::Integer(y2)] =>
+                {
+                    let username = ctx.username().to_string();
+
+                    let claim = Claim::new(
+                        name.clone(),
+                        ClaimOwner::Player(username.clone()),
+                        position(*x1, *y1)?,
+                        position(*x2, *y2)?,
+                    );
+
+                    if claim.area() > MAX_CLAIM_AREA {
+                        return Err(CommandError::Failed(format!(
+                            "Claims can cover at most {} tiles",
+                            MAX_CLAIM_AREA
+                        )));
+                    }
+
+                    let owned = ctx
+                        .world()
+                        .claims
+                        .iter()
+                        .filter(|claim| claim.owner == ClaimOwner::Player(username.clone()))
+                        .count();
+
+                    if owned >= MAX_CLAIMS {
+                        return Err(CommandError::Failed(format!(
+                            "You can own at most {} claims",
+                            MAX_CLAIMS
+                        )));
+                    }
+
+                    add(ctx.world(), claim)?;
+
+                    Ok(vec![
+                        Response::ClaimsModified(ctx.world_name()),
+                        Response::Reply(format!("Claimed {}", name)),
+                    ])
+                }
+                _ => unreachable!(),
+            }
+        })
+        .permission(permissions::WORLD_CLAIM)
+        .parameter(Parameter::required("name", ParameterKind::Word))
+        .parameter(Parameter::required("x1", ParameterKind::Integer))
+        .parameter(Parameter::required("y1", ParameterKind::Integer))
+        .parameter(Parameter::required("x2", ParameterKind::Integer))
+        .parameter(Parameter::required("y2", ParameterKind::Integer)),
+    );
+
+    commands.register(
+        Command::new(
+            "protect",
+            "Protects an area so only a role and above can edit it",
+            |ctx, args| match args {
+                [Argument::Word(name), Argument::Integer(x1), Argument::Integer(y1), Argument::Integer(x2), Argument::Integer(y2), rest @ ..] =>
+                {
+                    let role = match rest {
+                        [Argument::Word(role)] => {
+                            role.parse::<permissions::Role>()
+                                .map_err(|_| CommandError::InvalidArgument {
+                                    name: "role",
+                                    value: role.clone(),
+                                })?
+                        }
+                        _ => permissions::Role::Moderator,
+                    };
+
+                    let claim = Claim::new(
+                        name.clone(),
+                        ClaimOwner::Group(role.to_string()),
+                        position(*x1, *y1)?,
+                        position(*x2, *y2)?,
+                    );
+
+                    add(ctx.world(), claim)?;
+
+                    Ok(vec![
+                        Response::ClaimsModified(ctx.world_name()),
+                        Response::Reply(format!("Protected {} for {} and above", name, role)),
+                    ])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_PROTECT)
+        .parameter(Parameter::required("name", ParameterKind::Word))
+        .parameter(Parameter::required("x1", ParameterKind::Integer))
+        .parameter(Parameter::required("y1", ParameterKind::Integer))
+        .parameter(Parameter::required("x2", ParameterKind::Integer))
+        .parameter(Parameter::required("y2", ParameterKind::Integer))
+        .parameter(Parameter::optional("role", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "unclaim",
+            "Removes a claim or protected area",
+            |ctx, args| match args {
+                [Argument::Word(name)] => {
+                    let i = manageable(ctx, name)?;
+                    let claim = ctx.world().claims.remove(i);
+
+                    Ok(vec![
+                        Response::ClaimsModified(ctx.world_name()),
+                        Response::Reply(format!("Removed {}", claim.name)),
+                    ])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .parameter(Parameter::required("name", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "trust",
+            "Lets a player edit in your claim",
+            |ctx, args| match args {
+                [Argument::Word(name), Argument::Word(username)] => {
+                    let i = manageable(ctx, name)?;
+                    let world = ctx.world_name();
+                    let claim = &mut ctx.world().claims[i];
+
+                    if !claim.is_member(username) {
+                        claim.members.push(username.clone());
+                    }
+
+                    Ok(vec![
+                        Response::ClaimsModified(world),
+                        Response::Reply(format!("{} can now edit in {}", username, claim.name)),
+                    ])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .parameter(Parameter::required("claim", ParameterKind::Word))
+        .parameter(Parameter::required("username", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "untrust",
+            "Stops a player editing in your claim",
+            |ctx, args| match args {
+                [Argument::Word(name), Argument::Word(username)] => {
+                    let i = manageable(ctx, name)?;
+                    let world = ctx.world_name();
+                    let claim = &mut ctx.world().claims[i];
+
+                    claim
+                        .members
+                        .retain(|member| !member.eq_ignore_ascii_case(username));
+
+                    Ok(vec![
+                        Response::ClaimsModified(world),
+                        Response::Reply(format!(
+                            "{} can no longer edit in {}",
+                            username, claim.name
+                        )),
+                    ])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .parameter(Parameter::required("claim", ParameterKind::Word))
+        .parameter(Parameter::required("username", ParameterKind::Word)),
+    );
+
+    commands.register(Command::new(
+        "claims",
+        "Lists claims and protected areas",
+        |ctx, _| {
+            if ctx.world().claims.is_empty() {
+                return Ok(vec![Response::Reply("There are no claims".to_string())]);
+            }
+
+            Ok(ctx
+                .world()
+                .claims
+                .iter()
+                .map(|claim| {
+                    let owner = match &claim.owner {
+                        ClaimOwner::Player(owner) => owner.clone(),
+                        ClaimOwner::Group(group) => format!("{} and above", group),
+                    };
+
+                    Response::Reply(format!(
+                        "{} by {} from {}, {} to {}, {}",
+                        claim.name, owner, claim.min.x, claim.min.y, claim.max.x, claim.max.y
+                    ))
+                })
+                .collect())
+        },
+    ));
+}
+
+pub(super) fn position(x: i64, y: i64) -> Result<common::world::TilePos, CommandError> {
+    match (i32::try_from(x), i32::try_from(y)) {
+        (Ok(x), Ok(y)) => Ok(common::world::TilePos::new(x, y)),
+        _ => Err(CommandError::Failed(
+            "That area is outside the world".to_string(),
+        )),
+    }
+}
+
+/// Claims don't need their chunks, they can cover land that is not generated yet.
+fn add(world: &mut World, claim: Claim) -> Result<(), CommandError> {
+    for other in &world.claims {
+        if other.name.eq_ignore_ascii_case(&claim.name) {
+            return Err(CommandError::Failed(format!(
+                "There already is a claim named {}",
+                other.name
+            )));
+        }
+
+        if other.overlaps(&claim) {
+            return Err(CommandError::Failed(format!(
+                "That area overlaps {}",
+                other.name
+            )));
+        }
+    }
+
+    world.claims.push(claim);
+
+    Ok(())
+}
+
+/// Finds the claim named `name` if the issuing player may change it.
+fn manageable(ctx: &mut Context, name: &str) -> Result<usize, CommandError> {
+    let i = ctx
+        .world()
+        .claims
+        .iter()
+        .position(|claim| claim.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| CommandError::Failed(format!("There is no claim named {}", name)))?;
+
+    let allowed = match ctx.world().claims[i].owner.clone() {
+        ClaimOwner::Player(owner) => owner.eq_ignore_ascii_case(ctx.username()),
+        ClaimOwner::Group(_) => ctx.has_permission(permissions::ADMIN_PROTECT),
+    };
+
+    if allowed || ctx.has_permission(permissions::ADMIN_CLAIMS) {
+        Ok(i)
+    } else {
+        Err(CommandError::Failed(format!(
+            "You do not own {}",
+            ctx.world().claims[i].name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::world::TilePos;
+
+    use super::*;
+    use crate::{permissions::Role, plugin::Plugins, tests::state, State};
+
+    /// `state` with the members bob and carol in slots 1 and 2 and the moderator mod
+    /// in slot 3.
+    fn players(name: &str) -> (State, Commands) {
+        let mut state = state(name);
+
+        for (slot, username, role) in [
+            (1, "bob", Role::Member),
+            (2, "carol", Role::Member),
+            (3, "mod", Role::Moderator),
+        ] {
+            state.players[slot] = Some(common::world::Player {
+                username: username.to_string(),
+                world: "test".to_string(),
+            });
+            state.permissions.set_role(username, role);
+        }
+
+        let mut commands = Commands::new();
+        register_claims(&mut commands);
+
+        (state, commands)
+    }
+
+    fn run(
+        commands: &Commands,
+        state: &mut State,
+        client_id: usize,
+        input: &str,
+    ) -> Result<Vec<Response>, CommandError> {
+        commands.execute(Some(client_id), state, &mut Plugins::new(), input)
+    }
+
+    #[test]
+    fn only_owners_and_members_edit_player_claims() {
+        let (mut state, commands) = players("claim-members");
+        run(&commands, &mut state, 1, "claim home -5 -5 -1 -1").unwrap();
+
+        let inside = TilePos::new(-3, -2);
+        assert_eq!(state.edit_denied(1, inside), None);
+        assert_eq!(
+            state.edit_denied(2, inside),
+            Some("home is claimed by bob".to_string())
+        );
+        assert_eq!(state.edit_denied(2, TilePos::new(0, 0)), None);
+        // admins manage every claim
+        assert_eq!(state.edit_denied(0, inside), None);
+
+        assert!(run(&commands, &mut state, 2, "trust home carol").is_err());
+        run(&commands, &mut state, 1, "trust home Carol").unwrap();
+        assert_eq!(state.edit_denied(2, inside), None);
+
+        run(&commands, &mut state, 1, "untrust home carol").unwrap();
+        assert!(state.edit_denied(2, inside).is_some());
+    }
+
+    #[test]
+    fn group_claims_let_in_the_role_and_above() {
+        let (mut state, commands) = players("claim-group");
+        assert!(run(&commands, &mut state, 1, "protect spawn 0 0 9 9").is_err());
+        run(&commands, &mut state, 0, "protect spawn 0 0 9 9 moderator").unwrap();
+
+        let inside = TilePos::new(4, 4);
+        assert_eq!(
+            state.edit_denied(1, inside),
+            Some("spawn is protected".to_string())
+        );
+        assert_eq!(state.edit_denied(3, inside), None);
+        assert_eq!(state.edit_denied(0, inside), None);
+
+        // members of a group claim get in whatever their role
+        state.worlds.get_mut("test").unwrap().claims[0]
+            .members
+            .push("bob".to_string());
+        assert_eq!(state.edit_denied(1, inside), None);
+        assert!(state.edit_denied(2, inside).is_some());
+    }
+
+    #[test]
+    fn player_claims_are_limited() {
+        let (mut state, commands) = players("claim-limits");
+
+        assert_eq!(
+            run(&commands, &mut state, 1, "claim big 0 0 32 31"),
+            Err(CommandError::Failed(format!(
+                "Claims can cover at most {} tiles",
+                MAX_CLAIM_AREA
+            )))
+        );
+        assert!(run(
+            &commands,
+            &mut state,
+            1,
+            "claim huge -2147483648 0 2147483647 0"
+        )
+        .is_err());
+        assert!(run(&commands, &mut state, 1, "claim big 0 0 31 31").is_ok());
+
+        assert!(run(&commands, &mut state, 1, "claim overlapping 31 31 40 40").is_err());
+        assert!(run(&commands, &mut state, 1, "claim BIG 100 100 101 101").is_err());
+
+        for i in 1..MAX_CLAIMS {
+            let x = i * 100;
+            run(
+                &commands,
+                &mut state,
+                1,
+                &format!("claim small{} {} 0 {} 0", i, x, x),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            run(&commands, &mut state, 1, "claim another 1000 0 1000 0"),
+            Err(CommandError::Failed(format!(
+                "You can own at most {} claims",
+                MAX_CLAIMS
+            )))
+        );
+
+        // the limit is per player and far away land needs no generated chunks
+        assert!(run(
+            &commands,
+            &mut state,
+            2,
+            "claim far 1000000 1000000 1000001 1000001"
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,770 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use common::world::{
+    changed_chunks, Change, Layer, Region, Rotation, Tile, TilePos, World, WorldGenerator,
+};
+
+use crate::{
+    metrics, permissions,
+    plugin::{EventResult, PluginContext, TileChange},
+    State,
+};
+
+use super::{
+    claims::position, Argument, Command, CommandError, Commands, Context, Parameter, ParameterKind,
+    Response,
+};
+
+/// The most tiles a single command may change, or copy.
+const MAX_EDIT_AREA: u64 = 128 * 128;
+/// The largest circle `/circle` draws.
+const MAX_RADIUS: i64 = 64;
+/// The tile argument that empties a layer.
+const NOTHING: &str = "none";
+
+/// Registers the commands for changing large areas at once.
+pub fn register_edit(commands: &mut Commands) {
+    commands.register(
+        Command::new(
+            "fill",
+            "Sets every tile in an area",
+            |ctx, args| match args {
+                [Argument::Integer(x1), Argument::Integer(y1), Argument::Integer(x2), Argument::Integer(y2), Argument::Word(tile), rest @ ..] =>
+                {
+                    let region = region(*x1, *y1, *x2, *y2)?;
+                    let tile = parse_tile(ctx, tile)?;
+                    let layer = parse_layer(rest)?;
+
+                    apply(ctx, |world, generator| {
+                        world.fill(region, layer, tile, generator)
+                    })
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("x1", ParameterKind::Integer))
+        .parameter(Parameter::required("y1", ParameterKind::Integer))
+        .parameter(Parameter::required("x2", ParameterKind::Integer))
+        .parameter(Parameter::required("y2", ParameterKind::Integer))
+        .parameter(Parameter::required("tile", ParameterKind::Word))
+        .parameter(Parameter::optional("layer", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "replace",
+            "Changes one kind of tile into another in an area",
+            |ctx, args| match args {
+                [Argument::Integer(x1), Argument::Integer(y1), Argument::Integer(x2), Argument::Integer(y2), Argument::Word(from), Argument::Word(to), rest @ ..] =>
+                {
+                    let region = region(*x1, *y1, *x2, *y2)?;
+                    let from = parse_tile(ctx, from)?;
+                    let to = parse_tile(ctx, to)?;
+                    let layer = parse_layer(rest)?;
+
+                    apply(ctx, |world, generator| {
+                        world.replace(region, layer, from, to, generator)
+                    })
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("x1", ParameterKind::Integer))
+        .parameter(Parameter::required("y1", ParameterKind::Integer))
+        .parameter(Parameter::required("x2", ParameterKind::Integer))
+        .parameter(Parameter::required("y2", ParameterKind::Integer))
+        .parameter(Parameter::required("from", ParameterKind::Word))
+        .parameter(Parameter::required("to", ParameterKind::Word))
+        .parameter(Parameter::optional("layer", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "flood",
+            "Fills the connected tiles that are the same as the one at a position",
+            |ctx, args| match args {
+                [Argument::Integer(x), Argument::Integer(y), Argument::Word(tile), rest @ ..] => {
+                    let start = position(*x, *y)?;
+                    let tile = parse_tile(ctx, tile)?;
+                    let layer = parse_layer(rest)?;
+
+                    apply(ctx, |world, generator| {
+                        world.flood_fill(start, layer, tile, MAX_EDIT_AREA as usize, generator)
+                    })
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("x", ParameterKind::Integer))
+        .parameter(Parameter::required("y", ParameterKind::Integer))
+        .parameter(Parameter::required("tile", ParameterKind::Word))
+        .parameter(Parameter::optional("layer", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "line",
+            "Draws a straight line of tiles",
+            |ctx, args| match args {
+                [Argument::Integer(x1), Argument::Integer(y1), Argument::Integer(x2), Argument::Integer(y2), Argument::Word(tile), rest @ ..] =>
+                {
+                    let from = position(*x1, *y1)?;
+                    let to = position(*x2, *y2)?;
+                    let tile = parse_tile(ctx, tile)?;
+                    let layer = parse_layer(rest)?;
+
+                    let length = (x1 - x2).abs().max((y1 - y2).abs()) as u64 + 1;
+
+                    if length > MAX_EDIT_AREA {
+                        return Err(CommandError::Failed(format!(
+                            "Lines can be at most {} tiles long",
+                            MAX_EDIT_AREA
+                        )));
+                    }
+
+                    apply(ctx, |world, generator| {
+                        world.line(from, to, layer, tile, generator)
+                    })
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("x1", ParameterKind::Integer))
+        .parameter(Parameter::required("y1", ParameterKind::Integer))
+        .parameter(Parameter::required("x2", ParameterKind::Integer))
+        .parameter(Parameter::required("y2", ParameterKind::Integer))
+        .parameter(Parameter::required("tile", ParameterKind::Word))
+        .parameter(Parameter::optional("layer", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "circle",
+            "Draws a filled circle of tiles, or only its outline",
+            |ctx, args| match args {
+                [Argument::Integer(x), Argument::Integer(y), Argument::Integer(radius), Argument::Word(tile), rest @ ..] =>
+                {
+                    let center = position(*x, *y)?;
+                    let tile = parse_tile(ctx, tile)?;
+
+                    if !(0..=MAX_RADIUS).contains(radius) {
+                        return Err(CommandError::Failed(format!(
+                            "The radius must be between 0 and {}",
+                            MAX_RADIUS
+                        )));
+                    }
+
+                    let (layer, filled) = match rest {
+                        [layer, Argument::Word(mode)] => {
+                            let filled = match mode.to_lowercase().as_str() {
+                                "filled" => true,
+                                "outline" => false,
+                                _ => {
+                                    return Err(CommandError::InvalidArgument {
+                                        name: "mode",
+                                        value: mode.clone(),
+                                    })
+                                }
+                            };
+
+                            (parse_layer(std::slice::from_ref(layer))?, filled)
+                        }
+                        rest => (parse_layer(rest)?, true),
+                    };
+
+                    apply(ctx, |world, generator| {
+                        world.circle(center, *radius as u32, layer, tile, filled, generator)
+                    })
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("x", ParameterKind::Integer))
+        .parameter(Parameter::required("y", ParameterKind::Integer))
+        .parameter(Parameter::required("radius", ParameterKind::Integer))
+        .parameter(Parameter::required("tile", ParameterKind::Word))
+        .parameter(Parameter::optional("layer", ParameterKind::Word))
+        .parameter(Parameter::optional("filled|outline", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "copy",
+            "Copies an area so /paste can place it somewhere else",
+            |ctx, args| match args {
+                [Argument::Integer(x1), Argument::Integer(y1), Argument::Integer(x2), Argument::Integer(y2)] =>
+                {
+                    let region = region(*x1, *y1, *x2, *y2)?;
+                    let generator = generator(ctx)?;
+                    let structure = ctx.world().copy(region, generator.as_ref());
+
+                    let username = ctx.username().to_lowercase();
+                    ctx.state.clipboards.insert(username, structure);
+
+                    Ok(vec![Response::Reply(format!(
+                        "Copied {} by {} tiles",
+                        region.width(),
+                        region.height()
+                    ))])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("x1", ParameterKind::Integer))
+        .parameter(Parameter::required("y1", ParameterKind::Integer))
+        .parameter(Parameter::required("x2", ParameterKind::Integer))
+        .parameter(Parameter::required("y2", ParameterKind::Integer)),
+    );
+
+    commands.register(
+        Command::new(
+            "paste",
+            "Places what you copied or imported last at a position, turned and mirrored if asked",
+            |ctx, args| match args {
+                [Argument::Integer(x), Argument::Integer(y), rest @ ..] => {
+                    let origin = position(*x, *y)?;
+
+                    let (rotation, mirrored) = match rest {
+                        [] => (Rotation::None, false),
+                        [Argument::Integer(degrees), rest @ ..] => {
+                            let rotation = Rotation::from_degrees(*degrees).ok_or_else(|| {
+                                CommandError::InvalidArgument {
+                                    name: "degrees",
+                                    value: degrees.to_string(),
+                                }
+                            })?;
+
+                            let mirrored = match rest {
+                                [Argument::Word(mirror)]
+                                    if mirror.eq_ignore_ascii_case("mirror") =>
+                                {
+                                    true
+                                }
+                                [Argument::Word(mirror)] => {
+                                    return Err(CommandError::InvalidArgument {
+                                        name: "mirror",
+                                        value: mirror.clone(),
+                                    })
+                                }
+                                _ => false,
+                            };
+
+                            (rotation, mirrored)
+                        }
+                        _ => unreachable!(),
+                    };
+
+                    let structure = ctx
+                        .state
+                        .clipboards
+                        .get(&ctx.username().to_lowercase())
+                        .cloned()
+                        .ok_or_else(|| {
+                            CommandError::Failed("Copy something with /copy first".to_string())
+                        })?
+                        .transformed(rotation, mirrored);
+
+                    if structure.area() > MAX_EDIT_AREA {
+                        return Err(CommandError::Failed(format!(
+                            "You can change at most {} tiles at once",
+                            MAX_EDIT_AREA
+                        )));
+                    }
+
+                    apply(ctx, |world, generator| {
+                        world.paste(&structure, origin, generator)
+                    })
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("x", ParameterKind::Integer))
+        .parameter(Parameter::required("y", ParameterKind::Integer))
+        .parameter(Parameter::optional("degrees", ParameterKind::Integer))
+        .parameter(Parameter::optional("mirror", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "export",
+            "Saves what you copied last as a structure template",
+            |ctx, args| match args {
+                [Argument::Word(name)] => {
+                    let structure = ctx
+                        .state
+                        .clipboards
+                        .get(&ctx.username().to_lowercase())
+                        .ok_or_else(|| {
+                            CommandError::Failed("Copy something with /copy first".to_string())
+                        })?;
+
+                    let path = ctx
+                        .state
+                        .structures
+                        .save(name, structure, &ctx.state.tiles)
+                        .map_err(|err| {
+                            CommandError::Failed(format!("Can't export {}, {}", name, err))
+                        })?;
+
+                    log::info!("{} exported {}", ctx.username(), path.display());
+
+                    Ok(vec![Response::Reply(format!(
+                        "Saved {} to {}",
+                        name,
+                        path.display()
+                    ))])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("name", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new(
+            "import",
+            "Loads a structure template so /paste places it",
+            |ctx, args| match args {
+                [Argument::Word(name)] => {
+                    let structure =
+                        ctx.state
+                            .structures
+                            .load(name, &ctx.state.tiles)
+                            .map_err(|err| {
+                                CommandError::Failed(format!("Can't import {}, {}", name, err))
+                            })?;
+
+                    let reply = format!(
+                        "Loaded {}, {} by {} tiles",
+                        name,
+                        structure.width(),
+                        structure.height()
+                    );
+
+                    let username = ctx.username().to_lowercase();
+                    ctx.state.clipboards.insert(username, structure);
+
+                    Ok(vec![Response::Reply(reply)])
+                }
+                _ => unreachable!(),
+            },
+        )
+        .permission(permissions::ADMIN_EDIT)
+        .parameter(Parameter::required("name", ParameterKind::Word)),
+    );
+
+    commands.register(
+        Command::new("structures", "Lists the structure templates", |ctx, _| {
+            let names = ctx.state.structures.names().map_err(|err| {
+                CommandError::Failed(format!("Can't list the structures, {}", err))
+            })?;
+
+            if names.is_empty() {
+                return Ok(vec![Response::Reply(
+                    "There are no structures, save one with /export".to_string(),
+                )]);
+            }
+
+            Ok(vec![Response::Reply(format!(
+                "Structures: {}",
+                names.join(", ")
+            ))])
+        })
+        .permission(permissions::ADMIN_EDIT),
+    );
+}
+
+/// The area between two corners, if it is small enough to edit at once.
+fn region(x1: i64, y1: i64, x2: i64, y2: i64) -> Result<Region, CommandError> {
+    let region = Region::new(position(x1, y1)?, position(x2, y2)?);
+
+    if region.area() > MAX_EDIT_AREA {
+        return Err(CommandError::Failed(format!(
+            "You can change at most {} tiles at once",
+            MAX_EDIT_AREA
+        )));
+    }
+
+    Ok(region)
+}
+
+/// A tile by its string id, `none` for an empty layer.
+fn parse_tile(ctx: &Context, id: &str) -> Result<Option<Tile>, CommandError> {
+    if id.eq_ignore_ascii_case(NOTHING) {
+        return Ok(None);
+    }
+
+    ctx.state
+        .tiles
+        .resolve(id)
+        .map(|ty| Some(Tile { ty }))
+        .map_err(|_| CommandError::InvalidArgument {
+            name: "tile",
+            value: id.to_string(),
+        })
+}
+
+/// The optional layer argument, the ground if it was left out.
+fn parse_layer(rest: &[Argument]) -> Result<Layer, CommandError> {
+    match rest {
+        [Argument::Word(layer), ..] => layer.parse().map_err(|_| CommandError::InvalidArgument {
+            name: "layer",
+            value: layer.clone(),
+        }),
+        _ => Ok(Layer::Ground),
+    }
+}
+
+/// The generator of the world the issuer is in.
+fn generator(ctx: &Context) -> Result<Arc<dyn WorldGenerator>, CommandError> {
+    let name = ctx.world_name();
+
+    ctx.state
+        .worlds
+        .generator(&name)
+        .ok_or_else(|| CommandError::Failed(format!("The {} world is not loaded", name)))
+}
+
+/// Runs `edit` on the world of the issuer and lets the plugins veto its changes, then
+/// fixes the entities of the chunks it changed, records every changed tile like a single
+/// edit and sends each changed chunk once.
+fn apply(
+    ctx: &mut Context,
+    edit: impl FnOnce(&mut World, &dyn WorldGenerator) -> Vec<(TilePos, Change)>,
+) -> Result<Vec<Response>, CommandError> {
+    let name = ctx.world_name();
+    let username = ctx.username().to_string();
+
+    let generator = generator(ctx)?;
+
+    let world = ctx
+        .state
+        .worlds
+        .get_mut(&name)
+        .expect("players are always in a loaded world");
+    let changes = edit(world, generator.as_ref());
+
+    // the console is trusted like it is with permissions
+    let (mut changes, mut responses) = match ctx.client_id {
+        Some(client_id) => veto(ctx, client_id, &name, changes),
+        None => (changes, Vec::new()),
+    };
+
+    let State {
+        worlds,
+        tiles,
+        audit,
+        identities,
+        ..
+    } = &mut *ctx.state;
+    let world = worlds
+        .get_mut(&name)
+        .expect("players are always in a loaded world");
+
+    let dirty = changed_chunks(&changes);
+
+    for position in &dirty {
+        if let Some(chunk) = world.chunks.get_mut(position) {
+            let before = chunk.entities.clone();
+            chunk.update_entities(tiles);
+
+            // entities dropped or added for the new tiles are recorded too, so a sign
+            // that was built over gets its text back when the edit is rolled back
+            let locals = before.keys().chain(chunk.entities.keys()).copied();
+
+            for local in locals.collect::<BTreeSet<_>>() {
+                let (old, new) = (before.get(&local), chunk.entities.get(&local));
+
+                if let (true, Some(tile)) = (old != new, position.tile(local)) {
+                    changes.push((
+                        tile,
+                        Change::Entity {
+                            old: old.cloned(),
+                            new: new.cloned(),
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    log::info!(
+        "{} changed {} tiles in {} chunks of {} at once",
+        username,
+        changes.len(),
+        dirty.len(),
+        name
+    );
+
+    identities.record_edits(&username, changes.len() as u64);
+    metrics::METRICS.world_edits(changes.len());
+
+    for (position, change) in changes {
+        audit.record(&name, &username, position, change);
+    }
+
+    let reply = match dirty.len() {
+        0 => "Nothing changed".to_string(),
+        1 => "Changed 1 chunk".to_string(),
+        count => format!("Changed {} chunks", count),
+    };
+
+    responses.extend(
+        dirty
+            .into_iter()
+            .map(|position| Response::ChunkModified(name.clone(), position)),
+    );
+    responses.push(Response::Reply(reply));
+
+    Ok(responses)
+}
+
+/// Asks the plugins about every change `edit` made to the world `name`, which already
+/// has them, and undoes the vetoed ones newest first. Returns the changes that were
+/// kept and what the plugins responded.
+fn veto(
+    ctx: &mut Context,
+    client_id: usize,
+    name: &str,
+    changes: Vec<(TilePos, Change)>,
+) -> (Vec<(TilePos, Change)>, Vec<Response>) {
+    let mut plugin_ctx = PluginContext::new(ctx.state);
+    let mut reasons = Vec::new();
+
+    let (kept, vetoed): (Vec<_>, Vec<_>) = changes.into_iter().partition(|(position, change)| {
+        let change = TileChange {
+            position: *position,
+            change: change.clone(),
+        };
+
+        match ctx
+            .plugins
+            .on_tile_change(&mut plugin_ctx, client_id, &change)
+        {
+            EventResult::Continue => true,
+            EventResult::Cancel(reason) => {
+                reasons.extend(reason);
+                false
+            }
+        }
+    });
+
+    let mut responses = plugin_ctx.into_responses();
+
+    if !vetoed.is_empty() {
+        let State { worlds, tiles, .. } = &mut *ctx.state;
+
+        if let Some(world) = worlds.get_mut(name) {
+            for (position, change) in vetoed.iter().rev() {
+                change.reversed().apply(world, *position, tiles);
+            }
+        }
+
+        responses.push(Response::Reply(format!(
+            "{} of the changes were vetoed{}",
+            vetoed.len(),
+            reasons
+                .first()
+                .map(|reason| format!(": {}", reason))
+                .unwrap_or_default()
+        )));
+    }
+
+    (kept, responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::world::{TileEntity, TileRegistry};
+
+    use super::*;
+    use crate::{
+        command::register_builtin,
+        plugin::{Plugins, ServerPlugin},
+        tests::state,
+    };
+
+    /// Keeps the column at x 0 as it is.
+    struct KeepColumn;
+
+    impl ServerPlugin for KeepColumn {
+        fn name(&self) -> &'static str {
+            "keep column"
+        }
+
+        fn on_tile_change(
+            &mut self,
+            _ctx: &mut PluginContext,
+            _client_id: usize,
+            change: &TileChange,
+        ) -> EventResult {
+            if change.position.x == 0 {
+                EventResult::Cancel(Some("keep x 0".to_string()))
+            } else {
+                EventResult::Continue
+            }
+        }
+    }
+
+    #[test]
+    fn plugins_veto_bulk_edits() {
+        let mut state = state("fill-veto");
+        let mut plugins = Plugins::new();
+        plugins.register(KeepColumn);
+
+        let mut commands = Commands::new();
+        register_edit(&mut commands);
+
+        let region = Region::new(TilePos::new(0, 0), TilePos::new(1, 0));
+        state.worlds.load_chunks("test", &region.chunks());
+        let ground = |state: &State, x| {
+            state
+                .worlds
+                .get("test")
+                .unwrap()
+                .tile(TilePos::new(x, 0), Layer::Ground)
+        };
+        let generated = ground(&state, 0);
+        let stone = Some(Tile {
+            ty: state.tiles.resolve("stone").unwrap(),
+        });
+
+        let responses = commands
+            .execute(Some(0), &mut state, &mut plugins, "fill 0 0 1 0 stone")
+            .unwrap();
+        assert!(responses.contains(&Response::Reply(
+            "1 of the changes were vetoed: keep x 0".to_string()
+        )));
+        assert_eq!(ground(&state, 0), generated);
+        assert_eq!(ground(&state, 1), stone);
+        assert_eq!(state.audit.history("test", TilePos::new(0, 0)).count(), 0);
+        assert_eq!(state.audit.history("test", TilePos::new(1, 0)).count(), 1);
+
+        // the console is not asked
+        commands
+            .execute(None, &mut state, &mut plugins, "fill 0 0 1 0 stone")
+            .unwrap();
+        assert_eq!(ground(&state, 0), stone);
+    }
+
+    #[test]
+    fn rollback_undoes_a_fill() {
+        let mut state = state("fill");
+        let mut plugins = Plugins::new();
+
+        let mut commands = Commands::new();
+        register_builtin(&mut commands);
+        register_edit(&mut commands);
+
+        let mut run = |state: &mut State, input: &str| {
+            commands
+                .execute(Some(0), state, &mut plugins, input)
+                .unwrap()
+        };
+
+        let tiles = TileRegistry::default();
+        let stone = Some(Tile {
+            ty: tiles.resolve("stone").unwrap(),
+        });
+
+        let region = Region::new(TilePos::new(-2, 0), TilePos::new(13, 3));
+        let ground = |state: &State| {
+            let world = state.worlds.get("test").unwrap();
+
+            region
+                .positions()
+                .map(|position| world.tile(position, Layer::Ground))
+                .collect::<Vec<_>>()
+        };
+
+        state.worlds.load_chunks("test", &region.chunks());
+        let generated = ground(&state);
+
+        run(&mut state, "fill 0 0 13 3 stone");
+        run(&mut state, "fill -2 0 13 3 stone");
+        assert!(ground(&state).iter().all(|tile| *tile == stone));
+        assert_eq!(
+            state
+                .audit
+                .history("test", TilePos::new(-1, 2))
+                .map(|edit| edit.player.as_str())
+                .collect::<Vec<_>>(),
+            ["alice"]
+        );
+
+        // both fills are undone, the tiles are as generated again, only the console
+        // outranks the admin alice
+        commands
+            .execute(None, &mut state, &mut plugins, "rollback alice 1m")
+            .unwrap();
+        assert_eq!(ground(&state), generated);
+    }
+
+    #[test]
+    fn rollback_undoes_a_paste_with_entities() {
+        let mut state = state("paste");
+        let mut plugins = Plugins::new();
+
+        let mut commands = Commands::new();
+        register_builtin(&mut commands);
+        register_edit(&mut commands);
+
+        let mut run = |state: &mut State, input: &str| {
+            commands
+                .execute(Some(0), state, &mut plugins, input)
+                .unwrap()
+        };
+
+        let sign = |text: &str| TileEntity::Sign {
+            text: text.to_string(),
+        };
+        let place = |state: &mut State, x, id: &str, entity| {
+            let position = TilePos::new(x, 1);
+            let tile = Some(Tile {
+                ty: state.tiles.resolve(id).unwrap(),
+            });
+
+            state.set_tile("test", "bob", position, Layer::Object, tile);
+
+            if let Some(entity) = entity {
+                state.set_entity("test", "bob", position, Some(entity));
+            }
+        };
+
+        place(&mut state, 1, "sign", Some(sign("Welcome")));
+        place(&mut state, 20, "sign", Some(sign("Keep out")));
+        place(&mut state, 30, "wall", None);
+
+        let entity = |state: &State| {
+            state
+                .worlds
+                .get("test")
+                .unwrap()
+                .entity(TilePos::new(1, 1))
+                .cloned()
+        };
+
+        run(&mut state, "copy 20 1 20 1");
+        run(&mut state, "paste 1 1");
+        assert_eq!(entity(&state), Some(sign("Keep out")));
+
+        // building over the sign drops it
+        run(&mut state, "copy 30 1 30 1");
+        run(&mut state, "paste 1 1");
+        assert_eq!(entity(&state), None);
+
+        // only the console outranks the admin alice
+        commands
+            .execute(None, &mut state, &mut plugins, "rollback alice 1m")
+            .unwrap();
+        assert_eq!(entity(&state), Some(sign("Welcome")));
+    }
+}
@@ -0,0 +1,13 @@
+mod access;
+mod argument;
+mod builtin;
+mod claims;
+mod edit;
+mod registry;
+
+pub use access::*;
+pub use argument::*;
+pub use builtin::*;
+pub use claims::*;
+pub use edit::*;
+pub use registry::*;
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    plugin::{PluginContext, Plugins},
+    State,
+};
+
+use super::{argument, Argument, Parameter};
+
+/// The name commands run from the console act as.
+pub const CONSOLE: &str = "console";
+
+pub type Handler = Box<
+    dyn Fn(&mut Context, &[Argument]) -> std::result::Result<Vec<Response>, CommandError>
+        + Send
+        + Sync,
+>;
+
+/// What should happen after a command ran, sent out by the packet handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// A system message sent only to the player that issued the command.
+    Reply(String),
+    /// A system message sent only to the player in the given slot.
+    System(usize, String),
+    /// A chat message sent only to the player in the given slot.
+    Whisper(usize, String),
+    /// A chat message sent to every player in every world, for notices of the server.
+    Broadcast(String),
+    /// A chat message sent to every player in the named world.
+    BroadcastWorld(String, String),
+    /// Disconnects the player in the given slot with a reason.
+    Kick(usize, String),
+    /// Sends the chunk at the given chunk position to every player in the named world.
+    ChunkModified(String, common::world::ChunkPos),
+    /// Sends every claim of the named world to the players in it.
+    ClaimsModified(String),
+    /// Moves the player in the given slot to the named world.
+    MoveWorld(usize, String),
+    /// Disconnects every player the whitelist or ban list no longer lets in.
+    AccessModified,
+}
+
+pub struct Context<'a> {
+    /// The slot of the player that issued the command, `None` for the console.
+    pub client_id: Option<usize>,
+    pub state: &'a mut State,
+    pub commands: &'a Commands,
+    pub plugins: &'a mut Plugins,
+}
+
+impl<'a> Context<'a> {
+    pub fn username(&self) -> &str {
+        match self.client_id {
+            Some(client_id) => self.state.players[client_id]
+                .as_ref()
+                .map(|player| player.username.as_str())
+                .unwrap_or_default(),
+            None => CONSOLE,
+        }
+    }
+
+    /// The name of the world the issuer is in, the default world for the console.
+    pub fn world_name(&self) -> String {
+        self.client_id
+            .and_then(|client_id| self.state.world_of(client_id))
+            .unwrap_or_else(|| self.state.worlds.default_name())
+            .to_string()
+    }
+
+    /// The world the issuer is in, the default world for the console.
+    pub fn world(&mut self) -> &mut common::world::World {
+        let name = self.world_name();
+
+        self.state
+            .worlds
+            .get_mut(&name)
+            .expect("players are always in a loaded world")
+    }
+
+    /// The address the issuer joined from, `None` for the console.
+    pub fn address(&self) -> Option<std::net::IpAddr> {
+        self.client_id
+            .and_then(|client_id| self.state.address_of(client_id))
+    }
+
+    /// Whether the role of the issuer is above that of `username`, so the issuer may act
+    /// against them. The console outranks everyone.
+    pub fn outranks(&self, username: &str) -> bool {
+        match self.client_id {
+            Some(_) => {
+                let permissions = &self.state.permissions;
+
+                permissions.role(self.username()) > permissions.role(username)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether whoever issued the command has `permission`, the console has all of them.
+    pub fn has_permission(&self, permission: &str) -> bool {
+        match self.client_id {
+            Some(client_id) => self.state.has_permission(client_id, permission),
+            None => true,
+        }
+    }
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub permission: Option<&'static str>,
+    pub parameters: Vec<Parameter>,
+
+    handler: Handler,
+}
+
+impl Command {
+    pub fn new(
+        name: &'static str,
+        description: &'static str,
+        handler: impl Fn(&mut Context, &[Argument]) -> std::result::Result<Vec<Response>, CommandError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            permission: None,
+            parameters: Vec::new(),
+
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn parameter(mut self, parameter: Parameter) -> Self {
+        self.parameters.push(parameter);
+        self
+    }
+
+    pub fn permission(mut self, permission: &'static str) -> Self {
+        self.permission = Some(permission);
+        self
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+
+        for parameter in &self.parameters {
+            usage.push(' ');
+            usage.push_str(&parameter.usage());
+        }
+
+        usage
+    }
+}
+
+#[derive(Default)]
+pub struct Commands {
+    commands: BTreeMap<&'static str, Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, command: Command) {
+        if self.commands.insert(command.name, command).is_some() {
+            log::warn!("a command was registered twice, the last one wins");
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.get(name)
+    }
+
+    /// Iterates all commands sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Command> {
+        self.commands.values()
+    }
+
+    /// Runs `input` (without the leading `/`) as the player in `client_id`, or the
+    /// console if it is `None`.
+    ///
+    /// Commands that are not registered are offered to the plugins.
+    pub fn execute(
+        &self,
+        client_id: Option<usize>,
+        state: &mut State,
+        plugins: &mut Plugins,
+        input: &str,
+    ) -> std::result::Result<Vec<Response>, CommandError> {
+        let mut rest = input;
+
+        let name = argument::next_word(&mut rest).ok_or(CommandError::Empty)?;
+
+        let command = match self.get(name) {
+            Some(command) => command,
+            None => {
+                if let Some(client_id) = client_id {
+                    let mut ctx = PluginContext::new(state);
+
+                    if plugins.on_command(&mut ctx, client_id, name, rest.trim())? {
+                        return Ok(ctx.into_responses());
+                    }
+                }
+
+                return Err(CommandError::UnknownCommand(name.to_string()));
+            }
+        };
+
+        if let (Some(permission), Some(client_id)) = (command.permission, client_id) {
+            if !state.has_permission(client_id, permission) {
+                return Err(CommandError::PermissionDenied(command.name));
+            }
+        }
+
+        let arguments = argument::parse(
+            &command.parameters,
+            rest,
+            || command.usage(),
+            |username| state.find_player(username),
+        )?;
+
+        let mut context = Context {
+            client_id,
+            state,
+            commands: self,
+            plugins,
+        };
+
+        (command.handler)(&mut context, &arguments)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("Type /help for a list of commands")]
+    Empty,
+    #[error("Unknown command /{0}, type /help for a list of commands")]
+    UnknownCommand(String),
+    #[error("You do not have permission to use /{0}")]
+    PermissionDenied(&'static str),
+    #[error("Usage: {0}")]
+    Usage(String),
+    #[error("Invalid value '{value}' for {name}")]
+    InvalidArgument { name: &'static str, value: String },
+    #[error("No player named '{0}' is online")]
+    PlayerNotFound(String),
+    #[error("{0}")]
+    Failed(String),
+}
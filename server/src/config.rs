@@ -0,0 +1,102 @@
+use std::{fs, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::rate_limit::RateLimits;
+
+/// Server settings, persisted as RON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Overridden by the first command line argument.
+    pub address: String,
+    /// Where metrics are served in the Prometheus text format, `None` disables them.
+    pub metrics_address: Option<String>,
+    pub rate_limits: RateLimits,
+    /// The world players join in, must be one of `worlds`.
+    pub default_world: String,
+    /// The string id of the tile players place by clicking, tiles are configured in
+    /// tiles.ron.
+    pub placed_tile: String,
+    pub worlds: Vec<WorldConfig>,
+}
+
+/// A world the server hosts, its chunks are generated from the seed as they are needed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldConfig {
+    pub name: String,
+    /// Only players that bypass claims may edit it, e.g. a lobby.
+    pub protected: bool,
+    /// Generates the same world every time, a number or any text. A random seed is
+    /// picked when there is none.
+    #[serde(default)]
+    pub seed: Option<String>,
+    /// One of `common::world::GENERATORS`, biomes are configured in biomes.ron.
+    #[serde(default = "default_generator")]
+    pub generator: String,
+    /// Structures the generator scatters over the world, earlier ones win where two
+    /// would overlap. They are loaded from the structures next to the worlds.
+    #[serde(default)]
+    pub structures: Vec<common::world::Placement>,
+}
+
+fn default_generator() -> String {
+    "biomes".to_string()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0:8080".to_string(),
+            metrics_address: Some("127.0.0.1:9100".to_string()),
+            rate_limits: RateLimits::default(),
+            default_world: "lobby".to_string(),
+            placed_tile: "sand".to_string(),
+            worlds: vec![
+                WorldConfig {
+                    name: "lobby".to_string(),
+                    protected: true,
+                    seed: None,
+                    generator: "flat".to_string(),
+                    structures: Vec::new(),
+                },
+                WorldConfig {
+                    name: "build".to_string(),
+                    protected: false,
+                    seed: None,
+                    generator: default_generator(),
+                    structures: Vec::new(),
+                },
+            ],
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config at `path`, writing the defaults there if it does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        load_or_default(path)
+    }
+}
+
+/// Loads any RON config at `path`, writing its defaults there if it does not exist yet.
+pub fn load_or_default<T: Serialize + DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+) -> crate::Result<T> {
+    let path = path.as_ref();
+
+    if path.exists() {
+        return Ok(ron::from_str(&fs::read_to_string(path)?)?);
+    }
+
+    log::info!("creating default config at {}", path.display());
+
+    let config = T::default();
+
+    fs::write(
+        path,
+        ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default())?,
+    )?;
+
+    Ok(config)
+}
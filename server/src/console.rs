@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::UdpSocket,
+    sync::Mutex,
+};
+
+use crate::{
+    command::{Commands, Response},
+    plugin::Plugins,
+    respond, Client, State,
+};
+
+/// Runs commands typed into standard input with every permission and prints the replies.
+pub async fn run(
+    socket: Arc<UdpSocket>,
+    clients: Arc<Mutex<Vec<Option<Client>>>>,
+    state: Arc<Mutex<State>>,
+    plugins: Arc<Mutex<Plugins>>,
+    commands: Arc<Commands>,
+) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            // there is no console, e.g. when running in the background
+            Ok(None) => break,
+            Err(err) => {
+                log::warn!("Failed to read the console: {}", err);
+                break;
+            }
+        };
+
+        let input = line.trim();
+        let input = input.strip_prefix('/').unwrap_or(input);
+
+        if input.is_empty() {
+            continue;
+        }
+
+        let c = &mut clients.lock().await;
+
+        let responses = commands
+            .execute(
+                None,
+                &mut *state.lock().await,
+                &mut *plugins.lock().await,
+                input,
+            )
+            .unwrap_or_else(|err| vec![Response::Reply(err.to_string())]);
+
+        let (replies, responses): (Vec<_>, Vec<_>) = responses
+            .into_iter()
+            .partition(|response| matches!(response, Response::Reply(_)));
+
+        for reply in replies {
+            if let Response::Reply(message) = reply {
+                println!("{}", message);
+            }
+        }
+
+        respond(&socket, None, c, &state, &plugins, responses).await;
+    }
+}
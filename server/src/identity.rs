@@ -0,0 +1,190 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// What the server remembers about a player between sessions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    /// The username as it was last joined with.
+    pub username: String,
+    /// Unix time of the first join.
+    pub first_seen: u64,
+    /// Unix time of the last join or leave.
+    pub last_seen: u64,
+    /// Seconds spent online in finished sessions.
+    pub playtime: u64,
+    pub edits: u64,
+}
+
+/// Every player that has ever joined, saved as RON.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Identities {
+    /// Lowercase usernames mapped to their identity.
+    pub players: BTreeMap<String, Identity>,
+
+    /// When each online player joined.
+    #[serde(skip)]
+    sessions: BTreeMap<String, u64>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Identities {
+    /// Loads the identities at `path`, starting empty if it does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        let mut identities = if path.exists() {
+            ron::from_str::<Self>(&fs::read_to_string(path)?)?
+        } else {
+            Self::default()
+        };
+
+        identities.path = path.to_path_buf();
+
+        Ok(identities)
+    }
+
+    pub fn save(&self) -> crate::Result<()> {
+        let serialized = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        fs::write(&self.path, serialized)?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, username: &str) -> Option<&Identity> {
+        self.players.get(&username.to_lowercase())
+    }
+
+    /// Starts a session for `username`, creating its identity on the first join.
+    pub fn join(&mut self, username: &str, now: u64) {
+        let key = username.to_lowercase();
+
+        let identity = self.players.entry(key.clone()).or_insert(Identity {
+            username: username.to_string(),
+            first_seen: now,
+            last_seen: now,
+            playtime: 0,
+            edits: 0,
+        });
+
+        identity.username = username.to_string();
+        identity.last_seen = now;
+
+        self.sessions.insert(key, now);
+    }
+
+    /// Ends the session of `username` and adds it to its playtime.
+    pub fn leave(&mut self, username: &str, now: u64) {
+        let key = username.to_lowercase();
+
+        if let (Some(joined), Some(identity)) =
+            (self.sessions.remove(&key), self.players.get_mut(&key))
+        {
+            identity.playtime += now.saturating_sub(joined);
+            identity.last_seen = now;
+        }
+    }
+
+    /// Seconds `username` has been online, including the current session.
+    pub fn playtime(&self, username: &str, now: u64) -> u64 {
+        let key = username.to_lowercase();
+
+        let finished = self
+            .players
+            .get(&key)
+            .map_or(0, |identity| identity.playtime);
+        let current = self
+            .sessions
+            .get(&key)
+            .map_or(0, |joined| now.saturating_sub(*joined));
+
+        finished + current
+    }
+
+    pub fn is_online(&self, username: &str) -> bool {
+        self.sessions.contains_key(&username.to_lowercase())
+    }
+
+    /// Counts `count` tiles changed by the player.
+    pub fn record_edits(&mut self, username: &str, count: u64) {
+        if let Some(identity) = self.players.get_mut(&username.to_lowercase()) {
+            identity.edits += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::directory;
+
+    #[test]
+    fn sessions_add_to_the_playtime() {
+        let mut identities = Identities::default();
+
+        identities.join("Alice", 100);
+        assert!(identities.is_online("alice"));
+        assert_eq!(identities.playtime("ALICE", 130), 30);
+
+        identities.leave("alice", 160);
+        assert!(!identities.is_online("Alice"));
+        assert_eq!(identities.playtime("alice", 1000), 60);
+
+        let alice = identities.get("alice").unwrap();
+        assert_eq!(
+            (alice.first_seen, alice.last_seen, alice.playtime),
+            (100, 160, 60)
+        );
+
+        // a rejoin keeps the first join and the username it was made with last
+        identities.join("ALICE", 500);
+        identities.record_edits("alice", 3);
+        identities.leave("Alice", 540);
+
+        let alice = identities.get("Alice").unwrap();
+        assert_eq!(alice.username, "ALICE");
+        assert_eq!(
+            (alice.first_seen, alice.last_seen, alice.playtime),
+            (100, 540, 100)
+        );
+        assert_eq!(alice.edits, 3);
+        assert_eq!(identities.players.len(), 1);
+
+        // leaving without joining changes nothing
+        identities.leave("bob", 600);
+        assert!(identities.get("bob").is_none());
+    }
+
+    #[test]
+    fn identities_survive_a_restart() {
+        let directory = directory("identities");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("players.ron");
+
+        let mut identities = Identities::load(&path).unwrap();
+        identities.join("Bob", 10);
+        identities.record_edits("bob", 5);
+        identities.leave("bob", 70);
+        identities.join("carol", 20);
+        identities.save().unwrap();
+
+        let loaded = Identities::load(&path).unwrap();
+        let bob = loaded.get("BOB").unwrap();
+        assert_eq!(bob.username, "Bob");
+        assert_eq!(
+            (bob.first_seen, bob.last_seen, bob.playtime, bob.edits),
+            (10, 70, 60, 5)
+        );
+
+        // sessions end with the server
+        assert!(loaded.get("carol").is_some());
+        assert!(!loaded.is_online("carol"));
+    }
+}
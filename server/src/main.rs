@@ -1,4 +1,24 @@
-use std::{env, net::SocketAddr, sync::Arc};
+mod access;
+mod audit;
+mod command;
+mod config;
+mod console;
+mod identity;
+mod metrics;
+mod permissions;
+mod plugin;
+mod rate_limit;
+mod shutdown;
+mod structures;
+mod worlds;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Instant,
+};
 
 use tokio::{net::UdpSocket, sync::Mutex, time};
 
@@ -9,18 +29,281 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub struct State {
     players: Vec<Option<common::world::Player>>,
-    world: common::world::World,
+    /// The address each player joined from, by slot like `players`.
+    addresses: Vec<Option<IpAddr>>,
+    worlds: worlds::Worlds,
+    permissions: permissions::Permissions,
+    audit: audit::AuditLog,
+    identities: identity::Identities,
+    access: access::Access,
+    /// Shared with scripts, like world generators.
+    tiles: Arc<common::world::TileRegistry>,
+    /// What each player copied last with `/copy`, by lowercase username.
+    clipboards: HashMap<String, common::world::Structure>,
+    /// Templates saved with `/export`, shared by every world.
+    structures: structures::Structures,
+    /// Set once the server is shutting down, no one may join anymore.
+    shutting_down: bool,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(
+        worlds: worlds::Worlds,
+        permissions: permissions::Permissions,
+        audit: audit::AuditLog,
+        identities: identity::Identities,
+        access: access::Access,
+        tiles: common::world::TileRegistry,
+        structures: structures::Structures,
+    ) -> Self {
         let players = std::iter::repeat_with(|| None)
             .take(MAX_CLIENTS)
             .collect::<Vec<_>>();
 
-        let world = common::world::World::new(2, 2);
+        Self {
+            players,
+            addresses: vec![None; MAX_CLIENTS],
+            worlds,
+            permissions,
+            audit,
+            identities,
+            access,
+            tiles: Arc::new(tiles),
+            clipboards: HashMap::new(),
+            structures,
+            shutting_down: false,
+        }
+    }
+
+    /// Changes a tile in the named world and records who did it, returns false if
+    /// there is no such world.
+    pub fn set_tile(
+        &mut self,
+        world: &str,
+        player: &str,
+        position: common::world::TilePos,
+        layer: common::world::Layer,
+        tile: Option<common::world::Tile>,
+    ) -> bool {
+        let old = match self.worlds.load_and_set_tile(world, position, layer, tile) {
+            Some(old) => old,
+            None => return false,
+        };
+
+        if let Some(world) = self.worlds.get_mut(world) {
+            world.update_entity(position, &self.tiles);
+        }
+
+        self.audit.record(
+            world,
+            player,
+            position,
+            common::world::Change::Tile {
+                layer,
+                old,
+                new: tile,
+            },
+        );
+        self.identities.record_edits(player, 1);
+        metrics::METRICS.world_edits(1);
+
+        true
+    }
+
+    /// The height of a tile in the named world, generating its chunk if needed. `None`
+    /// if there is no such world.
+    pub fn load_height(&mut self, world: &str, position: common::world::TilePos) -> Option<u8> {
+        if !self.worlds.load_chunks(world, &[position.chunk()]) {
+            return None;
+        }
+
+        self.worlds.get(world)?.height(position)
+    }
+
+    /// Sets the height of a tile in the named world and records who did it, returns
+    /// false if there is no such world.
+    pub fn set_height(
+        &mut self,
+        world: &str,
+        player: &str,
+        position: common::world::TilePos,
+        height: u8,
+    ) -> bool {
+        let old = match self.load_height(world, position) {
+            Some(old) => old,
+            None => return false,
+        };
+
+        // heights are capped, record what the tile actually became
+        let new = match self.worlds.get_mut(world) {
+            Some(world) => {
+                world.set_height(position, height);
+                world.height(position).unwrap_or(height)
+            }
+            None => return false,
+        };
+
+        self.audit.record(
+            world,
+            player,
+            position,
+            common::world::Change::Height { old, new },
+        );
+        self.identities.record_edits(player, 1);
+        metrics::METRICS.world_edits(1);
+
+        true
+    }
 
-        Self { players, world }
+    /// The entity of a tile in the world of the player in `client_id` if they may edit
+    /// it into `edited`, with the name of the world, or why they may not.
+    pub fn check_entity_edit(
+        &self,
+        client_id: usize,
+        position: common::world::TilePos,
+        edited: &common::world::TileEntity,
+    ) -> std::result::Result<(String, common::world::TileEntity), String> {
+        let world = self.world_of(client_id).unwrap_or_default().to_string();
+
+        let chunk = self
+            .worlds
+            .get(&world)
+            .and_then(|world| world.chunk(position.chunk()));
+        let entity = chunk
+            .and_then(|chunk| chunk.entity(position.local()))
+            .ok_or(common::world::TileEntityError::Missing)
+            .and_then(|entity| entity.check_edit(edited).map(|_| entity.clone()))
+            .and_then(|entity| match chunk {
+                Some(chunk) if !chunk.has_room_for(position.local(), edited) => {
+                    Err(common::world::TileEntityError::ChunkFull)
+                }
+                _ => Ok(entity),
+            })
+            .map_err(|err| format!("You can't edit that, {}", err))?;
+
+        Ok((world, entity))
+    }
+
+    /// Replaces the entity of a tile in the named world and records who did it, returns
+    /// false if there is no such world or the chunk of the tile has not been generated.
+    pub fn set_entity(
+        &mut self,
+        world: &str,
+        player: &str,
+        position: common::world::TilePos,
+        entity: Option<common::world::TileEntity>,
+    ) -> bool {
+        let old = match self
+            .worlds
+            .get_mut(world)
+            .and_then(|world| world.set_entity(position, entity.clone()))
+        {
+            Some(old) => old,
+            None => return false,
+        };
+
+        log::debug!("{:?} at {} in {} became {:?}", old, position, world, entity);
+
+        self.audit.record(
+            world,
+            player,
+            position,
+            common::world::Change::Entity { old, new: entity },
+        );
+        self.identities.record_edits(player, 1);
+        metrics::METRICS.world_edits(1);
+
+        true
+    }
+
+    /// Writes the worlds and player data to disk.
+    pub fn save(&mut self) -> crate::Result<()> {
+        self.worlds.save()?;
+        self.identities.save()?;
+        self.access.save(audit::now())?;
+        self.audit.flush()?;
+
+        log::info!("saved the worlds and player data");
+
+        Ok(())
+    }
+
+    /// The name of the world the player in `client_id` is in.
+    pub fn world_of(&self, client_id: usize) -> Option<&str> {
+        self.players[client_id]
+            .as_ref()
+            .map(|player| player.world.as_str())
+    }
+
+    /// The address the player in `client_id` joined from.
+    pub fn address_of(&self, client_id: usize) -> Option<IpAddr> {
+        self.addresses.get(client_id).copied().flatten()
+    }
+
+    pub fn has_permission(&self, client_id: usize, permission: &str) -> bool {
+        match &self.players[client_id] {
+            Some(player) => self.permissions.has(&player.username, permission),
+            None => false,
+        }
+    }
+
+    /// Why the player in `client_id` may not change the tile at `position`, if they may not.
+    pub fn edit_denied(
+        &self,
+        client_id: usize,
+        position: common::world::TilePos,
+    ) -> Option<String> {
+        let (username, world) = match &self.players[client_id] {
+            Some(player) => (&player.username, &player.world),
+            None => return Some("You are not connected".to_string()),
+        };
+
+        if !self.permissions.has(username, permissions::WORLD_EDIT) {
+            return Some("You do not have permission to edit the world".to_string());
+        }
+
+        if self.permissions.has(username, permissions::ADMIN_CLAIMS) {
+            return None;
+        }
+
+        if self
+            .worlds
+            .config(world)
+            .is_some_and(|config| config.protected)
+        {
+            return Some(format!("The {} world is protected", world));
+        }
+
+        let claim = self.worlds.get(world)?.claim_at(position)?;
+
+        if self.is_claim_member(claim, username) {
+            return None;
+        }
+
+        Some(match &claim.owner {
+            common::world::ClaimOwner::Player(owner) => {
+                format!("{} is claimed by {}", claim.name, owner)
+            }
+            common::world::ClaimOwner::Group(_) => format!("{} is protected", claim.name),
+        })
+    }
+
+    pub fn is_claim_member(&self, claim: &common::world::Claim, username: &str) -> bool {
+        match &claim.owner {
+            common::world::ClaimOwner::Group(group) => match group.parse::<permissions::Role>() {
+                Ok(role) => self.permissions.role(username) >= role || claim.is_member(username),
+                Err(_) => claim.is_member(username),
+            },
+            common::world::ClaimOwner::Player(_) => claim.is_member(username),
+        }
+    }
+
+    /// Finds the slot of the online player with `username`, ignoring case.
+    pub fn find_player(&self, username: &str) -> Option<usize> {
+        self.players.iter().position(|player| match player {
+            Some(player) => player.username.eq_ignore_ascii_case(username),
+            None => false,
+        })
     }
 }
 
@@ -28,12 +311,25 @@ impl State {
 struct Client {
     addr: SocketAddr,
     last_heard: f32,
+    limiter: rate_limit::ClientLimiter,
 }
 
 const TICKS_PER_SECOND: usize = 60;
 const SECONDS_PER_TICK: f32 = 1.0 / TICKS_PER_SECOND as f32;
 const MAX_CLIENTS: usize = 32;
 const CLIENT_TIMEOUT: f32 = 5.0;
+/// The most chunks a client may ask for in a single packet.
+const MAX_CHUNK_REQUEST: usize = 16;
+const CONFIG_PATH: &str = "server.ron";
+const PERMISSIONS_PATH: &str = "permissions.ron";
+const AUDIT_PATH: &str = "audit.log";
+const PLAYERS_PATH: &str = "players.ron";
+const ACCESS_PATH: &str = "access.ron";
+const WORLDS_PATH: &str = "worlds";
+const BIOMES_PATH: &str = "biomes.ron";
+const TILES_PATH: &str = "tiles.ron";
+const SCRIPTS_PATH: &str = "scripts";
+const STRUCTURES_PATH: &str = "worlds/structures";
 
 #[tokio::main]
 async fn main() -> crate::Result<()> {
@@ -41,7 +337,37 @@ async fn main() -> crate::Result<()> {
         .without_timestamps()
         .init()?;
 
-    let state = Arc::new(Mutex::new(State::new()));
+    let config = config::Config::load(CONFIG_PATH)?;
+    let permissions = permissions::Permissions::load(PERMISSIONS_PATH)?;
+    let audit = audit::AuditLog::load(AUDIT_PATH)?;
+    let identities = identity::Identities::load(PLAYERS_PATH)?;
+    let access = access::Access::load(ACCESS_PATH)?;
+
+    let tiles: common::world::TileRegistry = config::load_or_default(TILES_PATH)?;
+    let placed_tile = common::world::Tile {
+        ty: tiles.resolve(&config.placed_tile)?,
+    };
+
+    let biomes: common::world::BiomeConfig = config::load_or_default(BIOMES_PATH)?;
+    let structures = structures::Structures::new(STRUCTURES_PATH);
+    let worlds = worlds::Worlds::load(
+        WORLDS_PATH,
+        &config.worlds,
+        &config.default_world,
+        &biomes,
+        &tiles,
+        &structures,
+    )?;
+
+    let state = Arc::new(Mutex::new(State::new(
+        worlds,
+        permissions,
+        audit,
+        identities,
+        access,
+        tiles,
+        structures,
+    )));
     let state2 = state.clone();
 
     let clients = Arc::new(Mutex::new(
@@ -50,28 +376,197 @@ async fn main() -> crate::Result<()> {
             .collect::<Vec<_>>(),
     ));
 
-    let addr = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "0.0.0.0:8080".to_string());
+    let addr = env::args().nth(1).unwrap_or(config.address);
 
     let socket = UdpSocket::bind(&addr).await?;
     println!("Listening on: {}", socket.local_addr()?);
 
+    if let Some(metrics_address) = config.metrics_address {
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(metrics_address).await {
+                log::warn!("metrics stopped: {}", err);
+            }
+        });
+    }
+
     let s = Arc::new(socket);
     let s2 = s.clone();
     let s3 = s.clone();
 
     let clients2 = clients.clone();
 
+    let mut commands = command::Commands::new();
+    command::register_builtin(&mut commands);
+    command::register_claims(&mut commands);
+    command::register_edit(&mut commands);
+    command::register_access(&mut commands);
+
+    let mut plugins = plugin::Plugins::new();
+    plugins.register(plugin::Welcome);
+    plugins.register(plugin::Scripts::new(SCRIPTS_PATH));
+
+    let plugins = Arc::new(Mutex::new(plugins));
+    let plugins2 = plugins.clone();
+
+    let commands = Arc::new(commands);
+
+    let rate_limits = config.rate_limits;
+    let mut join_limiter = rate_limit::JoinLimiter::new(rate_limits.join);
+
+    tokio::spawn(console::run(
+        s.clone(),
+        clients.clone(),
+        state.clone(),
+        plugins.clone(),
+        commands.clone(),
+    ));
+
     tokio::spawn(async move {
-        loop {
-            let mut buf = [0; 1024];
+        let mut received = vec![0; common::MAX_DATAGRAM_SIZE];
 
-            let (len, addr) = s.recv_from(&mut buf).await.unwrap();
+        loop {
+            let (len, addr) = match s.recv_from(&mut received).await {
+                Ok(received) => received,
+                Err(err) => {
+                    log::warn!("Failed to receive: {}", err);
+                    continue;
+                }
+            };
             log::debug!("{} bytes received from {}", len, addr);
+            let buf = &received[..len];
+
+            let packet = match buf
+                .first()
+                .map(|&packet| common::ClientPacket::try_from(packet))
+            {
+                Some(Ok(packet)) => packet,
+                _ => {
+                    log::debug!("malformed packet from {}", addr);
+                    metrics::METRICS.malformed_packet();
+                    continue;
+                }
+            };
+
+            // every packet but join starts with the slot of the client
+            if packet != common::ClientPacket::Join && (len < 2 || buf[1] as usize >= MAX_CLIENTS) {
+                log::debug!("malformed {:?} packet from {}", packet, addr);
+                metrics::METRICS.malformed_packet();
+                continue;
+            }
+
+            metrics::METRICS.packet_received(packet, len);
+
+            if packet == common::ClientPacket::Join {
+                if !join_limiter.allow(addr.ip(), Instant::now()) {
+                    metrics::METRICS.rate_limited(packet);
+
+                    reject(&s, addr, common::JoinRejection::TooManyAttempts).await;
+                    continue;
+                }
+            } else {
+                let client_id = buf[1] as usize;
+
+                let c = &mut clients2.lock().await;
+
+                let (verdict, violations) = match &mut c[client_id] {
+                    Some(client) if client.addr == addr => (
+                        client.limiter.check(packet, &rate_limits, Instant::now()),
+                        client.limiter.violations(),
+                    ),
+                    _ => (rate_limit::Verdict::Allow, 0),
+                };
+
+                if verdict != rate_limit::Verdict::Allow {
+                    metrics::METRICS.rate_limited(packet);
+                }
+
+                match verdict {
+                    rate_limit::Verdict::Allow => {}
+                    rate_limit::Verdict::Drop => {
+                        log::debug!("dropped {:?} from client {}", packet, client_id);
+                        continue;
+                    }
+                    rate_limit::Verdict::Warn => {
+                        log::info!(
+                            "warned client {} after {} dropped messages",
+                            client_id,
+                            violations
+                        );
+                        metrics::METRICS.rate_limit_warning();
 
-            match common::ClientPacket::try_from(buf[0]).unwrap() {
+                        deny(
+                            &s,
+                            addr,
+                            "You are sending too fast, slow down or you will be kicked",
+                        )
+                        .await;
+                        continue;
+                    }
+                    rate_limit::Verdict::Kick => {
+                        log::info!(
+                            "kicking client {} after {} dropped messages",
+                            client_id,
+                            violations
+                        );
+                        metrics::METRICS.rate_limit_kick();
+
+                        let responses = disconnect(
+                            &s,
+                            c,
+                            &state,
+                            &plugins,
+                            client_id,
+                            Some("Kicked for sending too fast"),
+                        )
+                        .await;
+
+                        respond(&s, None, c, &state, &plugins, responses).await;
+                        continue;
+                    }
+                }
+            }
+
+            match packet {
                 common::ClientPacket::Join => {
+                    let username = match std::str::from_utf8(&buf[1..len]) {
+                        Ok(username) => username.to_string(),
+                        Err(_) => {
+                            let reason = "Username is not valid UTF-8".to_string();
+
+                            reject(&s, addr, common::JoinRejection::InvalidUsername(reason)).await;
+                            continue;
+                        }
+                    };
+
+                    if let Err(err) = common::validate_username(&username) {
+                        reject(
+                            &s,
+                            addr,
+                            common::JoinRejection::InvalidUsername(err.to_string()),
+                        )
+                        .await;
+                        continue;
+                    }
+
+                    let checked = {
+                        let state = state.lock().await;
+
+                        match state.access.check(&username, addr.ip(), audit::now()) {
+                            Ok(_) if state.shutting_down => {
+                                Err(common::JoinRejection::ShuttingDown)
+                            }
+                            Ok(_) if state.find_player(&username).is_some() => {
+                                Err(common::JoinRejection::UsernameTaken(username.clone()))
+                            }
+                            checked => checked,
+                        }
+                    };
+
+                    if let Err(rejection) = checked {
+                        reject(&s, addr, rejection).await;
+                        continue;
+                    }
+
                     let mut slot = -1;
 
                     for i in 0..MAX_CLIENTS {
@@ -84,11 +579,23 @@ async fn main() -> crate::Result<()> {
                     if slot != -1 {
                         log::info!("client will be assigned to slot: {}", slot);
 
-                        let serialized_world = bincode::encode_to_vec(
-                            &state.lock().await.world,
-                            bincode::config::standard(),
-                        )
-                        .unwrap();
+                        let (world, serialized_world) = {
+                            let state = state.lock().await;
+
+                            let world = state.worlds.default_name().to_string();
+
+                            // the client asks for the chunks around it afterwards
+                            let join_info = common::JoinInfo {
+                                world: state.worlds.get(&world).unwrap().without_chunks(),
+                                tiles: (*state.tiles).clone(),
+                            };
+
+                            let serialized_world =
+                                bincode::encode_to_vec(join_info, bincode::config::standard())
+                                    .unwrap();
+
+                            (world, serialized_world)
+                        };
 
                         let mut data = vec![slot as u8];
                         data.extend(serialized_world.iter().copied());
@@ -97,37 +604,47 @@ async fn main() -> crate::Result<()> {
                             .await
                             .unwrap();
 
-                        let split = buf.split_first().unwrap();
-
-                        let username = std::str::from_utf8(split.1)
-                            .unwrap()
-                            .trim_matches(char::from(0))
-                            .to_string();
-
                         let c = &mut clients2.lock().await;
 
                         c[slot as usize] = Some(Client {
                             addr,
                             last_heard: 0.0,
+                            limiter: rate_limit::ClientLimiter::new(&rate_limits, Instant::now()),
                         });
 
-                        state.lock().await.players[slot as usize] =
-                            Some(common::world::Player { username });
+                        {
+                            let state = &mut *state.lock().await;
 
-                        // inform all clients that a client joined the server
-                        // sent to the new client aswell so they get the player list
-                        broadcast(
-                            &s,
-                            None,
-                            c,
-                            common::ServerPacket::ClientJoin,
-                            bincode::encode_to_vec(
-                                state.lock().await.players.clone(),
-                                bincode::config::standard(),
-                            )
-                            .unwrap(),
-                        )
-                        .await;
+                            state.identities.join(&username, audit::now());
+
+                            if let Err(err) = state.identities.save() {
+                                log::warn!("Failed to save players: {}", err);
+                            }
+
+                            state.players[slot as usize] = Some(common::world::Player {
+                                username,
+                                world: world.clone(),
+                            });
+                            state.addresses[slot as usize] = Some(addr.ip());
+
+                            // inform the clients in the world that a client joined it
+                            // sent to the new client aswell so they get the player list
+                            send_players(&s, c, state, &world, common::ServerPacket::ClientJoin)
+                                .await;
+                        }
+
+                        let responses = {
+                            let state = &mut *state.lock().await;
+
+                            let mut ctx = plugin::PluginContext::new(state);
+                            plugins.lock().await.on_join(&mut ctx, slot as usize);
+
+                            ctx.into_responses()
+                        };
+
+                        respond(&s, Some(slot as u8), c, &state, &plugins, responses).await;
+                    } else {
+                        reject(&s, addr, common::JoinRejection::ServerFull).await;
                     }
                 }
                 common::ClientPacket::Leave => {
@@ -135,23 +652,10 @@ async fn main() -> crate::Result<()> {
 
                     let c = &mut clients2.lock().await;
 
-                    c[client_id as usize] = None;
+                    let responses =
+                        disconnect(&s, c, &state, &plugins, client_id as usize, None).await;
 
-                    state.lock().await.players[client_id as usize] = None;
-
-                    // inform all clients that a client left the server
-                    broadcast(
-                        &s,
-                        Some(client_id),
-                        &c,
-                        common::ServerPacket::ClientLeave,
-                        bincode::encode_to_vec(
-                            state.lock().await.players.clone(),
-                            bincode::config::standard(),
-                        )
-                        .unwrap(),
-                    )
-                    .await;
+                    respond(&s, None, c, &state, &plugins, responses).await;
                 }
                 common::ClientPacket::KeepAlive => {
                     let client_id = buf[1];
@@ -169,21 +673,77 @@ async fn main() -> crate::Result<()> {
 
                     if let Some(client) = &mut c[client_id as usize] {
                         if verify_client(addr, client.addr) {
-                            let split = buf.split_first().unwrap();
+                            let client_addr = client.addr;
+
+                            let text = String::from_utf8_lossy(&buf[2..len]);
+                            let text = text.trim_matches(char::from(0));
+
+                            if let Some(input) = text.strip_prefix('/') {
+                                let responses = commands
+                                    .execute(
+                                        Some(client_id as usize),
+                                        &mut *state.lock().await,
+                                        &mut *plugins.lock().await,
+                                        input,
+                                    )
+                                    .unwrap_or_else(|err| {
+                                        vec![command::Response::Reply(err.to_string())]
+                                    });
+
+                                respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+
+                                continue;
+                            }
+
+                            if !state
+                                .lock()
+                                .await
+                                .has_permission(client_id as usize, permissions::CHAT_SEND)
+                            {
+                                deny(&s, client_addr, "You do not have permission to chat").await;
+
+                                continue;
+                            }
+
+                            let mut text = text.to_string();
+
+                            let (result, responses) = {
+                                let state = &mut *state.lock().await;
+
+                                let mut ctx = plugin::PluginContext::new(state);
+                                let result = plugins.lock().await.on_chat(
+                                    &mut ctx,
+                                    client_id as usize,
+                                    &mut text,
+                                );
+
+                                (result, ctx.into_responses())
+                            };
+
+                            respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+
+                            if let plugin::EventResult::Cancel(reason) = result {
+                                if let Some(reason) = reason {
+                                    deny(&s, client_addr, &reason).await;
+                                }
+
+                                continue;
+                            }
+
+                            let state = state.lock().await;
 
                             // should always be some
-                            if let Some(player) = &state.lock().await.players[client_id as usize] {
-                                let message = player.username.clone()
-                                    + ": "
-                                    + std::str::from_utf8(split.1).unwrap();
+                            if let Some(player) = &state.players[client_id as usize] {
+                                let message = format!("{}: {}", player.username, text);
 
-                                // send chat message to all clients
-                                broadcast(
+                                // chat stays in the world of the sender
+                                broadcast_world(
                                     &s,
-                                    None,
-                                    &c,
+                                    c,
+                                    &state,
+                                    &player.world,
                                     common::ServerPacket::Chat,
-                                    message.as_bytes().to_vec(),
+                                    message.into_bytes(),
                                 )
                                 .await;
                             }
@@ -199,61 +759,374 @@ async fn main() -> crate::Result<()> {
 
                     if let Some(client) = &mut c[client_id as usize] {
                         if verify_client(addr, client.addr) {
-                            let deserialized_position: common::Position =
-                                bincode::serde::decode_from_slice(
-                                    split.1,
-                                    bincode::config::standard(),
-                                )
-                                .unwrap()
-                                .0;
+                            let client_addr = client.addr;
 
-                            let chunk_position = common::Position {
-                                x: deserialized_position.x / common::world::CHUNK_SIZE,
-                                y: deserialized_position.y / common::world::CHUNK_SIZE,
+                            let (deserialized_position, layer): (
+                                common::world::TilePos,
+                                common::world::Layer,
+                            ) = match bincode::serde::decode_from_slice(
+                                split.1,
+                                bincode::config::standard(),
+                            ) {
+                                Ok((click, _)) => click,
+                                Err(_) => {
+                                    metrics::METRICS.malformed_packet();
+                                    continue;
+                                }
                             };
 
-                            let tile_chunk_position = common::Position {
-                                x: deserialized_position.x
-                                    - common::world::CHUNK_SIZE
-                                        * (deserialized_position.x / common::world::CHUNK_SIZE),
-                                y: deserialized_position.y
-                                    - common::world::CHUNK_SIZE
-                                        * (deserialized_position.y / common::world::CHUNK_SIZE),
-                            };
+                            let chunk_position = deserialized_position.chunk();
 
-                            state
+                            let denied = state
                                 .lock()
                                 .await
-                                .world
-                                .chunks
-                                .get_mut((chunk_position.x, chunk_position.y))
-                                .unwrap()
-                                .tiles
-                                .get_mut((tile_chunk_position.x, tile_chunk_position.y))
-                                .unwrap()
-                                .ty = common::world::TileType::Sand;
-
-                            let serialized_chunk = bincode::serde::encode_to_vec(
-                                &state
-                                    .lock()
-                                    .await
-                                    .world
-                                    .chunks
-                                    .get((chunk_position.x, chunk_position.y))
-                                    .unwrap(),
+                                .edit_denied(client_id as usize, deserialized_position);
+
+                            if let Some(reason) = denied {
+                                deny(&s, client_addr, &reason).await;
+
+                                continue;
+                            }
+
+                            let (world, old) = {
+                                let state = &mut *state.lock().await;
+
+                                let world = state
+                                    .world_of(client_id as usize)
+                                    .unwrap_or_default()
+                                    .to_string();
+
+                                // clients can only click chunks they asked for, which
+                                // generated them, so this rarely generates anything
+                                match state.worlds.load_tile(&world, deserialized_position, layer) {
+                                    Some(loaded) => (world, loaded),
+                                    None => continue,
+                                }
+                            };
+
+                            // clicking the ground places a tile, clicking anything on
+                            // top of it clears it
+                            let new = match layer {
+                                common::world::Layer::Ground => Some(placed_tile),
+                                _ => None,
+                            };
+
+                            let change = plugin::TileChange {
+                                position: deserialized_position,
+                                change: common::world::Change::Tile { layer, old, new },
+                            };
+
+                            let (result, responses) = {
+                                let state = &mut *state.lock().await;
+
+                                let mut ctx = plugin::PluginContext::new(state);
+                                let result = plugins.lock().await.on_tile_change(
+                                    &mut ctx,
+                                    client_id as usize,
+                                    &change,
+                                );
+
+                                (result, ctx.into_responses())
+                            };
+
+                            respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+
+                            if let plugin::EventResult::Cancel(reason) = result {
+                                if let Some(reason) = reason {
+                                    deny(&s, client_addr, &reason).await;
+                                }
+
+                                continue;
+                            }
+
+                            {
+                                let state = &mut *state.lock().await;
+
+                                let username = state.players[client_id as usize]
+                                    .as_ref()
+                                    .map(|player| player.username.clone())
+                                    .unwrap_or_default();
+
+                                state.set_tile(&world, &username, change.position, layer, new);
+                            }
+
+                            // send the changed chunk to everyone in the world
+                            let responses =
+                                vec![command::Response::ChunkModified(world, chunk_position)];
+
+                            respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+                        }
+                    }
+                }
+                common::ClientPacket::EditTileEntity => {
+                    let split = buf.split_at(2);
+
+                    let client_id = split.0[1];
+
+                    let c = &mut clients2.lock().await;
+
+                    if let Some(client) = &mut c[client_id as usize] {
+                        if verify_client(addr, client.addr) {
+                            let client_addr = client.addr;
+
+                            let (position, edited): (
+                                common::world::TilePos,
+                                common::world::TileEntity,
+                            ) = match bincode::serde::decode_from_slice(
+                                split.1,
                                 bincode::config::standard(),
-                            )
-                            .unwrap();
+                            ) {
+                                Ok((edit, _)) => edit,
+                                Err(_) => {
+                                    metrics::METRICS.malformed_packet();
+                                    continue;
+                                }
+                            };
+
+                            let checked = {
+                                let state = state.lock().await;
+
+                                match state.edit_denied(client_id as usize, position) {
+                                    Some(reason) => Err(reason),
+                                    None => state.check_entity_edit(
+                                        client_id as usize,
+                                        position,
+                                        &edited,
+                                    ),
+                                }
+                            };
+
+                            let (world, old) = match checked {
+                                Ok(checked) => checked,
+                                Err(reason) => {
+                                    deny(&s, client_addr, &reason).await;
+
+                                    continue;
+                                }
+                            };
+
+                            let change = plugin::TileChange {
+                                position,
+                                change: common::world::Change::Entity {
+                                    old: Some(old),
+                                    new: Some(edited.clone()),
+                                },
+                            };
+
+                            let (result, responses) = {
+                                let state = &mut *state.lock().await;
 
-                            // send new world to all clients
-                            broadcast(
-                                &s,
-                                None,
-                                &c,
-                                common::ServerPacket::ChunkModified,
-                                serialized_chunk,
-                            )
-                            .await;
+                                let mut ctx = plugin::PluginContext::new(state);
+                                let result = plugins.lock().await.on_tile_change(
+                                    &mut ctx,
+                                    client_id as usize,
+                                    &change,
+                                );
+
+                                (result, ctx.into_responses())
+                            };
+
+                            respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+
+                            if let plugin::EventResult::Cancel(reason) = result {
+                                if let Some(reason) = reason {
+                                    deny(&s, client_addr, &reason).await;
+                                }
+
+                                continue;
+                            }
+
+                            {
+                                let state = &mut *state.lock().await;
+
+                                let username = state.players[client_id as usize]
+                                    .as_ref()
+                                    .map(|player| player.username.clone())
+                                    .unwrap_or_default();
+
+                                state.set_entity(&world, &username, position, Some(edited));
+                            }
+
+                            let responses =
+                                vec![command::Response::ChunkModified(world, position.chunk())];
+
+                            respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+                        }
+                    }
+                }
+                common::ClientPacket::ChangeHeight => {
+                    let split = buf.split_at(2);
+
+                    let client_id = split.0[1];
+
+                    let c = &mut clients2.lock().await;
+
+                    if let Some(client) = &mut c[client_id as usize] {
+                        if verify_client(addr, client.addr) {
+                            let client_addr = client.addr;
+
+                            let (position, change): (
+                                common::world::TilePos,
+                                common::world::HeightChange,
+                            ) = match bincode::serde::decode_from_slice(
+                                split.1,
+                                bincode::config::standard(),
+                            ) {
+                                Ok((edit, _)) => edit,
+                                Err(_) => {
+                                    metrics::METRICS.malformed_packet();
+                                    continue;
+                                }
+                            };
+
+                            let denied =
+                                state.lock().await.edit_denied(client_id as usize, position);
+
+                            if let Some(reason) = denied {
+                                deny(&s, client_addr, &reason).await;
+
+                                continue;
+                            }
+
+                            let (world, old) = {
+                                let state = &mut *state.lock().await;
+
+                                let world = state
+                                    .world_of(client_id as usize)
+                                    .unwrap_or_default()
+                                    .to_string();
+
+                                match state.load_height(&world, position) {
+                                    Some(old) => (world, old),
+                                    None => continue,
+                                }
+                            };
+
+                            let new = change.apply(old);
+
+                            // already as high or low as it goes
+                            if new == old {
+                                continue;
+                            }
+
+                            let change = plugin::TileChange {
+                                position,
+                                change: common::world::Change::Height { old, new },
+                            };
+
+                            let (result, responses) = {
+                                let state = &mut *state.lock().await;
+
+                                let mut ctx = plugin::PluginContext::new(state);
+                                let result = plugins.lock().await.on_tile_change(
+                                    &mut ctx,
+                                    client_id as usize,
+                                    &change,
+                                );
+
+                                (result, ctx.into_responses())
+                            };
+
+                            respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+
+                            if let plugin::EventResult::Cancel(reason) = result {
+                                if let Some(reason) = reason {
+                                    deny(&s, client_addr, &reason).await;
+                                }
+
+                                continue;
+                            }
+
+                            {
+                                let state = &mut *state.lock().await;
+
+                                let username = state.players[client_id as usize]
+                                    .as_ref()
+                                    .map(|player| player.username.clone())
+                                    .unwrap_or_default();
+
+                                state.set_height(&world, &username, position, new);
+                            }
+
+                            let responses =
+                                vec![command::Response::ChunkModified(world, position.chunk())];
+
+                            respond(&s, Some(client_id), c, &state, &plugins, responses).await;
+                        }
+                    }
+                }
+                common::ClientPacket::RequestChunks => {
+                    let split = buf.split_at(2);
+
+                    let client_id = split.0[1];
+
+                    let c = &mut clients2.lock().await;
+
+                    if let Some(client) = &mut c[client_id as usize] {
+                        if verify_client(addr, client.addr) {
+                            let client_addr = client.addr;
+
+                            let positions: Vec<common::world::ChunkPos> =
+                                match bincode::serde::decode_from_slice(
+                                    split.1,
+                                    bincode::config::standard(),
+                                ) {
+                                    Ok((positions, _)) => positions,
+                                    Err(_) => {
+                                        metrics::METRICS.malformed_packet();
+                                        continue;
+                                    }
+                                };
+
+                            if positions.len() > MAX_CHUNK_REQUEST {
+                                metrics::METRICS.malformed_packet();
+                                continue;
+                            }
+
+                            let serialized_chunks = {
+                                let state = &mut *state.lock().await;
+
+                                let world = match state.world_of(client_id as usize) {
+                                    Some(world) => world.to_string(),
+                                    None => continue,
+                                };
+
+                                // chunks are generated the first time anyone asks for them
+                                if !state.worlds.load_chunks(&world, &positions) {
+                                    continue;
+                                }
+
+                                let world = match state.worlds.get(&world) {
+                                    Some(world) => world,
+                                    None => continue,
+                                };
+
+                                positions
+                                    .into_iter()
+                                    .filter_map(|position| world.chunk(position))
+                                    .map(|chunk| {
+                                        bincode::serde::encode_to_vec(
+                                            chunk,
+                                            bincode::config::standard(),
+                                        )
+                                        .unwrap()
+                                    })
+                                    .collect::<Vec<_>>()
+                            };
+
+                            for serialized_chunk in serialized_chunks {
+                                if send(
+                                    &s,
+                                    client_addr,
+                                    common::ServerPacket::ChunkModified,
+                                    serialized_chunk,
+                                )
+                                .await
+                                .is_err()
+                                {
+                                    log::warn!("Failed to send");
+                                }
+                            }
                         }
                     }
                 }
@@ -265,38 +1138,90 @@ async fn main() -> crate::Result<()> {
 
     let mut interval = time::interval(time::Duration::from_secs_f32(SECONDS_PER_TICK));
 
+    let mut second_timer = 0.0;
+    let mut world_edits = 0;
+
+    let signal = shutdown::signal();
+    tokio::pin!(signal);
+
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = interval.tick() => {}
+            signal = &mut signal => {
+                log::info!("received {}, shutting down", signal);
+                break;
+            }
+        }
+
+        let tick_start = Instant::now();
+
+        let c = &mut clients3.lock().await;
+
+        let mut timed_out = Vec::new();
 
-        for (client_id, client) in clients3.lock().await.iter_mut().enumerate() {
+        for (client_id, client) in c.iter_mut().enumerate() {
             if let Some(client) = client {
                 client.last_heard += SECONDS_PER_TICK;
 
                 if client.last_heard > CLIENT_TIMEOUT {
                     log::warn!("client {} timed out", client_id);
+                    metrics::METRICS.timeout();
 
-                    let c = &mut clients3.lock().await;
+                    timed_out.push(client_id);
+                }
+            }
+        }
 
-                    c[client_id] = None;
+        let mut responses = Vec::new();
 
-                    state2.lock().await.players[client_id] = None;
+        for client_id in timed_out {
+            responses.extend(disconnect(&s3, c, &state2, &plugins2, client_id, None).await);
+        }
 
-                    // inform all other clients that a client left the server
-                    broadcast(
-                        &s3,
-                        Some(client_id as u8),
-                        &c,
-                        common::ServerPacket::ClientLeave,
-                        bincode::encode_to_vec(
-                            state2.lock().await.players.clone(),
-                            bincode::config::standard(),
-                        )?,
-                    )
-                    .await;
-                }
-            }
+        {
+            let state = &mut *state2.lock().await;
+
+            let mut ctx = plugin::PluginContext::new(state);
+            plugins2.lock().await.on_tick(&mut ctx, SECONDS_PER_TICK);
+
+            responses.extend(ctx.into_responses());
+        }
+
+        respond(&s3, None, c, &state2, &plugins2, responses).await;
+
+        metrics::METRICS.set_players(c.iter().flatten().count());
+
+        second_timer += SECONDS_PER_TICK;
+
+        if second_timer >= 1.0 {
+            second_timer = 0.0;
+
+            let total = metrics::METRICS.world_edit_count();
+            metrics::METRICS.set_world_edits_per_second(total - world_edits);
+            world_edits = total;
         }
+
+        metrics::METRICS.tick(tick_start.elapsed().as_secs_f64());
     }
+
+    let shutdown = shutdown::run(&s3, &clients3, &state2, &plugins2);
+
+    let code = match time::timeout(shutdown::SHUTDOWN_TIMEOUT, shutdown).await {
+        Ok(Ok(())) => 0,
+        Ok(Err(err)) => {
+            log::error!("Failed to save: {}", err);
+            1
+        }
+        Err(_) => {
+            log::error!(
+                "shutting down took longer than {:?}, exiting anyway",
+                shutdown::SHUTDOWN_TIMEOUT
+            );
+            1
+        }
+    };
+
+    std::process::exit(code);
 }
 
 fn verify_client(addr1: SocketAddr, addr2: SocketAddr) -> bool {
@@ -305,6 +1230,7 @@ fn verify_client(addr1: SocketAddr, addr2: SocketAddr) -> bool {
     }
 
     log::warn!("message from {} expected {}", addr1, addr2);
+    metrics::METRICS.dropped_packet();
     return false;
 }
 
@@ -334,6 +1260,357 @@ async fn broadcast(
     }
 }
 
+/// Sends a packet to every client whose player is in `world`.
+async fn broadcast_world(
+    socket: &UdpSocket,
+    clients: &[Option<Client>],
+    state: &State,
+    world: &str,
+    packet: common::ServerPacket,
+    data: Vec<u8>,
+) {
+    for (client_id, client) in clients.iter().enumerate() {
+        if let Some(client) = client {
+            if state.world_of(client_id) != Some(world) {
+                continue;
+            }
+
+            if send(socket, client.addr, packet, data.clone())
+                .await
+                .is_err()
+            {
+                log::warn!("Failed to send");
+            }
+        }
+    }
+}
+
+/// Sends the players in `world` to every client in it, as `ClientJoin` or `ClientLeave`.
+async fn send_players(
+    socket: &UdpSocket,
+    clients: &[Option<Client>],
+    state: &State,
+    world: &str,
+    packet: common::ServerPacket,
+) {
+    let players = state
+        .players
+        .iter()
+        .map(|player| {
+            player
+                .as_ref()
+                .filter(|player| player.world == world)
+                .cloned()
+        })
+        .collect::<Vec<_>>();
+
+    let serialized_players = bincode::encode_to_vec(players, bincode::config::standard()).unwrap();
+
+    broadcast_world(socket, clients, state, world, packet, serialized_players).await;
+}
+
+/// Sends the responses of a command or plugin, `Response::Reply` goes to `client_id`.
+///
+/// Responses can come from scripts, so those for slots that don't exist are dropped.
+async fn respond(
+    socket: &UdpSocket,
+    client_id: Option<u8>,
+    clients: &mut Vec<Option<Client>>,
+    state: &Mutex<State>,
+    plugins: &Mutex<plugin::Plugins>,
+    responses: Vec<command::Response>,
+) {
+    let mut responses = VecDeque::from(responses);
+
+    while let Some(response) = responses.pop_front() {
+        let (target, packet, message) = match response {
+            command::Response::Reply(message) => match client_id {
+                Some(client_id) => (
+                    client_id as usize,
+                    common::ServerPacket::SystemMessage,
+                    message,
+                ),
+                None => {
+                    log::debug!("dropping reply without a player: {}", message);
+
+                    continue;
+                }
+            },
+            command::Response::System(target, message) => {
+                (target, common::ServerPacket::SystemMessage, message)
+            }
+            command::Response::Whisper(target, message) => {
+                (target, common::ServerPacket::Chat, message)
+            }
+            command::Response::Broadcast(message) => {
+                broadcast(
+                    socket,
+                    None,
+                    clients,
+                    common::ServerPacket::Chat,
+                    message.into_bytes(),
+                )
+                .await;
+
+                continue;
+            }
+            command::Response::BroadcastWorld(world, message) => {
+                broadcast_world(
+                    socket,
+                    clients,
+                    &*state.lock().await,
+                    &world,
+                    common::ServerPacket::Chat,
+                    message.into_bytes(),
+                )
+                .await;
+
+                continue;
+            }
+            command::Response::ChunkModified(world, position) => {
+                let state = state.lock().await;
+
+                let serialized_chunk = match state
+                    .worlds
+                    .get(&world)
+                    .and_then(|world| world.chunk(position))
+                {
+                    Some(chunk) => {
+                        bincode::serde::encode_to_vec(chunk, bincode::config::standard()).unwrap()
+                    }
+                    None => continue,
+                };
+
+                broadcast_world(
+                    socket,
+                    clients,
+                    &state,
+                    &world,
+                    common::ServerPacket::ChunkModified,
+                    serialized_chunk,
+                )
+                .await;
+
+                continue;
+            }
+            command::Response::ClaimsModified(world) => {
+                let state = state.lock().await;
+
+                let serialized_claims = match state.worlds.get(&world) {
+                    Some(world) => {
+                        bincode::serde::encode_to_vec(&world.claims, bincode::config::standard())
+                            .unwrap()
+                    }
+                    None => continue,
+                };
+
+                broadcast_world(
+                    socket,
+                    clients,
+                    &state,
+                    &world,
+                    common::ServerPacket::ClaimsModified,
+                    serialized_claims,
+                )
+                .await;
+
+                continue;
+            }
+            command::Response::MoveWorld(target, world) => {
+                let state = &mut *state.lock().await;
+
+                let serialized_world = match state.worlds.get(&world) {
+                    Some(world) => {
+                        bincode::encode_to_vec(world.without_chunks(), bincode::config::standard())
+                            .unwrap()
+                    }
+                    None => continue,
+                };
+
+                let (old, username) = match (state.players.get_mut(target), clients.get(target)) {
+                    (Some(Some(player)), Some(Some(_))) => (
+                        std::mem::replace(&mut player.world, world.clone()),
+                        player.username.clone(),
+                    ),
+                    _ => continue,
+                };
+
+                log::info!("moving {} from {} to {}", username, old, world);
+
+                if let Some(Some(client)) = clients.get(target) {
+                    if send(
+                        socket,
+                        client.addr,
+                        common::ServerPacket::WorldChanged,
+                        serialized_world,
+                    )
+                    .await
+                    .is_err()
+                    {
+                        log::warn!("Failed to send");
+                    }
+                }
+
+                send_players(
+                    socket,
+                    clients,
+                    state,
+                    &old,
+                    common::ServerPacket::ClientLeave,
+                )
+                .await;
+                send_players(
+                    socket,
+                    clients,
+                    state,
+                    &world,
+                    common::ServerPacket::ClientJoin,
+                )
+                .await;
+
+                continue;
+            }
+            command::Response::AccessModified => {
+                let state = state.lock().await;
+                let now = audit::now();
+
+                for (client_id, client) in clients.iter().enumerate() {
+                    if let (Some(client), Some(player)) = (client, &state.players[client_id]) {
+                        if let Err(rejection) =
+                            state.access.check(&player.username, client.addr.ip(), now)
+                        {
+                            responses.push_back(command::Response::Kick(
+                                client_id,
+                                rejection.to_string(),
+                            ));
+                        }
+                    }
+                }
+
+                continue;
+            }
+            command::Response::Kick(target, reason) => {
+                responses.extend(
+                    disconnect(socket, clients, state, plugins, target, Some(&reason)).await,
+                );
+
+                continue;
+            }
+        };
+
+        if let Some(Some(client)) = clients.get(target) {
+            if send(socket, client.addr, packet, message.into_bytes())
+                .await
+                .is_err()
+            {
+                log::warn!("Failed to send");
+            }
+        }
+    }
+}
+
+/// Tells a client why their message was rejected.
+async fn deny(socket: &UdpSocket, addr: SocketAddr, reason: &str) {
+    if send(
+        socket,
+        addr,
+        common::ServerPacket::SystemMessage,
+        reason.as_bytes().to_vec(),
+    )
+    .await
+    .is_err()
+    {
+        log::warn!("Failed to send");
+    }
+}
+
+/// Refuses a join request, telling the client why.
+async fn reject(socket: &UdpSocket, addr: SocketAddr, rejection: common::JoinRejection) {
+    log::info!("rejected join from {}: {}", addr, rejection);
+
+    if send(
+        socket,
+        addr,
+        common::ServerPacket::JoinRejected,
+        bincode::encode_to_vec(rejection, bincode::config::standard()).unwrap(),
+    )
+    .await
+    .is_err()
+    {
+        log::warn!("Failed to send");
+    }
+}
+
+/// Removes a client from the server and tells everyone else that they left.
+///
+/// The client is told the `reason` when they did not leave on their own. Returns
+/// what plugins want to send, replies are dropped as the player is gone.
+async fn disconnect(
+    socket: &UdpSocket,
+    clients: &mut [Option<Client>],
+    state: &Mutex<State>,
+    plugins: &Mutex<plugin::Plugins>,
+    client_id: usize,
+    reason: Option<&str>,
+) -> Vec<command::Response> {
+    let client = match clients.get_mut(client_id).and_then(Option::take) {
+        Some(client) => client,
+        None => return Vec::new(),
+    };
+
+    if let Some(reason) = reason {
+        log::info!("disconnecting client {}: {}", client_id, reason);
+
+        if send(
+            socket,
+            client.addr,
+            common::ServerPacket::Disconnect,
+            reason.as_bytes().to_vec(),
+        )
+        .await
+        .is_err()
+        {
+            log::warn!("Failed to send");
+        }
+    }
+
+    let responses = {
+        let state = &mut *state.lock().await;
+
+        let mut ctx = plugin::PluginContext::new(state);
+        plugins.lock().await.on_leave(&mut ctx, client_id);
+
+        let responses = ctx.into_responses();
+
+        state.addresses[client_id] = None;
+
+        if let Some(player) = state.players[client_id].take() {
+            state.identities.leave(&player.username, audit::now());
+
+            if let Err(err) = state.identities.save() {
+                log::warn!("Failed to save players: {}", err);
+            }
+
+            // inform the other clients in the world that a client left it
+            send_players(
+                socket,
+                clients,
+                state,
+                &player.world,
+                common::ServerPacket::ClientLeave,
+            )
+            .await;
+        }
+
+        responses
+    };
+
+    responses
+        .into_iter()
+        .filter(|response| !matches!(response, command::Response::Reply(_)))
+        .collect()
+}
+
 async fn send(
     socket: &UdpSocket,
     addr: SocketAddr,
@@ -346,5 +1623,142 @@ async fn send(
     let len = socket.send_to(&bytes, &addr).await?;
     log::debug!("{} bytes sent", len);
 
+    metrics::METRICS.packet_sent(packet, len);
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::*;
+
+    /// Where the files of the test `name` go.
+    pub fn directory(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("server-{}-{}", process::id(), name))
+    }
+
+    /// A server with a flat world called `test` and the admin `alice` in slot 0, its
+    /// files are written to a directory of its own called `name`.
+    pub fn state(name: &str) -> State {
+        let directory = directory(name);
+
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+
+        let tiles = common::world::TileRegistry::default();
+        let structures = structures::Structures::new(directory.join("structures"));
+
+        let worlds = worlds::Worlds::load(
+            directory.join("worlds"),
+            &[config::WorldConfig {
+                name: "test".to_string(),
+                protected: false,
+                seed: Some("test".to_string()),
+                generator: "flat".to_string(),
+                structures: Vec::new(),
+            }],
+            "test",
+            &common::world::BiomeConfig::default(),
+            &tiles,
+            &structures,
+        )
+        .unwrap();
+
+        let mut permissions = permissions::Permissions::default();
+        permissions.set_role("alice", permissions::Role::Admin);
+
+        let mut state = State::new(
+            worlds,
+            permissions,
+            audit::AuditLog::load(directory.join("audit.log")).unwrap(),
+            identity::Identities::load(directory.join("players.ron")).unwrap(),
+            access::Access::load(directory.join("access.ron")).unwrap(),
+            tiles,
+            structures,
+        );
+
+        state.players[0] = Some(common::world::Player {
+            username: "alice".to_string(),
+            world: "test".to_string(),
+        });
+
+        state
+    }
+
+    #[tokio::test]
+    async fn the_largest_entity_edit_is_received() {
+        use common::world::{Region, TileEntity, TileEntityError, TilePos};
+
+        let mut state = state("entity");
+        let position = TilePos::new(3, 4);
+        state
+            .worlds
+            .load_chunks("test", &Region::new(position, position).chunks());
+        let world = state.worlds.get_mut("test").unwrap();
+        world.set_entity(position, Some(TileEntity::Container { items: Vec::new() }));
+
+        let largest = TileEntity::Container {
+            items: vec!["\u{1F4E6}".repeat(TileEntity::MAX_TEXT_LENGTH); TileEntity::MAX_ITEMS],
+        };
+
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+
+        let mut packet = vec![common::ClientPacket::EditTileEntity as u8, 0];
+        packet.extend(
+            bincode::serde::encode_to_vec((position, &largest), bincode::config::standard())
+                .unwrap(),
+        );
+        client.send(&packet).await.unwrap();
+
+        let mut received = vec![0; common::MAX_DATAGRAM_SIZE];
+        let (len, _) = server.recv_from(&mut received).await.unwrap();
+        assert_eq!(len, packet.len());
+
+        let ((received_position, edited), _): ((TilePos, TileEntity), _) =
+            bincode::serde::decode_from_slice(&received[2..len], bincode::config::standard())
+                .unwrap();
+        assert_eq!(received_position, position);
+        assert_eq!(edited, largest);
+
+        assert!(state.check_entity_edit(0, position, &edited).is_ok());
+        assert!(state.set_entity("test", "alice", position, Some(edited.clone())));
+
+        // other containers fill the chunk until it has no room for another
+        let chunk = position.chunk();
+        let mut denied = None;
+        for local in common::world::LocalPos::all().filter(|local| *local != position.local()) {
+            let other = chunk.tile(local).unwrap();
+            let world = state.worlds.get_mut("test").unwrap();
+            world.set_entity(other, Some(TileEntity::Container { items: Vec::new() }));
+
+            if let Err(err) = state.check_entity_edit(0, other, &largest) {
+                denied = Some(err);
+                break;
+            }
+            state.set_entity("test", "alice", other, Some(largest.clone()));
+        }
+        assert_eq!(
+            denied,
+            Some(format!(
+                "You can't edit that, {}",
+                TileEntityError::ChunkFull
+            ))
+        );
+
+        // and the full chunk still reaches clients in one packet
+        let chunk = state.worlds.get("test").unwrap().chunk(chunk).unwrap();
+        let mut packet = vec![common::ServerPacket::ChunkModified as u8];
+        packet.extend(bincode::serde::encode_to_vec(chunk, bincode::config::standard()).unwrap());
+        server
+            .send_to(&packet, client.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let (len, _) = client.recv_from(&mut received).await.unwrap();
+        assert_eq!(&received[..len], &packet[..]);
+    }
+}
@@ -0,0 +1,438 @@
+//! Server health counters, served over HTTP in the Prometheus text format.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time,
+};
+
+pub static METRICS: Metrics = Metrics::new();
+
+/// How long to wait after a connection could not be accepted, so running out of file
+/// descriptors does not turn into a busy loop.
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Traffic {
+    packets: u64,
+    bytes: u64,
+}
+
+pub struct Metrics {
+    players: AtomicU64,
+    traffic: Mutex<BTreeMap<(Direction, String), Traffic>>,
+    malformed_packets: AtomicU64,
+    dropped_packets: AtomicU64,
+    rate_limited: Mutex<BTreeMap<String, u64>>,
+    rate_limit_warnings: AtomicU64,
+    rate_limit_kicks: AtomicU64,
+    timeouts: AtomicU64,
+    world_edits: AtomicU64,
+    world_edits_per_second: AtomicU64,
+    /// Stored as the bits of an `f64`.
+    tick_duration: AtomicU64,
+    tick_duration_sum: AtomicU64,
+    ticks: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            players: AtomicU64::new(0),
+            traffic: Mutex::new(BTreeMap::new()),
+            malformed_packets: AtomicU64::new(0),
+            dropped_packets: AtomicU64::new(0),
+            rate_limited: Mutex::new(BTreeMap::new()),
+            rate_limit_warnings: AtomicU64::new(0),
+            rate_limit_kicks: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            world_edits: AtomicU64::new(0),
+            world_edits_per_second: AtomicU64::new(0),
+            tick_duration: AtomicU64::new(0),
+            tick_duration_sum: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_players(&self, players: usize) {
+        self.players.store(players as u64, Ordering::Relaxed);
+    }
+
+    pub fn packet_received(&self, packet: common::ClientPacket, bytes: usize) {
+        self.record(Direction::In, format!("{:?}", packet), bytes);
+    }
+
+    pub fn packet_sent(&self, packet: common::ServerPacket, bytes: usize) {
+        self.record(Direction::Out, format!("{:?}", packet), bytes);
+    }
+
+    /// A packet that could not be decoded.
+    pub fn malformed_packet(&self) {
+        self.malformed_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A packet that was ignored, e.g. from an address that does not own the slot.
+    pub fn dropped_packet(&self) {
+        self.dropped_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A packet that was dropped because its client went over a rate limit.
+    pub fn rate_limited(&self, packet: common::ClientPacket) {
+        let mut rate_limited = self
+            .rate_limited
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        *rate_limited.entry(format!("{:?}", packet)).or_default() += 1;
+    }
+
+    pub fn rate_limit_warning(&self) {
+        self.rate_limit_warnings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rate_limit_kick(&self) {
+        self.rate_limit_kicks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn world_edits(&self, count: usize) {
+        self.world_edits.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn world_edit_count(&self) -> u64 {
+        self.world_edits.load(Ordering::Relaxed)
+    }
+
+    pub fn set_world_edits_per_second(&self, edits: u64) {
+        self.world_edits_per_second.store(edits, Ordering::Relaxed);
+    }
+
+    pub fn tick(&self, seconds: f64) {
+        self.tick_duration
+            .store(seconds.to_bits(), Ordering::Relaxed);
+
+        // only the tick loop writes these so load and store is fine
+        let sum = f64::from_bits(self.tick_duration_sum.load(Ordering::Relaxed)) + seconds;
+        self.tick_duration_sum
+            .store(sum.to_bits(), Ordering::Relaxed);
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record(&self, direction: Direction, packet: String, bytes: usize) {
+        let mut traffic = self.traffic.lock().unwrap_or_else(|err| err.into_inner());
+
+        let entry = traffic.entry((direction, packet)).or_default();
+        entry.packets += 1;
+        entry.bytes += bytes as u64;
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed);
+        let load_f64 = |value: &AtomicU64| f64::from_bits(value.load(Ordering::Relaxed));
+
+        header(&mut out, "wanhope_players", "gauge", "Connected players.");
+        let _ = writeln!(out, "wanhope_players {}", load(&self.players));
+
+        header(
+            &mut out,
+            "wanhope_malformed_packets_total",
+            "counter",
+            "Received packets that could not be decoded.",
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_malformed_packets_total {}",
+            load(&self.malformed_packets)
+        );
+
+        header(
+            &mut out,
+            "wanhope_dropped_packets_total",
+            "counter",
+            "Received packets that were ignored.",
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_dropped_packets_total {}",
+            load(&self.dropped_packets)
+        );
+
+        header(
+            &mut out,
+            "wanhope_rate_limited_packets_total",
+            "counter",
+            "Received packets dropped by rate limits, by type.",
+        );
+        for (packet, count) in self
+            .rate_limited
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "wanhope_rate_limited_packets_total{{type=\"{}\"}} {}",
+                packet, count
+            );
+        }
+
+        header(
+            &mut out,
+            "wanhope_rate_limit_warnings_total",
+            "counter",
+            "Clients warned for going over a rate limit.",
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_rate_limit_warnings_total {}",
+            load(&self.rate_limit_warnings)
+        );
+
+        header(
+            &mut out,
+            "wanhope_rate_limit_kicks_total",
+            "counter",
+            "Clients kicked for going over a rate limit.",
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_rate_limit_kicks_total {}",
+            load(&self.rate_limit_kicks)
+        );
+
+        header(
+            &mut out,
+            "wanhope_timeouts_total",
+            "counter",
+            "Clients that timed out.",
+        );
+        let _ = writeln!(out, "wanhope_timeouts_total {}", load(&self.timeouts));
+
+        header(
+            &mut out,
+            "wanhope_world_edits_total",
+            "counter",
+            "Tiles changed in the world.",
+        );
+        let _ = writeln!(out, "wanhope_world_edits_total {}", load(&self.world_edits));
+
+        header(
+            &mut out,
+            "wanhope_world_edits_per_second",
+            "gauge",
+            "Tiles changed in the world during the last second.",
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_world_edits_per_second {}",
+            load(&self.world_edits_per_second)
+        );
+
+        header(
+            &mut out,
+            "wanhope_last_tick_duration_seconds",
+            "gauge",
+            "Duration of the last server tick.",
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_last_tick_duration_seconds {}",
+            load_f64(&self.tick_duration)
+        );
+
+        header(
+            &mut out,
+            "wanhope_tick_duration_seconds",
+            "summary",
+            "Duration of server ticks.",
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_tick_duration_seconds_sum {}",
+            load_f64(&self.tick_duration_sum)
+        );
+        let _ = writeln!(
+            out,
+            "wanhope_tick_duration_seconds_count {}",
+            load(&self.ticks)
+        );
+
+        let traffic = self.traffic.lock().unwrap_or_else(|err| err.into_inner());
+
+        header(
+            &mut out,
+            "wanhope_packets_total",
+            "counter",
+            "Packets by direction and type.",
+        );
+        for ((direction, packet), traffic) in traffic.iter() {
+            let _ = writeln!(
+                out,
+                "wanhope_packets_total{} {}",
+                labels(*direction, packet),
+                traffic.packets
+            );
+        }
+
+        header(
+            &mut out,
+            "wanhope_bytes_total",
+            "counter",
+            "Bytes by direction and packet type.",
+        );
+        for ((direction, packet), traffic) in traffic.iter() {
+            let _ = writeln!(
+                out,
+                "wanhope_bytes_total{} {}",
+                labels(*direction, packet),
+                traffic.bytes
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labels(direction: Direction, packet: &str) -> String {
+    let direction = match direction {
+        Direction::In => "in",
+        Direction::Out => "out",
+    };
+
+    format!("{{direction=\"{}\",type=\"{}\"}}", direction, packet)
+}
+
+/// Answers every HTTP request on `addr` with the metrics, `/metrics` is the usual path.
+/// Only fails if `addr` can't be listened on.
+pub async fn serve(addr: String) -> crate::Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    log::info!(
+        "Serving metrics on: http://{}/metrics",
+        listener.local_addr()?
+    );
+
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                log::warn!("failed to accept a metrics connection: {}", err);
+                time::sleep(ACCEPT_RETRY).await;
+
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            let mut request = [0; 1024];
+
+            // the request itself does not matter, only that the client sent one
+            if stream.read(&mut request).await.is_err() {
+                return;
+            }
+
+            let body = METRICS.render();
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+
+            if let Err(err) = stream.write_all(response.as_bytes()).await {
+                log::debug!("failed to serve metrics: {}", err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(metrics: &Metrics) -> Vec<String> {
+        metrics.render().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn every_metric_has_help_and_type() {
+        let lines = lines(&Metrics::new());
+
+        for (name, kind) in [
+            ("wanhope_players", "gauge"),
+            ("wanhope_rate_limited_packets_total", "counter"),
+            ("wanhope_world_edits_total", "counter"),
+            ("wanhope_tick_duration_seconds", "summary"),
+            ("wanhope_packets_total", "counter"),
+            ("wanhope_bytes_total", "counter"),
+        ] {
+            let help = lines
+                .iter()
+                .position(|line| line.starts_with(&format!("# HELP {} ", name)))
+                .unwrap_or_else(|| panic!("{} has no HELP", name));
+
+            assert_eq!(lines[help + 1], format!("# TYPE {} {}", name, kind));
+        }
+
+        // nothing was counted so there are no labelled samples yet
+        assert!(!lines.iter().any(|line| line.contains('{')));
+        assert!(lines.contains(&"wanhope_world_edits_total 0".to_string()));
+    }
+
+    #[test]
+    fn counters_are_rendered_with_their_labels() {
+        let metrics = Metrics::new();
+
+        metrics.packet_received(common::ClientPacket::Chat, 10);
+        metrics.packet_received(common::ClientPacket::Chat, 15);
+        metrics.packet_sent(common::ServerPacket::ChunkModified, 300);
+        metrics.rate_limited(common::ClientPacket::WorldClick);
+        metrics.rate_limited(common::ClientPacket::WorldClick);
+        metrics.world_edits(3);
+        metrics.world_edits(4);
+
+        let lines = lines(&metrics);
+
+        for expected in [
+            "wanhope_packets_total{direction=\"in\",type=\"Chat\"} 2",
+            "wanhope_bytes_total{direction=\"in\",type=\"Chat\"} 25",
+            "wanhope_packets_total{direction=\"out\",type=\"ChunkModified\"} 1",
+            "wanhope_bytes_total{direction=\"out\",type=\"ChunkModified\"} 300",
+            "wanhope_rate_limited_packets_total{type=\"WorldClick\"} 2",
+            "wanhope_world_edits_total 7",
+        ] {
+            assert!(
+                lines.iter().any(|line| line == expected),
+                "{} is missing",
+                expected
+            );
+        }
+        assert_eq!(metrics.world_edit_count(), 7);
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+pub const WORLD_EDIT: &str = "world.edit";
+pub const WORLD_CLAIM: &str = "world.claim";
+pub const CHAT_SEND: &str = "chat.send";
+pub const ADMIN_KICK: &str = "admin.kick";
+pub const ADMIN_BAN: &str = "admin.ban";
+pub const ADMIN_WHITELIST: &str = "admin.whitelist";
+pub const ADMIN_ROLE: &str = "admin.role";
+pub const ADMIN_AUDIT: &str = "admin.audit";
+pub const ADMIN_ROLLBACK: &str = "admin.rollback";
+/// Edit inside and manage every claim.
+pub const ADMIN_CLAIMS: &str = "admin.claims";
+pub const ADMIN_PROTECT: &str = "admin.protect";
+/// Change large areas at once with `/fill`, `/paste` and the like.
+pub const ADMIN_EDIT: &str = "admin.edit";
+/// Move other players between worlds.
+pub const ADMIN_WORLD: &str = "admin.world";
+
+/// Roles are ordered, every role also holds the permissions of the roles below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Role {
+    Guest,
+    Member,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Guest, Role::Member, Role::Moderator, Role::Admin];
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.to_string().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// Roles and the permissions they grant, persisted as RON.
+///
+/// A granted permission of `*` matches everything and `admin.*` matches every
+/// permission starting with `admin.`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Permissions {
+    /// The role of players that are not listed in `players`.
+    pub default_role: Role,
+    pub roles: BTreeMap<Role, BTreeSet<String>>,
+    /// Lowercase usernames mapped to their role.
+    pub players: BTreeMap<String, Role>,
+
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        let grant = |permissions: &[&str]| {
+            permissions
+                .iter()
+                .map(|permission| permission.to_string())
+                .collect::<BTreeSet<_>>()
+        };
+
+        let roles = BTreeMap::from([
+            (Role::Guest, grant(&[CHAT_SEND])),
+            (Role::Member, grant(&[WORLD_EDIT, WORLD_CLAIM])),
+            (
+                Role::Moderator,
+                grant(&[ADMIN_KICK, ADMIN_BAN, ADMIN_AUDIT, ADMIN_ROLLBACK]),
+            ),
+            (Role::Admin, grant(&["*"])),
+        ]);
+
+        Self {
+            default_role: Role::Member,
+            roles,
+            players: BTreeMap::new(),
+
+            path: PathBuf::new(),
+        }
+    }
+}
+
+impl Permissions {
+    /// Loads the permissions at `path`, writing the defaults there if it does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            let mut permissions = ron::from_str::<Self>(&fs::read_to_string(path)?)?;
+            permissions.path = path.to_path_buf();
+
+            return Ok(permissions);
+        }
+
+        log::info!("creating default permissions at {}", path.display());
+
+        let permissions = Self {
+            path: path.to_path_buf(),
+            ..Self::default()
+        };
+
+        permissions.save()?;
+
+        Ok(permissions)
+    }
+
+    pub fn save(&self) -> crate::Result<()> {
+        let serialized = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        fs::write(&self.path, serialized)?;
+
+        Ok(())
+    }
+
+    pub fn role(&self, username: &str) -> Role {
+        self.players
+            .get(&username.to_lowercase())
+            .copied()
+            .unwrap_or(self.default_role)
+    }
+
+    pub fn set_role(&mut self, username: &str, role: Role) {
+        self.players.insert(username.to_lowercase(), role);
+    }
+
+    pub fn has(&self, username: &str, permission: &str) -> bool {
+        let role = self.role(username);
+
+        self.roles
+            .iter()
+            .filter(|(r, _)| **r <= role)
+            .flat_map(|(_, granted)| granted)
+            .any(|granted| matches(granted, permission))
+    }
+}
+
+fn matches(granted: &str, permission: &str) -> bool {
+    if granted == "*" || granted == permission {
+        return true;
+    }
+
+    match granted.strip_suffix('*') {
+        Some(prefix) => prefix.ends_with('.') && permission.starts_with(prefix),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_are_ordered() {
+        assert!(Role::Guest < Role::Member);
+        assert!(Role::Member < Role::Moderator);
+        assert!(Role::Moderator < Role::Admin);
+
+        let mut sorted = Role::ALL;
+        sorted.sort();
+        assert_eq!(sorted, Role::ALL);
+
+        assert_eq!("Moderator".parse(), Ok(Role::Moderator));
+        assert_eq!("owner".parse::<Role>(), Err(()));
+    }
+
+    #[test]
+    fn wildcards_match_prefixes() {
+        assert!(matches("*", "admin.kick"));
+        assert!(matches("chat.send", "chat.send"));
+        assert!(matches("admin.*", "admin.kick"));
+        assert!(matches("admin.*", "admin.world.move"));
+
+        assert!(!matches("chat.send", "chat.sen"));
+        assert!(!matches("world.edit", "world.edit.more"));
+        assert!(!matches("admin.*", "admin"));
+        assert!(!matches("admin.*", "administrator.kick"));
+        assert!(!matches("adm*", "admin.kick"));
+        assert!(!matches("admin.kick", "admin.*"));
+    }
+
+    #[test]
+    fn roles_inherit_the_permissions_below_them() {
+        let mut permissions = Permissions::default();
+        permissions.set_role("Guest", Role::Guest);
+        permissions.set_role("mod", Role::Moderator);
+        permissions.set_role("root", Role::Admin);
+        permissions
+            .roles
+            .get_mut(&Role::Member)
+            .unwrap()
+            .insert("world.*".to_string());
+
+        assert!(permissions.has("guest", CHAT_SEND));
+        assert!(!permissions.has("guest", WORLD_EDIT));
+
+        // players that are not listed get the default role
+        assert_eq!(permissions.role("someone"), Role::Member);
+        assert!(permissions.has("someone", CHAT_SEND));
+        assert!(permissions.has("someone", "world.anything"));
+        assert!(!permissions.has("someone", ADMIN_KICK));
+
+        assert!(permissions.has("MOD", CHAT_SEND));
+        assert!(permissions.has("mod", WORLD_CLAIM));
+        assert!(permissions.has("mod", ADMIN_ROLLBACK));
+        assert!(!permissions.has("mod", ADMIN_ROLE));
+
+        assert!(permissions.has("root", ADMIN_ROLE));
+        assert!(permissions.has("root", "anything.at.all"));
+    }
+}
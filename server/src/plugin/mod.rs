@@ -0,0 +1,7 @@
+mod registry;
+mod script;
+mod welcome;
+
+pub use registry::*;
+pub use script::*;
+pub use welcome::*;
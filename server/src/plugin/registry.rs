@@ -0,0 +1,343 @@
+use common::world::Change;
+
+use crate::{
+    command::{CommandError, Response},
+    State,
+};
+
+/// A tile a player wants to change, before it is applied to the world.
+#[derive(Debug, Clone)]
+pub struct TileChange {
+    pub position: common::world::TilePos,
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventResult {
+    Continue,
+    /// Stops the event, the optional reason is sent to the player that caused it.
+    Cancel(Option<String>),
+}
+
+pub struct PluginContext<'a> {
+    pub state: &'a mut State,
+
+    responses: Vec<Response>,
+}
+
+impl<'a> PluginContext<'a> {
+    pub fn new(state: &'a mut State) -> Self {
+        Self {
+            state,
+
+            responses: Vec::new(),
+        }
+    }
+
+    /// Queues a message, `Response::Reply` goes to the player that caused the event.
+    pub fn respond(&mut self, response: Response) {
+        self.responses.push(response);
+    }
+
+    pub fn into_responses(self) -> Vec<Response> {
+        self.responses
+    }
+}
+
+/// Game rules that hook into server events, every callback does nothing by default.
+pub trait ServerPlugin: Send {
+    fn name(&self) -> &'static str;
+
+    fn on_join(&mut self, _ctx: &mut PluginContext, _client_id: usize) {}
+
+    fn on_leave(&mut self, _ctx: &mut PluginContext, _client_id: usize) {}
+
+    /// Called for chat that is not a command, `message` can be rewritten.
+    fn on_chat(
+        &mut self,
+        _ctx: &mut PluginContext,
+        _client_id: usize,
+        _message: &mut String,
+    ) -> EventResult {
+        EventResult::Continue
+    }
+
+    /// Called before a player changes a tile, its height or its entity, cancel to veto.
+    ///
+    /// Commands like `/fill` that change many tiles at once ask after changing the world
+    /// and undo what is vetoed. Edits from the console and rollbacks are not asked about.
+    fn on_tile_change(
+        &mut self,
+        _ctx: &mut PluginContext,
+        _client_id: usize,
+        _change: &TileChange,
+    ) -> EventResult {
+        EventResult::Continue
+    }
+
+    fn on_tick(&mut self, _ctx: &mut PluginContext, _delta: f32) {}
+
+    /// Runs a command the server does not know, returns whether the plugin handled it.
+    fn on_command(
+        &mut self,
+        _ctx: &mut PluginContext,
+        _client_id: usize,
+        _name: &str,
+        _input: &str,
+    ) -> Result<bool, CommandError> {
+        Ok(false)
+    }
+
+    /// Lines shown by `/help` for the commands handled in `on_command`.
+    fn help(&self, _state: &State, _client_id: usize) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Every registered plugin, called in the order they were registered.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Box<dyn ServerPlugin>>,
+}
+
+impl Plugins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, plugin: impl ServerPlugin + 'static) {
+        log::info!("registered plugin {}", plugin.name());
+
+        self.plugins.push(Box::new(plugin));
+    }
+
+    pub fn on_join(&mut self, ctx: &mut PluginContext, client_id: usize) {
+        for plugin in &mut self.plugins {
+            plugin.on_join(ctx, client_id);
+        }
+    }
+
+    pub fn on_leave(&mut self, ctx: &mut PluginContext, client_id: usize) {
+        for plugin in &mut self.plugins {
+            plugin.on_leave(ctx, client_id);
+        }
+    }
+
+    /// Stops at the first plugin that cancels the message.
+    pub fn on_chat(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: usize,
+        message: &mut String,
+    ) -> EventResult {
+        for plugin in &mut self.plugins {
+            if let EventResult::Cancel(reason) = plugin.on_chat(ctx, client_id, message) {
+                log::debug!("{} cancelled a chat message", plugin.name());
+
+                return EventResult::Cancel(reason);
+            }
+        }
+
+        EventResult::Continue
+    }
+
+    /// Stops at the first plugin that vetoes the change.
+    pub fn on_tile_change(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: usize,
+        change: &TileChange,
+    ) -> EventResult {
+        for plugin in &mut self.plugins {
+            if let EventResult::Cancel(reason) = plugin.on_tile_change(ctx, client_id, change) {
+                log::debug!(
+                    "{} vetoed {:?} at {}",
+                    plugin.name(),
+                    change.change,
+                    change.position
+                );
+
+                return EventResult::Cancel(reason);
+            }
+        }
+
+        EventResult::Continue
+    }
+
+    pub fn on_tick(&mut self, ctx: &mut PluginContext, delta: f32) {
+        for plugin in &mut self.plugins {
+            plugin.on_tick(ctx, delta);
+        }
+    }
+
+    /// Stops at the first plugin that handles the command.
+    pub fn on_command(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: usize,
+        name: &str,
+        input: &str,
+    ) -> Result<bool, CommandError> {
+        for plugin in &mut self.plugins {
+            if plugin.on_command(ctx, client_id, name, input)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    pub fn help(&self, state: &State, client_id: usize) -> Vec<String> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.help(state, client_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use common::world::{Layer, Tile, TilePos};
+
+    use super::*;
+    use crate::{
+        command::{register_edit, Commands},
+        tests::state,
+    };
+
+    /// Appends to chat and cancels events whose text contains `stop`, writing down
+    /// every call in `calls`.
+    struct Rule {
+        name: &'static str,
+        append: &'static str,
+        stop: Option<&'static str>,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Rule {
+        fn stopped(&self, text: &str) -> EventResult {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}: {}", self.name, text));
+
+            match self.stop {
+                Some(stop) if text.contains(stop) => {
+                    EventResult::Cancel(Some(format!("{} stopped it", self.name)))
+                }
+                _ => EventResult::Continue,
+            }
+        }
+    }
+
+    impl ServerPlugin for Rule {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn on_chat(
+            &mut self,
+            _ctx: &mut PluginContext,
+            _client_id: usize,
+            message: &mut String,
+        ) -> EventResult {
+            message.push_str(self.append);
+
+            self.stopped(message)
+        }
+
+        fn on_tile_change(
+            &mut self,
+            _ctx: &mut PluginContext,
+            _client_id: usize,
+            change: &TileChange,
+        ) -> EventResult {
+            self.stopped(&change.position.to_string())
+        }
+    }
+
+    fn plugins(calls: &Arc<Mutex<Vec<String>>>) -> Plugins {
+        let mut plugins = Plugins::new();
+
+        for (name, append, stop) in [
+            ("a", "!", None),
+            ("b", "?", Some("bad!?")),
+            ("c", ".", Some("5, ")),
+        ] {
+            plugins.register(Rule {
+                name,
+                append,
+                stop,
+                calls: calls.clone(),
+            });
+        }
+
+        plugins
+    }
+
+    #[test]
+    fn chat_is_rewritten_in_order_until_cancelled() {
+        let mut state = state("plugin-chat");
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut plugins = plugins(&calls);
+        let mut ctx = PluginContext::new(&mut state);
+
+        let mut message = "hi".to_string();
+        assert_eq!(
+            plugins.on_chat(&mut ctx, 0, &mut message),
+            EventResult::Continue
+        );
+        assert_eq!(message, "hi!?.");
+
+        // b only stops the message a rewrote, c never sees it
+        calls.lock().unwrap().clear();
+        let mut message = "bad".to_string();
+        assert_eq!(
+            plugins.on_chat(&mut ctx, 0, &mut message),
+            EventResult::Cancel(Some("b stopped it".to_string()))
+        );
+        assert_eq!(*calls.lock().unwrap(), ["a: bad!", "b: bad!?"]);
+    }
+
+    #[test]
+    fn vetoed_tiles_are_left_as_they_were() {
+        let mut state = state("plugin-veto");
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut plugins = plugins(&calls);
+
+        let position = TilePos::new(5, 0);
+        let before = state
+            .worlds
+            .load_tile("test", position, Layer::Ground)
+            .unwrap();
+
+        let change = TileChange {
+            position,
+            change: Change::Tile {
+                layer: Layer::Ground,
+                old: before,
+                new: None,
+            },
+        };
+        assert_eq!(
+            plugins.on_tile_change(&mut PluginContext::new(&mut state), 0, &change),
+            EventResult::Cancel(Some("c stopped it".to_string()))
+        );
+        assert_eq!(*calls.lock().unwrap(), ["a: 5, 0", "b: 5, 0", "c: 5, 0"]);
+
+        let mut commands = Commands::new();
+        register_edit(&mut commands);
+        commands
+            .execute(Some(0), &mut state, &mut plugins, "fill 5 0 6 0 stone")
+            .unwrap();
+
+        let world = state.worlds.get("test").unwrap();
+        let stone = Some(Tile {
+            ty: state.tiles.resolve("stone").unwrap(),
+        });
+        assert_eq!(world.tile(position, Layer::Ground), before);
+        assert_eq!(world.tile(TilePos::new(6, 0), Layer::Ground), stone);
+    }
+}
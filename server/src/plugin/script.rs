@@ -0,0 +1,821 @@
+//! Rhai scripts loaded from a directory, reloaded when they change on disk.
+//!
+//! Scripts handle events by defining any of these functions:
+//!
+//! - `on_join(player)` and `on_leave(player)`
+//! - `on_chat(player, message)`, return `false` to cancel or a string to rewrite the message
+//! - `on_tile_change(player, x, y, layer, old, new)`, return `false` to veto, `old` and
+//!   `new` are empty strings where the layer is empty
+//! - `on_height_change(player, x, y, old, new)`, return `false` to veto
+//! - `on_entity_change(player, x, y, kind)` when a sign, container or the like is
+//!   edited, return `false` to veto
+//! - `on_tick(delta)`, which has no player so the world functions below work on the
+//!   default world
+//!
+//! and can call:
+//!
+//! - `tile(x, y)` and `set_tile(x, y, id)` with string tile ids, generating the chunk if
+//!   needed, and `tile(x, y, layer)` and `set_tile(x, y, layer, id)` for layers other
+//!   than the ground, where an empty id clears the layer, these work on
+//!   the world of the player the event is about and on the default world in `on_tick`
+//! - `world_name()` for the name of that world
+//! - `players()` as an array of `#{ id, username, world }`
+//! - `send_chat(message)` and `send_message(player, message)`
+//! - `register_command(name, description, Fn("callback"))`, optionally with a permission,
+//!   the callback is called with the player and the rest of the input
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use common::world::Change;
+use rhai::{Dynamic, Engine, FnPtr, AST};
+
+use crate::{
+    command::{CommandError, Response},
+    State, MAX_CLIENTS,
+};
+
+use super::{EventResult, PluginContext, ServerPlugin, TileChange};
+
+/// How often the script directory is checked for changes.
+const RELOAD_INTERVAL: f32 = 1.0;
+
+struct ScriptCommand {
+    name: String,
+    description: String,
+    permission: Option<String>,
+    callback: FnPtr,
+}
+
+struct Script {
+    path: PathBuf,
+    modified: SystemTime,
+    ast: AST,
+    commands: Vec<ScriptCommand>,
+}
+
+/// What the functions registered with the engine work on during a call.
+#[derive(Default)]
+struct Bindings {
+    world_name: String,
+    world: common::world::World,
+    generator: Option<Arc<dyn common::world::WorldGenerator>>,
+    tiles: Arc<common::world::TileRegistry>,
+    players: Vec<Option<common::world::Player>>,
+
+    responses: Vec<Response>,
+    modified_chunks: BTreeSet<common::world::ChunkPos>,
+    edits: Vec<(
+        common::world::TilePos,
+        common::world::Layer,
+        Option<common::world::Tile>,
+        Option<common::world::Tile>,
+    )>,
+    commands: Vec<ScriptCommand>,
+}
+
+impl Bindings {
+    /// The position of `x` and `y` and the generator of the world, if the world has one
+    /// and the coordinates fit.
+    fn locate(
+        &self,
+        x: i64,
+        y: i64,
+    ) -> Option<(
+        common::world::TilePos,
+        Arc<dyn common::world::WorldGenerator>,
+    )> {
+        let position = common::world::TilePos::new(i32::try_from(x).ok()?, i32::try_from(y).ok()?);
+
+        Some((position, self.generator.clone()?))
+    }
+
+    /// The string id of a tile, empty if the layer is empty or the tile is outside the world.
+    fn tile(&mut self, x: i64, y: i64, layer: common::world::Layer) -> String {
+        let tile = self.locate(x, y).and_then(|(position, generator)| {
+            self.world.load_tile(position, layer, generator.as_ref())
+        });
+
+        match tile {
+            Some(tile) => self.tiles.id_of(tile.ty),
+            None => String::new(),
+        }
+    }
+
+    /// Clears the layer if `id` is empty, false if `id` is unknown or the tile is outside
+    /// the world.
+    fn set_tile(&mut self, x: i64, y: i64, layer: common::world::Layer, id: &str) -> bool {
+        let new = if id.is_empty() {
+            None
+        } else {
+            match self.tiles.resolve(id) {
+                Ok(ty) => Some(common::world::Tile { ty }),
+                Err(_) => return false,
+            }
+        };
+
+        match self.locate(x, y) {
+            Some((position, generator)) => {
+                let old = self
+                    .world
+                    .load_and_set_tile(position, layer, new, generator.as_ref());
+
+                crate::metrics::METRICS.world_edits(1);
+
+                self.world.update_entity(position, &self.tiles);
+
+                self.modified_chunks.insert(position.chunk());
+                self.edits.push((position, layer, old, new));
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct Scripts {
+    directory: PathBuf,
+
+    engine: Engine,
+    bindings: Arc<Mutex<Bindings>>,
+
+    scripts: Vec<Script>,
+
+    reload_timer: f32,
+}
+
+impl Scripts {
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        let bindings = Arc::new(Mutex::new(Bindings::default()));
+
+        let mut scripts = Self {
+            directory: directory.as_ref().to_path_buf(),
+
+            engine: create_engine(&bindings),
+            bindings,
+
+            scripts: Vec::new(),
+
+            reload_timer: 0.0,
+        };
+
+        scripts.reload();
+
+        scripts
+    }
+
+    /// Compiles scripts that are new or changed and forgets the ones that were removed.
+    ///
+    /// A script that fails to compile keeps running its previous version.
+    fn reload(&mut self) {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(_) => {
+                self.scripts.clear();
+                return;
+            }
+        };
+
+        let mut found = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .filter_map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((path, modified))
+            })
+            .collect::<Vec<_>>();
+
+        found.sort();
+
+        self.scripts.retain(|script| {
+            let exists = found.iter().any(|(path, _)| *path == script.path);
+
+            if !exists {
+                log::info!("unloaded script {}", script.path.display());
+            }
+
+            exists
+        });
+
+        for (path, modified) in found {
+            let loaded = self.scripts.iter().position(|script| script.path == path);
+
+            if let Some(i) = loaded {
+                if self.scripts[i].modified == modified {
+                    continue;
+                }
+            }
+
+            match self.load(&path, modified) {
+                Ok(script) => {
+                    log::info!("loaded script {}", path.display());
+
+                    match loaded {
+                        Some(i) => self.scripts[i] = script,
+                        None => self.scripts.push(script),
+                    }
+                }
+                Err(err) => {
+                    log::warn!("failed to load script {}: {}", path.display(), err);
+
+                    // don't retry until it changes again
+                    if let Some(i) = loaded {
+                        self.scripts[i].modified = modified;
+                    }
+                }
+            }
+        }
+    }
+
+    fn load(&self, path: &Path, modified: SystemTime) -> Result<Script, String> {
+        let ast = self
+            .engine
+            .compile_file(path.to_path_buf())
+            .map_err(|err| err.to_string())?;
+
+        // top level statements run once, this is where commands are registered
+        lock(&self.bindings).commands.clear();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.engine.run_ast(&ast)));
+
+        let commands = std::mem::take(&mut lock(&self.bindings).commands);
+
+        match result {
+            Ok(Ok(())) => Ok(Script {
+                path: path.to_path_buf(),
+                modified,
+                ast,
+                commands,
+            }),
+            Ok(Err(err)) => Err(err.to_string()),
+            Err(_) => Err("panicked".to_string()),
+        }
+    }
+
+    /// Lends the world of `client_id`, or the default world without one, to the
+    /// engine for the duration of `f`.
+    fn with_bindings<T>(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: Option<usize>,
+        f: impl FnOnce(&Engine, &[Script]) -> T,
+    ) -> T {
+        let world_name = client_id
+            .and_then(|client_id| ctx.state.world_of(client_id))
+            .unwrap_or_else(|| ctx.state.worlds.default_name())
+            .to_string();
+
+        {
+            let mut bindings = lock(&self.bindings);
+
+            bindings.world = ctx
+                .state
+                .worlds
+                .get_mut(&world_name)
+                .map(std::mem::take)
+                .unwrap_or_default();
+            bindings.world_name = world_name.clone();
+            bindings.generator = ctx.state.worlds.generator(&world_name);
+            bindings.tiles = ctx.state.tiles.clone();
+            bindings.players = ctx.state.players.clone();
+        }
+
+        let result = f(&self.engine, &self.scripts);
+
+        let mut bindings = lock(&self.bindings);
+
+        if let Some(world) = ctx.state.worlds.get_mut(&world_name) {
+            *world = std::mem::take(&mut bindings.world);
+        }
+
+        for response in bindings.responses.drain(..) {
+            ctx.respond(response);
+        }
+
+        for position in std::mem::take(&mut bindings.modified_chunks) {
+            ctx.respond(Response::ChunkModified(world_name.clone(), position));
+        }
+
+        for (position, layer, old, new) in bindings.edits.drain(..) {
+            ctx.state.audit.record(
+                &world_name,
+                "script",
+                position,
+                Change::Tile { layer, old, new },
+            );
+        }
+
+        result
+    }
+
+    /// Calls `name` in every script that defines it, errors are logged and skipped.
+    fn call(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: Option<usize>,
+        name: &str,
+        args: impl Fn() -> Vec<Dynamic>,
+    ) -> Vec<Dynamic> {
+        let arity = args().len();
+
+        self.with_bindings(ctx, client_id, |engine, scripts| {
+            scripts
+                .iter()
+                .filter(|script| {
+                    script
+                        .ast
+                        .iter_functions()
+                        .any(|f| f.name == name && f.params.len() == arity)
+                })
+                .filter_map(|script| {
+                    let options = rhai::CallFnOptions::new().eval_ast(false);
+
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        engine.call_fn_with_options::<Dynamic>(
+                            options,
+                            &mut rhai::Scope::new(),
+                            &script.ast,
+                            name,
+                            args(),
+                        )
+                    }));
+
+                    match result {
+                        Ok(Ok(value)) => Some(value),
+                        Ok(Err(err)) => {
+                            log::warn!("{} in {}: {}", name, script.path.display(), err);
+                            None
+                        }
+                        Err(_) => {
+                            log::warn!("{} in {} panicked", name, script.path.display());
+                            None
+                        }
+                    }
+                })
+                .collect()
+        })
+    }
+}
+
+impl ServerPlugin for Scripts {
+    fn name(&self) -> &'static str {
+        "scripts"
+    }
+
+    fn on_join(&mut self, ctx: &mut PluginContext, client_id: usize) {
+        self.call(ctx, Some(client_id), "on_join", || {
+            vec![Dynamic::from(client_id as i64)]
+        });
+    }
+
+    fn on_leave(&mut self, ctx: &mut PluginContext, client_id: usize) {
+        self.call(ctx, Some(client_id), "on_leave", || {
+            vec![Dynamic::from(client_id as i64)]
+        });
+    }
+
+    fn on_chat(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: usize,
+        message: &mut String,
+    ) -> EventResult {
+        let original = message.clone();
+
+        for value in self.call(ctx, Some(client_id), "on_chat", || {
+            vec![
+                Dynamic::from(client_id as i64),
+                Dynamic::from(original.clone()),
+            ]
+        }) {
+            if value.as_bool() == Ok(false) {
+                return EventResult::Cancel(None);
+            }
+
+            if let Ok(rewritten) = value.into_string() {
+                *message = rewritten;
+            }
+        }
+
+        EventResult::Continue
+    }
+
+    fn on_tile_change(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: usize,
+        change: &TileChange,
+    ) -> EventResult {
+        let id_of = |tile: Option<common::world::Tile>| {
+            tile.map_or_else(String::new, |tile| ctx.state.tiles.id_of(tile.ty))
+        };
+
+        let player = Dynamic::from(client_id as i64);
+        let x = Dynamic::from(change.position.x as i64);
+        let y = Dynamic::from(change.position.y as i64);
+
+        let (name, args) = match &change.change {
+            Change::Tile { layer, old, new } => (
+                "on_tile_change",
+                vec![
+                    player,
+                    x,
+                    y,
+                    Dynamic::from(layer.name().to_string()),
+                    Dynamic::from(id_of(*old)),
+                    Dynamic::from(id_of(*new)),
+                ],
+            ),
+            Change::Height { old, new } => (
+                "on_height_change",
+                vec![
+                    player,
+                    x,
+                    y,
+                    Dynamic::from(*old as i64),
+                    Dynamic::from(*new as i64),
+                ],
+            ),
+            Change::Entity { old, new } => (
+                "on_entity_change",
+                vec![
+                    player,
+                    x,
+                    y,
+                    Dynamic::from(
+                        new.as_ref()
+                            .or(old.as_ref())
+                            .map_or("", |entity| entity.kind())
+                            .to_string(),
+                    ),
+                ],
+            ),
+        };
+
+        let vetoed = self
+            .call(ctx, Some(client_id), name, || args.clone())
+            .iter()
+            .any(|value| value.as_bool() == Ok(false));
+
+        if vetoed {
+            EventResult::Cancel(None)
+        } else {
+            EventResult::Continue
+        }
+    }
+
+    fn on_tick(&mut self, ctx: &mut PluginContext, delta: f32) {
+        self.reload_timer += delta;
+
+        if self.reload_timer >= RELOAD_INTERVAL {
+            self.reload_timer = 0.0;
+            self.reload();
+        }
+
+        self.call(ctx, None, "on_tick", || vec![Dynamic::from(delta as f64)]);
+    }
+
+    fn on_command(
+        &mut self,
+        ctx: &mut PluginContext,
+        client_id: usize,
+        name: &str,
+        input: &str,
+    ) -> Result<bool, CommandError> {
+        let found = self.scripts.iter().enumerate().find_map(|(i, script)| {
+            script
+                .commands
+                .iter()
+                .position(|command| command.name == name)
+                .map(|j| (i, j))
+        });
+
+        let (i, j) = match found {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+
+        let command = &self.scripts[i].commands[j];
+
+        if let Some(permission) = &command.permission {
+            if !ctx.state.has_permission(client_id, permission) {
+                return Err(CommandError::Failed(format!(
+                    "You do not have permission to use /{}",
+                    name
+                )));
+            }
+        }
+
+        let callback = command.callback.clone();
+
+        let result = self.with_bindings(ctx, Some(client_id), |engine, scripts| {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                callback.call::<Dynamic>(
+                    engine,
+                    &scripts[i].ast,
+                    (client_id as i64, input.to_string()),
+                )
+            }))
+        });
+
+        match result {
+            Ok(Ok(value)) => {
+                if let Ok(reply) = value.into_string() {
+                    ctx.respond(Response::Reply(reply));
+                }
+
+                Ok(true)
+            }
+            Ok(Err(err)) => {
+                log::warn!("/{} in {}: {}", name, self.scripts[i].path.display(), err);
+
+                Err(CommandError::Failed(format!("/{} failed", name)))
+            }
+            Err(_) => Err(CommandError::Failed(format!("/{} failed", name))),
+        }
+    }
+
+    fn help(&self, state: &State, client_id: usize) -> Vec<String> {
+        self.scripts
+            .iter()
+            .flat_map(|script| &script.commands)
+            .filter(|command| match &command.permission {
+                Some(permission) => state.has_permission(client_id, permission),
+                None => true,
+            })
+            .map(|command| format!("/{} - {}", command.name, command.description))
+            .collect()
+    }
+}
+
+/// A script that panicked inside a binding should not take the server down with it.
+fn lock(bindings: &Mutex<Bindings>) -> MutexGuard<'_, Bindings> {
+    bindings.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn create_engine(bindings: &Arc<Mutex<Bindings>>) -> Engine {
+    let mut engine = Engine::new();
+
+    // keep a runaway script from hanging the server
+    engine.set_max_operations(100_000);
+    engine.set_max_call_levels(32);
+    engine.set_max_string_size(4096);
+    engine.set_max_array_size(4096);
+    engine.set_max_map_size(4096);
+
+    engine.on_print(|text| log::info!("[script] {}", text));
+
+    let b = bindings.clone();
+    engine.register_fn("tile", move |x: i64, y: i64| -> String {
+        lock(&b).tile(x, y, common::world::Layer::Ground)
+    });
+
+    let b = bindings.clone();
+    engine.register_fn("tile", move |x: i64, y: i64, layer: &str| -> String {
+        match layer.parse() {
+            Ok(layer) => lock(&b).tile(x, y, layer),
+            Err(()) => String::new(),
+        }
+    });
+
+    let b = bindings.clone();
+    engine.register_fn("set_tile", move |x: i64, y: i64, id: &str| -> bool {
+        lock(&b).set_tile(x, y, common::world::Layer::Ground, id)
+    });
+
+    let b = bindings.clone();
+    engine.register_fn(
+        "set_tile",
+        move |x: i64, y: i64, layer: &str, id: &str| -> bool {
+            match layer.parse() {
+                Ok(layer) => lock(&b).set_tile(x, y, layer, id),
+                Err(()) => false,
+            }
+        },
+    );
+
+    let b = bindings.clone();
+    engine.register_fn("world_name", move || -> String {
+        lock(&b).world_name.clone()
+    });
+
+    let b = bindings.clone();
+    engine.register_fn("players", move || -> rhai::Array {
+        lock(&b)
+            .players
+            .iter()
+            .enumerate()
+            .filter_map(|(id, player)| {
+                let player = player.as_ref()?;
+
+                let mut map = rhai::Map::new();
+                map.insert("id".into(), Dynamic::from(id as i64));
+                map.insert("username".into(), Dynamic::from(player.username.clone()));
+                map.insert("world".into(), Dynamic::from(player.world.clone()));
+
+                Some(Dynamic::from_map(map))
+            })
+            .collect()
+    });
+
+    let b = bindings.clone();
+    engine.register_fn("send_chat", move |message: &str| {
+        lock(&b)
+            .responses
+            .push(Response::Broadcast(message.to_string()));
+    });
+
+    let b = bindings.clone();
+    engine.register_fn("send_message", move |player: i64, message: &str| {
+        // a slot that does not exist would be out of range when the message is sent
+        match usize::try_from(player) {
+            Ok(player) if player < MAX_CLIENTS => lock(&b)
+                .responses
+                .push(Response::System(player, message.to_string())),
+            _ => log::warn!("send_message to player {} who can't exist", player),
+        }
+    });
+
+    let b = bindings.clone();
+    engine.register_fn(
+        "register_command",
+        move |name: &str, description: &str, callback: FnPtr| {
+            lock(&b).commands.push(ScriptCommand {
+                name: name.to_string(),
+                description: description.to_string(),
+                permission: None,
+                callback,
+            });
+        },
+    );
+
+    let b = bindings.clone();
+    engine.register_fn(
+        "register_command",
+        move |name: &str, description: &str, callback: FnPtr, permission: &str| {
+            lock(&b).commands.push(ScriptCommand {
+                name: name.to_string(),
+                description: description.to_string(),
+                permission: Some(permission.to_string()),
+                callback,
+            });
+        },
+    );
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::Duration};
+
+    use common::world::{Tile, TilePos};
+
+    use super::*;
+    use crate::tests::{directory, state};
+
+    /// Loads `files` as scripts from a directory of the test `name`, `state` must be
+    /// called for `name` first since it clears the directory.
+    fn scripts(name: &str, files: &[(&str, &str)]) -> Scripts {
+        let directory = directory(name).join("scripts");
+        fs::create_dir_all(&directory).unwrap();
+
+        for (file, source) in files {
+            fs::write(directory.join(file), source).unwrap();
+        }
+
+        Scripts::new(directory)
+    }
+
+    /// Replaces a script and moves its modification time on so `reload` notices.
+    fn rewrite(scripts: &Scripts, file: &str, source: &str, seconds: u64) {
+        let path = scripts.directory.join(file);
+        fs::write(&path, source).unwrap();
+
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    fn chat(scripts: &mut Scripts, state: &mut State, message: &str) -> (EventResult, String) {
+        let mut message = message.to_string();
+        let result = scripts.on_chat(&mut PluginContext::new(state), 0, &mut message);
+
+        (result, message)
+    }
+
+    #[test]
+    fn failing_scripts_are_skipped() {
+        let mut state = state("script-failing");
+        let mut scripts = scripts(
+            "script-failing",
+            &[
+                ("a_runaway.rhai", "fn on_chat(player, message) { loop {} }"),
+                ("b_panic.rhai", "fn on_chat(player, message) { crash() }"),
+                (
+                    "c_throw.rhai",
+                    "fn on_chat(player, message) { throw \"oops\" }",
+                ),
+                (
+                    "d_shout.rhai",
+                    "fn on_chat(player, message) { message.to_upper() }",
+                ),
+            ],
+        );
+        scripts
+            .engine
+            .register_fn("crash", || -> bool { panic!("a binding crashed") });
+        assert_eq!(scripts.scripts.len(), 4);
+
+        assert_eq!(
+            chat(&mut scripts, &mut state, "hello"),
+            (EventResult::Continue, "HELLO".to_string())
+        );
+    }
+
+    #[test]
+    fn scripts_that_fail_to_compile_keep_their_last_version() {
+        let mut state = state("script-reload");
+        let mut scripts = scripts(
+            "script-reload",
+            &[("version.rhai", "fn on_chat(player, message) { \"one\" }")],
+        );
+        assert_eq!(chat(&mut scripts, &mut state, "hi").1, "one");
+
+        rewrite(
+            &scripts,
+            "version.rhai",
+            "fn on_chat(player, message) { \"two\" ",
+            1,
+        );
+        scripts.reload();
+        assert_eq!(chat(&mut scripts, &mut state, "hi").1, "one");
+
+        rewrite(
+            &scripts,
+            "version.rhai",
+            "fn on_chat(player, message) { \"three\" }",
+            2,
+        );
+        scripts.reload();
+        assert_eq!(chat(&mut scripts, &mut state, "hi").1, "three");
+
+        fs::remove_file(scripts.directory.join("version.rhai")).unwrap();
+        scripts.reload();
+        assert_eq!(chat(&mut scripts, &mut state, "hi").1, "hi");
+    }
+
+    #[test]
+    fn script_edits_are_audited_and_sent() {
+        let mut state = state("script-edit");
+        let mut scripts = scripts(
+            "script-edit",
+            &[(
+                "paint.rhai",
+                "fn on_tick(delta) { set_tile(-1, 2, \"stone\"); }",
+            )],
+        );
+        let position = TilePos::new(-1, 2);
+
+        let mut ctx = PluginContext::new(&mut state);
+        scripts.on_tick(&mut ctx, 0.0);
+        assert_eq!(
+            ctx.into_responses(),
+            [Response::ChunkModified(
+                "test".to_string(),
+                position.chunk()
+            )]
+        );
+
+        let stone = Some(Tile {
+            ty: state.tiles.resolve("stone").unwrap(),
+        });
+        assert_eq!(
+            state
+                .worlds
+                .get("test")
+                .unwrap()
+                .tile(position, common::world::Layer::Ground),
+            stone
+        );
+
+        let edits = state.audit.history("test", position).collect::<Vec<_>>();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].player, "script");
+        assert!(matches!(
+            edits[0].change,
+            Change::Tile { new, .. } if new == stone
+        ));
+    }
+}
@@ -0,0 +1,23 @@
+use crate::command::Response;
+
+use super::{PluginContext, ServerPlugin};
+
+/// Greets players when they join.
+pub struct Welcome;
+
+impl ServerPlugin for Welcome {
+    fn name(&self) -> &'static str {
+        "welcome"
+    }
+
+    fn on_join(&mut self, ctx: &mut PluginContext, client_id: usize) {
+        if let Some(player) = &ctx.state.players[client_id] {
+            let message = format!(
+                "Welcome, {}! Type /help for a list of commands",
+                player.username
+            );
+
+            ctx.respond(Response::Reply(message));
+        }
+    }
+}
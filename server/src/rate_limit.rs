@@ -0,0 +1,321 @@
+//! Token buckets that keep clients from flooding the server.
+//!
+//! Everything takes the current `Instant` so the limits don't depend on the clock.
+
+use std::{collections::HashMap, net::IpAddr, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// How many join buckets are kept before full ones are forgotten.
+const MAX_JOIN_BUCKETS: usize = 1024;
+
+/// Lets through `burst` messages at once, refilled at `per_second`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Limit {
+    pub burst: f32,
+    pub per_second: f32,
+}
+
+/// Rate limits, part of the server config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    pub chat: Limit,
+    pub world_click: Limit,
+    /// Packets asking for chunks, each for up to 16 of them.
+    pub chunk_request: Limit,
+    /// Join attempts per IP address.
+    pub join: Limit,
+    /// Dropped messages before the client is warned.
+    pub warn_after: u32,
+    /// Dropped messages before the client is kicked.
+    pub kick_after: u32,
+    /// Seconds without dropped messages after which the count starts over.
+    pub forgive_after: f32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            chat: Limit {
+                burst: 5.0,
+                per_second: 1.0,
+            },
+            world_click: Limit {
+                burst: 30.0,
+                per_second: 15.0,
+            },
+            chunk_request: Limit {
+                burst: 20.0,
+                per_second: 5.0,
+            },
+            join: Limit {
+                burst: 3.0,
+                per_second: 0.2,
+            },
+            warn_after: 5,
+            kick_after: 30,
+            forgive_after: 10.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: Limit,
+    tokens: f32,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: Limit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            refilled: now,
+        }
+    }
+
+    /// Takes a token, returns false if there is none left.
+    pub fn take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f32();
+
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst);
+        self.refilled = self.refilled.max(now);
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+
+        self.tokens >= self.limit.burst
+    }
+}
+
+/// What to do with a message from a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Drop,
+    /// Drop the message and tell the client to slow down.
+    Warn,
+    Kick,
+}
+
+/// The buckets of a single client.
+#[derive(Debug)]
+pub struct ClientLimiter {
+    chat: TokenBucket,
+    world_click: TokenBucket,
+    chunk_request: TokenBucket,
+
+    violations: u32,
+    last_violation: Instant,
+}
+
+impl ClientLimiter {
+    pub fn new(limits: &RateLimits, now: Instant) -> Self {
+        Self {
+            chat: TokenBucket::new(limits.chat, now),
+            world_click: TokenBucket::new(limits.world_click, now),
+            chunk_request: TokenBucket::new(limits.chunk_request, now),
+
+            violations: 0,
+            last_violation: now,
+        }
+    }
+
+    /// Counts a `packet` against its bucket, escalating the more the client floods.
+    pub fn check(
+        &mut self,
+        packet: common::ClientPacket,
+        limits: &RateLimits,
+        now: Instant,
+    ) -> Verdict {
+        let bucket = match packet {
+            common::ClientPacket::Chat => &mut self.chat,
+            // editing an entity or a height is another way of clicking the world
+            common::ClientPacket::WorldClick
+            | common::ClientPacket::EditTileEntity
+            | common::ClientPacket::ChangeHeight => &mut self.world_click,
+            common::ClientPacket::RequestChunks => &mut self.chunk_request,
+            _ => return Verdict::Allow,
+        };
+
+        if bucket.take(now) {
+            return Verdict::Allow;
+        }
+
+        if now
+            .saturating_duration_since(self.last_violation)
+            .as_secs_f32()
+            > limits.forgive_after
+        {
+            self.violations = 0;
+        }
+
+        self.violations += 1;
+        self.last_violation = now;
+
+        if self.violations >= limits.kick_after {
+            Verdict::Kick
+        } else if self.violations == limits.warn_after {
+            Verdict::Warn
+        } else {
+            Verdict::Drop
+        }
+    }
+
+    /// Messages dropped since the client last behaved.
+    pub fn violations(&self) -> u32 {
+        self.violations
+    }
+}
+
+/// Join attempts per IP address.
+#[derive(Debug)]
+pub struct JoinLimiter {
+    limit: Limit,
+    buckets: HashMap<IpAddr, TokenBucket>,
+}
+
+impl JoinLimiter {
+    pub fn new(limit: Limit) -> Self {
+        Self {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn allow(&mut self, address: IpAddr, now: Instant) -> bool {
+        if self.buckets.len() >= MAX_JOIN_BUCKETS {
+            self.buckets.retain(|_, bucket| !bucket.is_full(now));
+        }
+
+        let limit = self.limit;
+
+        self.buckets
+            .entry(address)
+            .or_insert_with(|| TokenBucket::new(limit, now))
+            .take(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const LIMIT: Limit = Limit {
+        burst: 3.0,
+        per_second: 2.0,
+    };
+
+    #[test]
+    fn buckets_allow_a_burst_then_refill() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(LIMIT, start);
+
+        assert!(bucket.take(start));
+        assert!(bucket.take(start));
+        assert!(bucket.take(start));
+        assert!(!bucket.take(start));
+
+        // half a second is one token at two per second
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.take(later));
+        assert!(!bucket.take(later));
+
+        // a long wait refills up to the burst, no further
+        let much_later = later + Duration::from_secs(60);
+        assert!(bucket.is_full(much_later));
+        for _ in 0..3 {
+            assert!(bucket.take(much_later));
+        }
+        assert!(!bucket.take(much_later));
+    }
+
+    #[test]
+    fn floods_escalate_to_a_kick() {
+        let limits = RateLimits {
+            chat: LIMIT,
+            warn_after: 2,
+            kick_after: 4,
+            forgive_after: 10.0,
+            ..RateLimits::default()
+        };
+        let start = Instant::now();
+        let mut limiter = ClientLimiter::new(&limits, start);
+        let mut chat = |at| limiter.check(common::ClientPacket::Chat, &limits, at);
+
+        assert_eq!([chat(start), chat(start), chat(start)], [Verdict::Allow; 3]);
+        assert_eq!(chat(start), Verdict::Drop);
+        assert_eq!(chat(start), Verdict::Warn);
+        assert_eq!(chat(start), Verdict::Drop);
+        assert_eq!(chat(start), Verdict::Kick);
+    }
+
+    #[test]
+    fn violations_are_forgiven_after_a_while() {
+        let limits = RateLimits {
+            chat: LIMIT,
+            warn_after: 2,
+            kick_after: 4,
+            forgive_after: 10.0,
+            ..RateLimits::default()
+        };
+        let start = Instant::now();
+        let mut limiter = ClientLimiter::new(&limits, start);
+
+        for _ in 0..6 {
+            limiter.check(common::ClientPacket::Chat, &limits, start);
+        }
+        assert_eq!(limiter.violations(), 3);
+
+        // other packets have buckets of their own
+        assert_eq!(
+            limiter.check(common::ClientPacket::WorldClick, &limits, start),
+            Verdict::Allow
+        );
+
+        // the bucket is full again but the next flood starts over
+        let later = start + Duration::from_secs(11);
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.check(common::ClientPacket::Chat, &limits, later),
+                Verdict::Allow
+            );
+        }
+        assert_eq!(
+            limiter.check(common::ClientPacket::Chat, &limits, later),
+            Verdict::Drop
+        );
+        assert_eq!(limiter.violations(), 1);
+    }
+
+    #[test]
+    fn joins_are_limited_per_address() {
+        let start = Instant::now();
+        let mut limiter = JoinLimiter::new(LIMIT);
+        let a = "10.0.0.1".parse().unwrap();
+        let b = "10.0.0.2".parse().unwrap();
+
+        for _ in 0..3 {
+            assert!(limiter.allow(a, start));
+        }
+        assert!(!limiter.allow(a, start));
+        assert!(limiter.allow(b, start));
+        assert!(limiter.allow(a, start + Duration::from_millis(500)));
+    }
+}
@@ -0,0 +1,162 @@
+//! Stops the server cleanly on SIGINT or SIGTERM.
+
+use std::time::Duration;
+
+use tokio::{net::UdpSocket, sync::Mutex};
+
+use crate::{broadcast, disconnect, plugin::Plugins, respond, Client, State};
+
+/// How long shutting down may take before the server exits anyway, below the 10
+/// seconds `docker stop` waits before killing the container.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(8);
+
+const SHUTDOWN_NOTICE: &str = "The server is shutting down";
+
+/// Waits for SIGINT or SIGTERM and returns the name of the signal.
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = interrupt() => "SIGINT",
+                    _ = terminate.recv() => "SIGTERM",
+                }
+            }
+            Err(err) => {
+                log::warn!("Failed to listen for SIGTERM: {}", err);
+
+                interrupt().await
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    interrupt().await
+}
+
+async fn interrupt() -> &'static str {
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        log::warn!("Failed to listen for SIGINT: {}", err);
+
+        std::future::pending::<()>().await;
+    }
+
+    "SIGINT"
+}
+
+/// Turns away new players, tells everyone the server is going down, disconnects
+/// them and saves, the caller bounds it with `SHUTDOWN_TIMEOUT`.
+pub async fn run(
+    socket: &UdpSocket,
+    clients: &Mutex<Vec<Option<Client>>>,
+    state: &Mutex<State>,
+    plugins: &Mutex<Plugins>,
+) -> crate::Result<()> {
+    state.lock().await.shutting_down = true;
+
+    let c = &mut clients.lock().await;
+
+    broadcast(
+        socket,
+        None,
+        c,
+        common::ServerPacket::SystemMessage,
+        SHUTDOWN_NOTICE.as_bytes().to_vec(),
+    )
+    .await;
+
+    let mut responses = Vec::new();
+
+    for client_id in 0..c.len() {
+        responses
+            .extend(disconnect(socket, c, state, plugins, client_id, Some(SHUTDOWN_NOTICE)).await);
+    }
+
+    // nobody is left to receive these, but plugins get to finish what they started
+    respond(socket, None, c, state, plugins, responses).await;
+
+    state.lock().await.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Instant};
+
+    use super::*;
+    use crate::{rate_limit, tests};
+
+    #[tokio::test]
+    async fn everyone_is_told_disconnected_and_saved() {
+        let mut state = tests::state("shutdown");
+        state.players[1] = Some(common::world::Player {
+            username: "bob".to_string(),
+            world: "test".to_string(),
+        });
+
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut players = Vec::new();
+        let mut clients = (0..crate::MAX_CLIENTS).map(|_| None).collect::<Vec<_>>();
+
+        for client in clients.iter_mut().take(2) {
+            let player = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+            *client = Some(Client {
+                addr: player.local_addr().unwrap(),
+                last_heard: 0.0,
+                limiter: rate_limit::ClientLimiter::new(
+                    &rate_limit::RateLimits::default(),
+                    Instant::now(),
+                ),
+            });
+            players.push(player);
+        }
+
+        let clients = Mutex::new(clients);
+        let state = Mutex::new(state);
+        let plugins = Mutex::new(Plugins::new());
+
+        run(&server, &clients, &state, &plugins).await.unwrap();
+
+        let notice = |packet: common::ServerPacket| {
+            let mut bytes = vec![packet as u8];
+            bytes.extend(SHUTDOWN_NOTICE.as_bytes());
+            bytes
+        };
+
+        // players also hear about the ones that left before them
+        let mut buf = vec![0; common::MAX_DATAGRAM_SIZE];
+        for player in &players {
+            let mut received = Vec::new();
+
+            while let Ok(len) =
+                tokio::time::timeout(Duration::from_millis(100), player.recv(&mut buf)).await
+            {
+                received.push(buf[..len.unwrap()].to_vec());
+            }
+
+            assert_eq!(
+                received.first(),
+                Some(&notice(common::ServerPacket::SystemMessage))
+            );
+            assert_eq!(
+                received.last(),
+                Some(&notice(common::ServerPacket::Disconnect))
+            );
+        }
+
+        assert!(clients.lock().await.iter().all(Option::is_none));
+
+        let state = state.lock().await;
+        assert!(state.shutting_down);
+        assert!(state.players.iter().all(Option::is_none));
+
+        let saved = fs::read_dir(tests::directory("shutdown").join("worlds"))
+            .unwrap()
+            .count();
+        assert_eq!(saved, 1);
+        assert!(tests::directory("shutdown").join("access.ron").exists());
+    }
+}
@@ -0,0 +1,117 @@
+//! Structure templates saved next to the worlds, one RON file each in the format of
+//! `common::world::StructureFile`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use common::world::{Structure, StructureError, StructureFile, TileRegistry};
+
+#[derive(thiserror::Error, Debug)]
+pub enum StructuresError {
+    #[error("structure names may only use letters, digits, - and _")]
+    InvalidName,
+    #[error("there is no structure {0}")]
+    Missing(String),
+    #[error("can not read the structure {name}: {err}")]
+    Parse { name: String, err: ron::Error },
+    #[error("the structure {name} is broken: {err}")]
+    Invalid { name: String, err: StructureError },
+}
+
+#[derive(Debug)]
+pub struct Structures {
+    directory: PathBuf,
+}
+
+impl Structures {
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The path of the structure called `name`, names are checked so they can't point
+    /// outside the directory.
+    fn path(&self, name: &str) -> Result<PathBuf, StructuresError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            return Err(StructuresError::InvalidName);
+        }
+
+        Ok(self.directory.join(format!("{}.ron", name)))
+    }
+
+    /// Reads the structure called `name`, resolving its tiles with `tiles`.
+    pub fn load(&self, name: &str, tiles: &TileRegistry) -> crate::Result<Structure> {
+        let path = self.path(name)?;
+
+        if !path.exists() {
+            return Err(StructuresError::Missing(name.to_string()).into());
+        }
+
+        let file = ron::from_str::<StructureFile>(&fs::read_to_string(&path)?).map_err(|err| {
+            StructuresError::Parse {
+                name: name.to_string(),
+                err,
+            }
+        })?;
+
+        Ok(file
+            .to_structure(tiles)
+            .map_err(|err| StructuresError::Invalid {
+                name: name.to_string(),
+                err,
+            })?)
+    }
+
+    /// Writes `structure` as `name`, replacing any structure of that name, and returns
+    /// where it was written.
+    pub fn save(
+        &self,
+        name: &str,
+        structure: &Structure,
+        tiles: &TileRegistry,
+    ) -> crate::Result<PathBuf> {
+        let path = self.path(name)?;
+
+        let serialized = ron::ser::to_string_pretty(
+            &StructureFile::new(structure, tiles),
+            ron::ser::PrettyConfig::default(),
+        )?;
+
+        fs::create_dir_all(&self.directory)?;
+        fs::write(&path, serialized)?;
+
+        Ok(path)
+    }
+
+    /// The names of every saved structure, sorted.
+    pub fn names(&self) -> crate::Result<Vec<String>> {
+        if !self.directory.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = fs::read_dir(&self.directory)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+
+                match path.extension() {
+                    Some(extension) if extension == "ron" => {
+                        Some(path.file_stem()?.to_str()?.to_string())
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        names.sort();
+
+        Ok(names)
+    }
+}
@@ -0,0 +1,314 @@
+//! The named worlds a server hosts, each saved to its own file.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use common::world::{
+    BiomeConfig, ChunkPos, GeneratorError, Layer, ScatterError, ScatterGenerator, Tile, TilePos,
+    TileRegistry, World, WorldGenerator,
+};
+
+use crate::{config::WorldConfig, structures::Structures};
+
+#[derive(thiserror::Error, Debug)]
+pub enum WorldsError {
+    #[error("the default world {0} is not configured")]
+    MissingDefault(String),
+    #[error("the world {0} is configured twice")]
+    Duplicate(String),
+    #[error("can not generate the world {world}: {err}")]
+    Generator { world: String, err: GeneratorError },
+    #[error("can not scatter structures over the world {world}: {err}")]
+    Scatter { world: String, err: ScatterError },
+}
+
+#[derive(Debug)]
+pub struct Worlds {
+    worlds: BTreeMap<String, World>,
+    configs: BTreeMap<String, WorldConfig>,
+    /// Shared with scripts, which get lent a world without the rest of the server.
+    generators: BTreeMap<String, Arc<dyn WorldGenerator>>,
+    /// Where players join.
+    default: String,
+
+    directory: PathBuf,
+}
+
+impl Worlds {
+    /// Loads every configured world from `directory`, generating the ones that
+    /// have not been saved yet.
+    pub fn load(
+        directory: impl AsRef<Path>,
+        configs: &[WorldConfig],
+        default: &str,
+        biomes: &BiomeConfig,
+        tiles: &TileRegistry,
+        structures: &Structures,
+    ) -> crate::Result<Self> {
+        let directory = directory.as_ref();
+
+        let mut worlds = Self {
+            worlds: BTreeMap::new(),
+            configs: BTreeMap::new(),
+            generators: BTreeMap::new(),
+            default: default.to_string(),
+
+            directory: directory.to_path_buf(),
+        };
+
+        for config in configs {
+            if worlds.configs.contains_key(&config.name) {
+                return Err(WorldsError::Duplicate(config.name.clone()).into());
+            }
+
+            let generator = common::world::generator_from_name(&config.generator, biomes, tiles)
+                .map_err(|err| WorldsError::Generator {
+                    world: config.name.clone(),
+                    err,
+                })?;
+
+            let generator =
+                if config.structures.is_empty() {
+                    generator
+                } else {
+                    let placements = config
+                        .structures
+                        .iter()
+                        .map(|placement| {
+                            Ok((
+                                placement.clone(),
+                                structures.load(&placement.structure, tiles)?,
+                            ))
+                        })
+                        .collect::<crate::Result<Vec<_>>>()?;
+
+                    Box::new(ScatterGenerator::new(generator, placements, tiles).map_err(
+                        |err| WorldsError::Scatter {
+                            world: config.name.clone(),
+                            err,
+                        },
+                    )?)
+                };
+
+            let path = worlds.path(&config.name);
+
+            let world = if path.exists() {
+                bincode::decode_from_slice(&fs::read(&path)?, bincode::config::standard())?.0
+            } else {
+                let seed = match &config.seed {
+                    Some(seed) => common::world::seed_from_str(seed),
+                    None => rand::random(),
+                };
+
+                log::info!(
+                    "generating the world {} with the {} generator and seed {}, it will be saved to {}",
+                    config.name,
+                    config.generator,
+                    seed,
+                    path.display()
+                );
+
+                World::new(seed)
+            };
+
+            worlds.worlds.insert(config.name.clone(), world);
+            worlds
+                .generators
+                .insert(config.name.clone(), Arc::from(generator));
+            worlds.configs.insert(config.name.clone(), config.clone());
+        }
+
+        if !worlds.worlds.contains_key(default) {
+            return Err(WorldsError::MissingDefault(default.to_string()).into());
+        }
+
+        Ok(worlds)
+    }
+
+    /// Writes every world to disk.
+    pub fn save(&self) -> crate::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+
+        for (name, world) in &self.worlds {
+            let serialized = bincode::encode_to_vec(world, bincode::config::standard())?;
+
+            // written next to the old save first so a crash can not leave half a world behind
+            let path = self.path(name);
+            let temporary = path.with_extension("tmp");
+
+            fs::write(&temporary, serialized)?;
+            fs::rename(&temporary, &path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    pub fn get(&self, name: &str) -> Option<&World> {
+        self.worlds.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut World> {
+        self.worlds.get_mut(name)
+    }
+
+    pub fn config(&self, name: &str) -> Option<&WorldConfig> {
+        self.configs.get(name)
+    }
+
+    /// Generates the chunks of the named world.
+    pub fn generator(&self, name: &str) -> Option<Arc<dyn WorldGenerator>> {
+        self.generators.get(name).cloned()
+    }
+
+    /// Generates the chunks at `positions` in the named world that do not exist yet,
+    /// false if there is no such world.
+    pub fn load_chunks(&mut self, name: &str, positions: &[ChunkPos]) -> bool {
+        match (self.worlds.get_mut(name), self.generators.get(name)) {
+            (Some(world), Some(generator)) => {
+                world.load_chunks(positions, generator.as_ref());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The tile in `layer` at `position` in the named world, generating its chunk first
+    /// if needed. `None` if there is no such world.
+    pub fn load_tile(
+        &mut self,
+        name: &str,
+        position: TilePos,
+        layer: Layer,
+    ) -> Option<Option<Tile>> {
+        let generator = self.generators.get(name)?;
+
+        Some(
+            self.worlds
+                .get_mut(name)?
+                .load_tile(position, layer, generator.as_ref()),
+        )
+    }
+
+    /// Replaces the tile in `layer` at `position` in the named world and returns the old
+    /// one, generating its chunk first if needed. `None` if there is no such world.
+    pub fn load_and_set_tile(
+        &mut self,
+        name: &str,
+        position: TilePos,
+        layer: Layer,
+        tile: Option<Tile>,
+    ) -> Option<Option<Tile>> {
+        let generator = self.generators.get(name)?;
+
+        Some(self.worlds.get_mut(name)?.load_and_set_tile(
+            position,
+            layer,
+            tile,
+            generator.as_ref(),
+        ))
+    }
+
+    /// The names of every world, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.worlds.keys().map(String::as_str)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.bin", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::directory;
+
+    fn config(name: &str, seed: Option<&str>) -> WorldConfig {
+        WorldConfig {
+            name: name.to_string(),
+            protected: false,
+            seed: seed.map(str::to_string),
+            generator: "flat".to_string(),
+            structures: Vec::new(),
+        }
+    }
+
+    fn load(name: &str, configs: &[WorldConfig], default: &str) -> crate::Result<Worlds> {
+        let directory = directory(name);
+
+        Worlds::load(
+            directory.join("worlds"),
+            configs,
+            default,
+            &BiomeConfig::default(),
+            &TileRegistry::default(),
+            &Structures::new(directory.join("structures")),
+        )
+    }
+
+    #[test]
+    fn configs_must_be_unique_and_include_the_default() {
+        let err = load(
+            "worlds-duplicate",
+            &[config("lobby", None), config("lobby", Some("seed"))],
+            "lobby",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(WorldsError::Duplicate(name)) if name == "lobby"
+        ));
+
+        let err = load(
+            "worlds-default",
+            &[config("lobby", None), config("build", None)],
+            "spawn",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(WorldsError::MissingDefault(name)) if name == "spawn"
+        ));
+    }
+
+    #[test]
+    fn saved_worlds_keep_their_seed_and_tiles() {
+        let _ = fs::remove_dir_all(directory("worlds-save"));
+        let configs = [config("lobby", None), config("build", Some("build"))];
+
+        let mut worlds = load("worlds-save", &configs, "lobby").unwrap();
+        let position = TilePos::new(-20, 7);
+        let stone = Some(Tile {
+            ty: TileRegistry::default().resolve("stone").unwrap(),
+        });
+        worlds.load_and_set_tile("build", position, Layer::Object, stone);
+        worlds.save().unwrap();
+
+        let seeds = |worlds: &Worlds| {
+            worlds
+                .names()
+                .map(|name| worlds.get(name).unwrap().seed)
+                .collect::<Vec<_>>()
+        };
+
+        // the lobby got a random seed, which must not be rolled again
+        let reloaded = load("worlds-save", &configs, "lobby").unwrap();
+        assert_eq!(seeds(&reloaded), seeds(&worlds));
+        assert_eq!(
+            reloaded.get("build").unwrap().seed,
+            common::world::seed_from_str("build")
+        );
+        assert_eq!(
+            reloaded.get("build").unwrap().tile(position, Layer::Object),
+            stone
+        );
+    }
+}